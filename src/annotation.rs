@@ -0,0 +1,343 @@
+//! Annotations and their element values (JVMS 4.7.16 - 4.7.22).
+
+use std::sync::Arc;
+use bytes::{Bytes, BytesMut};
+use crate::attribute::{Attribute, AttributeKind};
+use crate::constant::{Constant, ConstantPool, StringRef};
+use crate::error::{ParseResult, Result};
+use crate::reader::Reader;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Annotation {
+    /// The field descriptor of the annotation interface, e.g.
+    /// `Lorg/junit/Test;`.
+    pub type_name: Arc<str>,
+    pub elements: Vec<(Arc<str>, ElementValue)>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ElementValue {
+    Byte(i8),
+    Char(u16),
+    Double(f64),
+    Float(f32),
+    Int(i32),
+    Long(i64),
+    Short(i16),
+    Boolean(bool),
+    /// A string, which like a `CONSTANT_String` may hold unpaired
+    /// surrogates.
+    String(StringRef),
+    Enum {
+        /// The field descriptor of the enum class.
+        type_name: Arc<str>,
+        const_name: Arc<str>,
+    },
+    /// A class literal, as a return descriptor such as `Ljava/lang/Object;`
+    /// or `V`.
+    Class(Arc<str>),
+    Annotation(Annotation),
+    Array(Vec<ElementValue>),
+}
+
+/// An annotation on a use of a type (JVMS 4.7.20).
+#[derive(Clone, Debug, PartialEq)]
+pub struct TypeAnnotation {
+    /// The kind of target, from JVMS tables 4.7.20-A to 4.7.20-C.
+    pub target_type: u8,
+    pub target: TypeAnnotationTarget,
+    /// The path to the annotated part of the type, outermost first.
+    pub target_path: Vec<TypePathEntry>,
+    pub annotation: Annotation,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum TypeAnnotationTarget {
+    TypeParameter { index: u8 },
+    /// `index` is into the class's interfaces, or 65535 for the superclass.
+    Supertype { index: u16 },
+    TypeParameterBound { type_parameter_index: u8, bound_index: u8 },
+    /// A field type, method return type or receiver type.
+    Empty,
+    FormalParameter { index: u8 },
+    Throws { index: u16 },
+    LocalVariable(Vec<LocalVariableTarget>),
+    Catch { exception_table_index: u16 },
+    /// An `instanceof`, `new`, or method reference expression at the
+    /// instruction with this byte offset.
+    Offset(u16),
+    /// A cast or explicit type argument at the instruction with this byte
+    /// offset.
+    TypeArgument { offset: u16, type_argument_index: u8 },
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct LocalVariableTarget {
+    pub start_pc: u16,
+    pub length: u16,
+    pub index: u16,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct TypePathEntry {
+    /// 0 for an array element, 1 for a nested type, 2 for a wildcard bound
+    /// and 3 for a type argument.
+    pub kind: u8,
+    pub type_argument_index: u8,
+}
+
+impl Annotation {
+    /// The value of the element `name`, if it is given explicitly.
+    pub fn element(&self, name: &str) -> Option<&ElementValue> {
+        self.elements.iter()
+            .find(|(element, _)| element.as_ref() == name)
+            .map(|(_, value)| value)
+    }
+
+    pub fn from_bytes(reader: &mut Reader, constant_pool: &ConstantPool) -> ParseResult<Self> {
+        let type_name = reader.utf8_ref(constant_pool)?;
+        let elements = reader.list(|r| Ok((
+            r.utf8_ref(constant_pool)?,
+            ElementValue::from_bytes(r, constant_pool)?,
+        )))?;
+        Ok(Self { type_name, elements })
+    }
+
+    /// Parses the body of a `Runtime*ParameterAnnotations` attribute.
+    pub fn parse_parameters(reader: &mut Reader, constant_pool: &ConstantPool) -> ParseResult<Vec<Vec<Self>>> {
+        let count = reader.u8()?;
+        (0..count)
+            .map(|_| reader.list(|r| Annotation::from_bytes(r, constant_pool)))
+            .collect()
+    }
+}
+
+impl ElementValue {
+    pub fn from_bytes(reader: &mut Reader, constant_pool: &ConstantPool) -> ParseResult<Self> {
+        let offset = reader.offset();
+        let tag = reader.u8()?;
+
+        let constant_offset = reader.offset();
+        let mut constant = |reader: &mut Reader| {
+            let index = reader.u16()?;
+            constant_pool.get(index)
+                .cloned()
+                .ok_or_else(|| reader.error_at(constant_offset, format!("constant {index} is not a valid entry")))
+        };
+        let mismatch = |reader: &Reader| {
+            reader.error_at(constant_offset, format!("constant does not match element value tag '{}'", tag as char))
+        };
+
+        Ok(match tag {
+            b'B' | b'C' | b'I' | b'S' | b'Z' => {
+                let Constant::Integer(value) = constant(reader)? else {
+                    return Err(mismatch(reader));
+                };
+                match tag {
+                    b'B' => Self::Byte(value as i8),
+                    b'C' => Self::Char(value as u16),
+                    b'S' => Self::Short(value as i16),
+                    b'Z' => Self::Boolean(value != 0),
+                    _ => Self::Int(value),
+                }
+            }
+            b'D' => match constant(reader)? {
+                Constant::Double(value) => Self::Double(value),
+                _ => return Err(mismatch(reader)),
+            },
+            b'F' => match constant(reader)? {
+                Constant::Float(value) => Self::Float(value),
+                _ => return Err(mismatch(reader)),
+            },
+            b'J' => match constant(reader)? {
+                Constant::Long(value) => Self::Long(value),
+                _ => return Err(mismatch(reader)),
+            },
+            b's' => match constant(reader)? {
+                Constant::Utf8(string) => Self::String(StringRef::Str(string)),
+                Constant::Utf16(units) => Self::String(StringRef::Utf16(units)),
+                _ => return Err(mismatch(reader)),
+            },
+            b'e' => Self::Enum {
+                type_name: reader.utf8_ref(constant_pool)?,
+                const_name: reader.utf8_ref(constant_pool)?,
+            },
+            b'c' => Self::Class(reader.utf8_ref(constant_pool)?),
+            b'@' => Self::Annotation(Annotation::from_bytes(reader, constant_pool)?),
+            b'[' => Self::Array(reader.list(|r| ElementValue::from_bytes(r, constant_pool))?),
+            _ => return Err(reader.error_at(offset, format!("invalid element value tag {tag:#04x}"))),
+        })
+    }
+}
+
+impl TypeAnnotation {
+    pub fn from_bytes(reader: &mut Reader, constant_pool: &ConstantPool) -> ParseResult<Self> {
+        let offset = reader.offset();
+        let target_type = reader.u8()?;
+        let target = match target_type {
+            0x00 | 0x01 => TypeAnnotationTarget::TypeParameter { index: reader.u8()? },
+            0x10 => TypeAnnotationTarget::Supertype { index: reader.u16()? },
+            0x11 | 0x12 => TypeAnnotationTarget::TypeParameterBound {
+                type_parameter_index: reader.u8()?,
+                bound_index: reader.u8()?,
+            },
+            0x13..=0x15 => TypeAnnotationTarget::Empty,
+            0x16 => TypeAnnotationTarget::FormalParameter { index: reader.u8()? },
+            0x17 => TypeAnnotationTarget::Throws { index: reader.u16()? },
+            0x40 | 0x41 => TypeAnnotationTarget::LocalVariable(reader.list(|r| Ok(LocalVariableTarget {
+                start_pc: r.u16()?,
+                length: r.u16()?,
+                index: r.u16()?,
+            }))?),
+            0x42 => TypeAnnotationTarget::Catch { exception_table_index: reader.u16()? },
+            0x43..=0x46 => TypeAnnotationTarget::Offset(reader.u16()?),
+            0x47..=0x4B => TypeAnnotationTarget::TypeArgument {
+                offset: reader.u16()?,
+                type_argument_index: reader.u8()?,
+            },
+            _ => return Err(reader.error_at(offset, format!("invalid type annotation target type {target_type:#04x}"))),
+        };
+
+        let path_length = reader.u8()?;
+        let target_path = (0..path_length)
+            .map(|_| Ok(TypePathEntry {
+                kind: reader.u8()?,
+                type_argument_index: reader.u8()?,
+            }))
+            .collect::<ParseResult<_>>()?;
+
+        Ok(Self {
+            target_type,
+            target,
+            target_path,
+            annotation: Annotation::from_bytes(reader, constant_pool)?,
+        })
+    }
+}
+
+/// Re-encodes the body of a `Runtime*TypeAnnotations` attribute of a `Code`
+/// attribute whose instructions have moved, with the instruction offsets
+/// and local variable ranges of its targets at the offsets `remap` gives
+/// for their old ones. The rest of each annotation is copied as it is.
+pub fn remap_type_annotations(info: &Bytes, constant_pool: &ConstantPool, remap: impl Fn(u32) -> Result<u16>) -> Result<Bytes> {
+    let mut reader = Reader::new(info.clone());
+    let mut remapped = BytesMut::from(&info[..]);
+    let patch = |bytes: &mut BytesMut, at: usize, value: u16| bytes[at..at + 2].copy_from_slice(&value.to_be_bytes());
+
+    let count = reader.u16()?;
+    for _ in 0..count {
+        let offset = reader.offset();
+        match reader.u8()? {
+            0x40 | 0x41 => {
+                for _ in 0..reader.u16()? {
+                    let at = reader.offset();
+                    let start_pc = reader.u16()? as u32;
+                    let end_pc = start_pc + reader.u16()? as u32;
+                    reader.u16()?;
+                    let (start_pc, end_pc) = (remap(start_pc)?, remap(end_pc)?);
+                    patch(&mut remapped, at, start_pc);
+                    patch(&mut remapped, at + 2, end_pc - start_pc);
+                }
+            }
+            0x42 => {
+                reader.u16()?;
+            }
+            target_type @ 0x43..=0x4B => {
+                let at = reader.offset();
+                patch(&mut remapped, at, remap(reader.u16()? as u32)?);
+                if target_type >= 0x47 {
+                    reader.u8()?;
+                }
+            }
+            target_type => return Err(reader.error_at(offset, format!(
+                "type annotation target type {target_type:#04x} cannot appear in code"
+            )).into()),
+        }
+        let path_length = reader.u8()?;
+        reader.bytes(2 * path_length as usize)?;
+        Annotation::from_bytes(&mut reader, constant_pool)?;
+    }
+    reader.expect_end()?;
+    Ok(remapped.freeze())
+}
+
+/// Whether `name` is one of the attributes that hold annotations. The JVM
+/// itself does not depend on them, so a malformed one does not stop a class
+/// from linking; its annotations are just not there.
+pub fn is_annotation_attribute(name: &str) -> bool {
+    matches!(
+        name,
+        "RuntimeVisibleAnnotations" | "RuntimeInvisibleAnnotations"
+            | "RuntimeVisibleParameterAnnotations" | "RuntimeInvisibleParameterAnnotations"
+            | "RuntimeVisibleTypeAnnotations" | "RuntimeInvisibleTypeAnnotations"
+            | "AnnotationDefault"
+    )
+}
+
+/// The visible and invisible annotations declared in `attributes`. Those of
+/// a malformed attribute are left out.
+pub fn annotations(attributes: &[Attribute]) -> impl Iterator<Item = &Annotation> {
+    attributes.iter().flat_map(|attr| match &attr.kind() {
+        AttributeKind::RuntimeVisibleAnnotations(annotations)
+        | AttributeKind::RuntimeInvisibleAnnotations(annotations) => annotations.as_slice(),
+        _ => &[],
+    })
+}
+
+/// The visible and invisible type annotations declared in `attributes`.
+/// Those of a malformed attribute are left out.
+pub fn type_annotations(attributes: &[Attribute]) -> impl Iterator<Item = &TypeAnnotation> {
+    attributes.iter().flat_map(|attr| match &attr.kind() {
+        AttributeKind::RuntimeVisibleTypeAnnotations(annotations)
+        | AttributeKind::RuntimeInvisibleTypeAnnotations(annotations) => annotations.as_slice(),
+        _ => &[],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::{BufMut, Bytes};
+    use crate::class::Class;
+    use super::*;
+
+    /// Hello.class with a `RuntimeInvisibleAnnotations` attribute added,
+    /// whose body `info` builds from the class's constant pool.
+    fn annotated<F>(info: F) -> Class
+    where
+        F: FnOnce(&mut ConstantPool) -> Vec<u8>,
+    {
+        let mut class = Class::parse(Bytes::from_static(include_bytes!("../Hello.class"))).unwrap();
+        let mut constant_pool = ConstantPool::clone(&class.constant_pool);
+        let name_index = constant_pool.add_utf8("RuntimeInvisibleAnnotations").unwrap();
+        let info = Bytes::from(info(&mut constant_pool));
+        class.constant_pool = Arc::new(constant_pool);
+        let attribute = Attribute::new(name_index, info, class.constant_pool.clone(), class.version).unwrap();
+        class.attributes.push(attribute);
+        Class::parse(Bytes::from(class.write().unwrap())).unwrap()
+    }
+
+    #[test]
+    fn skips_malformed_annotations() {
+        // One annotation, then nothing where its type should be.
+        let class = annotated(|_| vec![0, 1]);
+        assert_eq!(class.annotations().count(), 0);
+        assert!(class.check_format().is_empty());
+    }
+
+    #[test]
+    fn reads_strings_with_unpaired_surrogates() {
+        let units: Arc<[u16]> = Arc::from([0x41, 0xd800]);
+        let class = annotated(|constant_pool| {
+            let mut info = Vec::new();
+            info.put_u16(1);
+            info.put_u16(constant_pool.add_utf8("LNote;").unwrap());
+            info.put_u16(1);
+            info.put_u16(constant_pool.add_utf8("value").unwrap());
+            info.put_u8(b's');
+            info.put_u16(constant_pool.add(Constant::Utf16(units.clone())).unwrap());
+            info
+        });
+        let annotation = class.annotation("LNote;").unwrap();
+        assert_eq!(annotation.elements, [(Arc::from("value"), ElementValue::String(StringRef::Utf16(units)))]);
+    }
+}
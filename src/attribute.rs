@@ -0,0 +1,411 @@
+use std::cell::OnceCell;
+use std::fmt::{Debug, Formatter};
+use std::sync::Arc;
+use bytes::{BufMut, Bytes};
+use crate::annotation::{self, Annotation, ElementValue, TypeAnnotation};
+use crate::code::Code;
+use crate::constant::ConstantPool;
+use crate::error::{ClassFormatError, Error, ParseResult, Result};
+use crate::module::ModuleDescriptor;
+use crate::reader::Reader;
+use crate::signature::{self, FieldSignature};
+use crate::stack_map::StackMapFrame;
+use crate::version::ClassVersion;
+
+/// An attribute, kept as its raw body together with the decoded form of
+/// it. `info` is what gets written back, so edits go through `set_info`,
+/// which clears the decoded form.
+#[derive(Clone)]
+pub struct Attribute {
+    pub name: Arc<str>,
+    /// The index of `name` in `constant_pool`.
+    pub name_index: u16,
+    /// The raw body, sharing the buffer the class file was parsed from.
+    info: Bytes,
+    /// Byte offset of `info` in the class file.
+    pub offset: usize,
+    /// The pool of the class the attribute is in, which `info` refers to.
+    constant_pool: Arc<ConstantPool>,
+    version: ClassVersion,
+    /// Decoded from `info` the first time it is asked for, since most
+    /// attributes of most classes are never looked at.
+    kind: OnceCell<AttributeKind>,
+}
+
+/// The decoded contents of an attribute (JVMS 4.7).
+///
+/// Attributes that are not predefined, or that are predefined but newer than
+/// the class file's version, are `Unknown` and only available as raw `info`.
+/// Predefined attributes with a malformed body are `Invalid`.
+#[derive(Clone, Debug)]
+pub enum AttributeKind {
+    /// Index of the constant holding the field's initial value.
+    ConstantValue(u16),
+    Code(Code),
+    Exceptions(Vec<Arc<str>>),
+    InnerClasses(Vec<InnerClass>),
+    EnclosingMethod {
+        class: Arc<str>,
+        /// Name and descriptor of the enclosing method, if there is one.
+        method: Option<(Arc<str>, Arc<str>)>,
+    },
+    Synthetic,
+    Signature(Arc<str>),
+    SourceFile(Arc<str>),
+    SourceDebugExtension(Bytes),
+    LineNumberTable(Vec<LineNumber>),
+    LocalVariableTable(Vec<LocalVariable>),
+    /// Like `LocalVariableTable`, but `descriptor` holds a generic signature.
+    LocalVariableTypeTable(Vec<LocalVariable>),
+    StackMapTable(Vec<StackMapFrame>),
+    Deprecated,
+    RuntimeVisibleAnnotations(Vec<Annotation>),
+    RuntimeInvisibleAnnotations(Vec<Annotation>),
+    /// One list of annotations per formal parameter.
+    RuntimeVisibleParameterAnnotations(Vec<Vec<Annotation>>),
+    RuntimeInvisibleParameterAnnotations(Vec<Vec<Annotation>>),
+    RuntimeVisibleTypeAnnotations(Vec<TypeAnnotation>),
+    RuntimeInvisibleTypeAnnotations(Vec<TypeAnnotation>),
+    /// The default value of an annotation interface element.
+    AnnotationDefault(ElementValue),
+    BootstrapMethods(Vec<BootstrapMethod>),
+    MethodParameters(Vec<MethodParameter>),
+    NestHost(Arc<str>),
+    NestMembers(Vec<Arc<str>>),
+    Record(Vec<RecordComponent>),
+    PermittedSubclasses(Vec<Arc<str>>),
+    Module(ModuleDescriptor),
+    /// Every package in the module, in internal form.
+    ModulePackages(Vec<Arc<str>>),
+    ModuleMainClass(Arc<str>),
+    Unknown,
+    /// A predefined attribute whose body could not be decoded. Classes are
+    /// parsed without decoding their attributes, so this is found when the
+    /// attribute is first used, or when the class is linked.
+    Invalid(ClassFormatError),
+}
+
+#[derive(Clone, Debug)]
+pub struct InnerClass {
+    pub inner_class: Arc<str>,
+    pub outer_class: Option<Arc<str>>,
+    /// `None` for anonymous classes.
+    pub inner_name: Option<Arc<str>>,
+    pub access_flags: u16,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct LineNumber {
+    pub start_pc: u16,
+    pub line_number: u16,
+}
+
+#[derive(Clone, Debug)]
+pub struct LocalVariable {
+    pub start_pc: u16,
+    pub length: u16,
+    pub name: Arc<str>,
+    pub descriptor: Arc<str>,
+    pub index: u16,
+}
+
+#[derive(Clone, Debug)]
+pub struct BootstrapMethod {
+    /// Index of the `CONSTANT_MethodHandle` for the bootstrap method.
+    pub method_ref: u16,
+    /// Indices of the loadable constants passed as static arguments.
+    pub arguments: Vec<u16>,
+}
+
+#[derive(Clone, Debug)]
+pub struct MethodParameter {
+    /// `None` for a formal parameter with no name.
+    pub name: Option<Arc<str>>,
+    pub access_flags: u16,
+}
+
+#[derive(Clone, Debug)]
+pub struct RecordComponent {
+    pub name: Arc<str>,
+    pub descriptor: Arc<str>,
+    pub attributes: Vec<Attribute>,
+}
+
+impl Attribute {
+    pub fn from_bytes(reader: &mut Reader, constant_pool: &Arc<ConstantPool>, version: ClassVersion) -> ParseResult<Self> {
+        reader.within("attribute_info", |reader| {
+            let name_offset = reader.offset();
+            let name_index = reader.u16()?;
+            let name = constant_pool.utf8(name_index)
+                .cloned()
+                .ok_or_else(|| reader.error_at(name_offset, format!("constant {name_index} is not a Utf8 entry")))?;
+            let length: u32 = reader.u32()?;
+            let offset = reader.offset();
+            let info = reader.bytes(length as usize)?;
+
+            Ok(Self {
+                name,
+                name_index,
+                info,
+                offset,
+                constant_pool: constant_pool.clone(),
+                version,
+                kind: OnceCell::new(),
+            })
+        })
+    }
+
+    /// An attribute with the body `info` that is not from a class file,
+    /// such as one built to add to a method. `name_index` must name a
+    /// `CONSTANT_Utf8` entry in `constant_pool`.
+    pub fn new(name_index: u16, info: Bytes, constant_pool: Arc<ConstantPool>, version: ClassVersion) -> Result<Self> {
+        let name = constant_pool.utf8(name_index)
+            .ok_or(Error::InvalidConstant { index: name_index, expected: "Utf8" })?
+            .clone();
+        Ok(Self {
+            name,
+            name_index,
+            info,
+            offset: 0,
+            constant_pool,
+            version,
+            kind: OnceCell::new(),
+        })
+    }
+
+    pub fn parse_list(reader: &mut Reader, constant_pool: &Arc<ConstantPool>, version: ClassVersion) -> ParseResult<Vec<Self>> {
+        reader.list(|reader| Attribute::from_bytes(reader, constant_pool, version))
+    }
+
+    /// The raw body of the attribute.
+    pub fn info(&self) -> &Bytes {
+        &self.info
+    }
+
+    /// The decoded body of the attribute, decoding it if this is the first
+    /// time it is needed.
+    pub fn kind(&self) -> &AttributeKind {
+        self.kind.get_or_init(|| {
+            decode(&self.name, &self.info, self.offset, &self.constant_pool, self.version)
+                .unwrap_or_else(AttributeKind::Invalid)
+        })
+    }
+
+    /// Like `kind`, but a malformed body is an error rather than `Invalid`.
+    pub fn try_kind(&self) -> ParseResult<&AttributeKind> {
+        match self.kind() {
+            AttributeKind::Invalid(err) => Err(err.clone()),
+            kind => Ok(kind),
+        }
+    }
+
+    /// The constant pool `info` refers to.
+    pub fn constant_pool(&self) -> &Arc<ConstantPool> {
+        &self.constant_pool
+    }
+
+    /// Replaces the body of the attribute, to be decoded again when next
+    /// asked for. Any constants `info` refers to must be in the attribute's
+    /// constant pool.
+    pub fn set_info(&mut self, info: Bytes) {
+        self.kind = OnceCell::new();
+        self.info = info;
+        self.offset = 0;
+    }
+
+    /// Appends this attribute as an `attribute_info` structure, adding its
+    /// name to `constant_pool` if it is missing. The body is written from
+    /// `info`.
+    pub fn to_bytes(&self, constant_pool: &mut ConstantPool, buf: &mut Vec<u8>) -> Result<()> {
+        let name_index = constant_pool.add_utf8(&self.name)?;
+        buf.put_u16(name_index);
+        buf.put_u32(self.info.len() as u32);
+        buf.put_slice(&self.info);
+        Ok(())
+    }
+
+    /// Appends this attribute to the body of another attribute in the same
+    /// class, such as `Code`, using its own name index.
+    pub fn put(&self, buf: &mut Vec<u8>) {
+        buf.put_u16(self.name_index);
+        buf.put_u32(self.info.len() as u32);
+        buf.put_slice(&self.info);
+    }
+}
+
+impl Debug for Attribute {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Attribute")
+            .field("name", &self.name)
+            .field("offset", &self.offset)
+            .field("length", &self.info.len())
+            .field("kind", self.kind())
+            .finish()
+    }
+}
+
+/// Decodes `info`, the body of an attribute called `name` that starts at
+/// byte offset `offset` of the class file, if `version` recognizes it.
+fn decode(name: &str, info: &Bytes, offset: usize, constant_pool: &Arc<ConstantPool>, version: ClassVersion) -> ParseResult<AttributeKind> {
+    if !version.recognizes_attribute(name) {
+        return Ok(AttributeKind::Unknown);
+    }
+    let mut body = Reader::at(info.clone(), offset);
+    AttributeKind::from_bytes(name, &mut body, constant_pool, version)
+}
+
+impl AttributeKind {
+    /// Decodes the body of the predefined attribute `name`. The whole body
+    /// must be consumed.
+    pub fn from_bytes(name: &str, reader: &mut Reader, pool: &Arc<ConstantPool>, version: ClassVersion) -> ParseResult<Self> {
+        let kind = match name {
+            "ConstantValue" => Self::ConstantValue(reader.u16()?),
+            "Code" => Self::Code(Code::from_bytes(reader, pool, version)?),
+            "Exceptions" => Self::Exceptions(reader.list(|r| r.class_ref(pool))?),
+            "InnerClasses" => Self::InnerClasses(reader.list(|r| Ok(InnerClass {
+                inner_class: r.class_ref(pool)?,
+                outer_class: r.optional_class_ref(pool)?,
+                inner_name: r.optional_utf8_ref(pool)?,
+                access_flags: r.u16()?,
+            }))?),
+            "EnclosingMethod" => {
+                let class = reader.class_ref(pool)?;
+                let offset = reader.offset();
+                let method = match reader.u16()? {
+                    0 => None,
+                    index => {
+                        let (name, descriptor) = pool.name_and_type(index).ok_or_else(|| {
+                            reader.error_at(offset, format!("constant {index} is not a NameAndType entry"))
+                        })?;
+                        Some((name.clone(), descriptor.clone()))
+                    }
+                };
+                Self::EnclosingMethod { class, method }
+            }
+            "Synthetic" => Self::Synthetic,
+            "Signature" => Self::Signature(reader.utf8_ref(pool)?),
+            "SourceFile" => Self::SourceFile(reader.utf8_ref(pool)?),
+            "SourceDebugExtension" => {
+                let length = reader.remaining();
+                Self::SourceDebugExtension(reader.bytes(length)?)
+            }
+            "LineNumberTable" => Self::LineNumberTable(reader.list(|r| Ok(LineNumber {
+                start_pc: r.u16()?,
+                line_number: r.u16()?,
+            }))?),
+            "LocalVariableTable" => Self::LocalVariableTable(reader.list(|r| LocalVariable::from_bytes(r, pool))?),
+            "LocalVariableTypeTable" => Self::LocalVariableTypeTable(reader.list(|r| LocalVariable::from_bytes(r, pool))?),
+            "Deprecated" => Self::Deprecated,
+            "RuntimeVisibleAnnotations" => Self::RuntimeVisibleAnnotations(reader.list(|r| Annotation::from_bytes(r, pool))?),
+            "RuntimeInvisibleAnnotations" => Self::RuntimeInvisibleAnnotations(reader.list(|r| Annotation::from_bytes(r, pool))?),
+            "RuntimeVisibleParameterAnnotations" => Self::RuntimeVisibleParameterAnnotations(Annotation::parse_parameters(reader, pool)?),
+            "RuntimeInvisibleParameterAnnotations" => Self::RuntimeInvisibleParameterAnnotations(Annotation::parse_parameters(reader, pool)?),
+            "RuntimeVisibleTypeAnnotations" => Self::RuntimeVisibleTypeAnnotations(reader.list(|r| TypeAnnotation::from_bytes(r, pool))?),
+            "RuntimeInvisibleTypeAnnotations" => Self::RuntimeInvisibleTypeAnnotations(reader.list(|r| TypeAnnotation::from_bytes(r, pool))?),
+            "AnnotationDefault" => Self::AnnotationDefault(ElementValue::from_bytes(reader, pool)?),
+            "StackMapTable" => Self::StackMapTable(reader.list(|r| StackMapFrame::from_bytes(r, pool))?),
+            "BootstrapMethods" => Self::BootstrapMethods(reader.list(|r| Ok(BootstrapMethod {
+                method_ref: r.u16()?,
+                arguments: r.list(|r| r.u16())?,
+            }))?),
+            "MethodParameters" => {
+                let count = reader.u8()?;
+                let parameters = (0..count)
+                    .map(|_| Ok(MethodParameter {
+                        name: reader.optional_utf8_ref(pool)?,
+                        access_flags: reader.u16()?,
+                    }))
+                    .collect::<ParseResult<_>>()?;
+                Self::MethodParameters(parameters)
+            }
+            "NestHost" => Self::NestHost(reader.class_ref(pool)?),
+            "NestMembers" => Self::NestMembers(reader.list(|r| r.class_ref(pool))?),
+            "Record" => Self::Record(reader.list(|r| Ok(RecordComponent {
+                name: r.utf8_ref(pool)?,
+                descriptor: r.utf8_ref(pool)?,
+                attributes: Attribute::parse_list(r, pool, version)?,
+            }))?),
+            "Module" => Self::Module(ModuleDescriptor::from_bytes(reader, pool)?),
+            "ModulePackages" => Self::ModulePackages(reader.list(|r| r.package_ref(pool))?),
+            "ModuleMainClass" => Self::ModuleMainClass(reader.class_ref(pool)?),
+            "PermittedSubclasses" => Self::PermittedSubclasses(reader.list(|r| r.class_ref(pool))?),
+            _ => return Ok(Self::Unknown),
+        };
+        reader.expect_end()?;
+        Ok(kind)
+    }
+}
+
+impl RecordComponent {
+    /// The generic type from the component's `Signature` attribute, if
+    /// there is one.
+    pub fn signature(&self) -> Result<Option<FieldSignature>> {
+        signature::from_attributes(&self.attributes, FieldSignature::parse)
+    }
+
+    pub fn annotations(&self) -> impl Iterator<Item = &Annotation> {
+        annotation::annotations(&self.attributes)
+    }
+
+    pub fn annotation(&self, type_name: &str) -> Option<&Annotation> {
+        self.annotations().find(|a| a.type_name.as_ref() == type_name)
+    }
+}
+
+impl LocalVariable {
+    fn from_bytes(reader: &mut Reader, constant_pool: &ConstantPool) -> ParseResult<Self> {
+        Ok(Self {
+            start_pc: reader.u16()?,
+            length: reader.u16()?,
+            name: reader.utf8_ref(constant_pool)?,
+            descriptor: reader.utf8_ref(constant_pool)?,
+            index: reader.u16()?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ops::Range;
+    use crate::class::Class;
+    use super::*;
+
+    static LOCALS: &[u8] = include_bytes!("../tests/fixtures/Locals.class");
+
+    fn address_range(bytes: &[u8]) -> Range<usize> {
+        let start = bytes.as_ptr() as usize;
+        start..start + bytes.len()
+    }
+
+    /// Asserts that each attribute's body is a view of `class_bytes` rather
+    /// than a copy, and that none of them has been decoded yet.
+    fn assert_undecoded_views(attributes: &[Attribute], class_bytes: &Range<usize>) {
+        assert!(!attributes.is_empty());
+        for attribute in attributes {
+            let info = address_range(attribute.info());
+            assert!(class_bytes.contains(&info.start) && info.end <= class_bytes.end, "{} was copied", attribute.name);
+            assert!(attribute.kind.get().is_none(), "{} was decoded", attribute.name);
+        }
+    }
+
+    #[test]
+    fn parsing_leaves_attributes_undecoded() {
+        let class = Class::parse(Bytes::from_static(LOCALS)).unwrap();
+        let class_bytes = address_range(LOCALS);
+        assert_undecoded_views(&class.attributes, &class_bytes);
+        for method in class.static_methods.iter().chain(&class.methods) {
+            assert_undecoded_views(&method.attributes, &class_bytes);
+        }
+
+        let sum = class.static_methods.iter().find(|method| method.name.as_ref() == "sum").unwrap();
+        let code = sum.code().unwrap();
+        let attribute = sum.attributes.iter().find(|attribute| attribute.name.as_ref() == "Code").unwrap();
+        assert!(attribute.kind.get().is_some());
+        // The attributes of the code are views of the class too, and are
+        // left alone until they are needed in turn.
+        assert_undecoded_views(&code.attributes, &class_bytes);
+        assert!(!code.local_variables().is_empty());
+        let table = code.attributes.iter().find(|attribute| attribute.name.as_ref() == "LocalVariableTable").unwrap();
+        assert!(table.kind.get().is_some());
+    }
+}
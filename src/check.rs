@@ -0,0 +1,592 @@
+//! Format checking of a parsed class (JVMS 4.8).
+//!
+//! Parsing only rejects class files it cannot make sense of. The checks
+//! here cover the remaining static constraints of chapter 4: the kinds and
+//! contents of constant pool cross-references, names and descriptors,
+//! access flag combinations, duplicate members, and the limits of each
+//! method's code.
+
+use std::collections::HashSet;
+use std::fmt::{Display, Formatter};
+use std::sync::Arc;
+use crate::annotation;
+use crate::attribute::{Attribute, AttributeKind};
+use crate::class::{Class, ClassAccessFlags, Field, FieldAccessFlags, Method, MethodAccessFlags};
+use crate::code::Code;
+use crate::constant::{Constant, ConstantPool, MemberRef, ReferenceKind};
+use crate::descriptor::{self, FieldType, MethodDescriptor};
+
+/// A constraint of the class file format that a class does not meet.
+#[derive(Clone, Debug)]
+pub struct FormatViolation {
+    pub location: Location,
+    pub reason: String,
+}
+
+/// Where in a class a `FormatViolation` was found.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Location {
+    /// The class declaration: its flags, names and attributes.
+    Class,
+    Constant(u16),
+    Field { name: Arc<str>, descriptor: Arc<str> },
+    Method { name: Arc<str>, descriptor: Arc<str> },
+}
+
+impl Display for FormatViolation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.location, self.reason)
+    }
+}
+
+impl Display for Location {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Class => write!(f, "class"),
+            Self::Constant(index) => write!(f, "constant {index}"),
+            Self::Field { name, descriptor } => write!(f, "field {name}:{descriptor}"),
+            Self::Method { name, descriptor } => write!(f, "method {name}{descriptor}"),
+        }
+    }
+}
+
+/// Checks `class` against the static constraints of the class file format,
+/// returning every violation found.
+pub fn check_class(class: &Class) -> Vec<FormatViolation> {
+    let mut checker = Checker {
+        class,
+        pool: &class.constant_pool,
+        violations: Vec::new(),
+    };
+    checker.check_constant_pool();
+    checker.check_header();
+    checker.check_fields();
+    checker.check_methods();
+    checker.check_attributes();
+    checker.violations
+}
+
+struct Checker<'a> {
+    class: &'a Class,
+    pool: &'a ConstantPool,
+    violations: Vec<FormatViolation>,
+}
+
+impl Checker<'_> {
+    fn report<R: Into<String>>(&mut self, location: Location, reason: R) {
+        self.violations.push(FormatViolation {
+            location,
+            reason: reason.into(),
+        });
+    }
+
+    fn bootstrap_method_count(&self) -> usize {
+        self.class.attributes.iter()
+            .find_map(|attr| match &attr.kind() {
+                AttributeKind::BootstrapMethods(methods) => Some(methods.len()),
+                _ => None,
+            })
+            .unwrap_or(0)
+    }
+
+    fn check_constant_pool(&mut self) {
+        let bootstrap_methods = self.bootstrap_method_count();
+
+        for (index, constant) in self.pool.iter() {
+            let location = Location::Constant(index);
+            let problem = match constant {
+                Constant::Class { name_index } => match self.pool.utf8(*name_index) {
+                    Some(name) if is_class_name(name) => None,
+                    Some(name) => Some(format!("{name:?} is not a valid class name")),
+                    None => Some(format!("name_index {name_index} is not a Utf8 entry")),
+                },
+                Constant::String { string_index } => match self.pool.get(*string_index) {
+                    Some(Constant::Utf8(_) | Constant::Utf16(_)) => None,
+                    _ => Some(format!("string_index {string_index} is not a Utf8 entry")),
+                },
+                Constant::FieldRef { class_index, name_and_type_index } => {
+                    self.check_member(*class_index, *name_and_type_index, |name, descriptor| {
+                        if !descriptor::is_unqualified_name(name) {
+                            Some(format!("{name:?} is not a valid field name"))
+                        } else if FieldType::parse(descriptor).is_none() {
+                            Some(format!("{descriptor:?} is not a field descriptor"))
+                        } else {
+                            None
+                        }
+                    })
+                }
+                Constant::MethodRef { class_index, name_and_type_index }
+                | Constant::InterfaceMethodRef { class_index, name_and_type_index } => {
+                    let is_interface = matches!(constant, Constant::InterfaceMethodRef { .. });
+                    self.check_member(*class_index, *name_and_type_index, |name, descriptor| {
+                        let Some(method_type) = MethodDescriptor::parse(descriptor) else {
+                            return Some(format!("{descriptor:?} is not a method descriptor"));
+                        };
+                        match name {
+                            "<clinit>" => Some("<clinit> cannot be referenced".to_owned()),
+                            "<init>" if is_interface => Some("an interface has no <init> method".to_owned()),
+                            "<init>" if method_type.return_type.is_some() => Some("<init> must return void".to_owned()),
+                            _ if !descriptor::is_method_name(name) => Some(format!("{name:?} is not a valid method name")),
+                            _ => None,
+                        }
+                    })
+                }
+                Constant::NameAndType { name_index, descriptor_index } => {
+                    match (self.pool.utf8(*name_index), self.pool.utf8(*descriptor_index)) {
+                        (Some(name), _) if !descriptor::is_unqualified_name(name) && !descriptor::is_method_name(name) =>
+                            Some(format!("{name:?} is not a valid name")),
+                        (Some(_), Some(descriptor))
+                            if FieldType::parse(descriptor).is_none() && MethodDescriptor::parse(descriptor).is_none() =>
+                            Some(format!("{descriptor:?} is not a field or method descriptor")),
+                        (Some(_), Some(_)) => None,
+                        _ => Some("name or descriptor is not a Utf8 entry".to_owned()),
+                    }
+                }
+                Constant::MethodHandle { .. } => self.check_method_handle(index),
+                Constant::MethodType { descriptor_index } => match self.pool.utf8(*descriptor_index) {
+                    Some(descriptor) if MethodDescriptor::parse(descriptor).is_some() => None,
+                    _ => Some(format!("descriptor_index {descriptor_index} is not a method descriptor")),
+                },
+                Constant::Dynamic { bootstrap_method_attr_index, name_and_type_index }
+                | Constant::InvokeDynamic { bootstrap_method_attr_index, name_and_type_index } => {
+                    let is_field = matches!(constant, Constant::Dynamic { .. });
+                    match self.pool.name_and_type(*name_and_type_index) {
+                        _ if *bootstrap_method_attr_index as usize >= bootstrap_methods =>
+                            Some(format!("bootstrap method {bootstrap_method_attr_index} does not exist")),
+                        Some((_, descriptor)) if is_field && FieldType::parse(descriptor).is_none() =>
+                            Some(format!("{descriptor:?} is not a field descriptor")),
+                        Some((_, descriptor)) if !is_field && MethodDescriptor::parse(descriptor).is_none() =>
+                            Some(format!("{descriptor:?} is not a method descriptor")),
+                        Some(_) => None,
+                        None => Some(format!("name_and_type_index {name_and_type_index} is not a NameAndType entry")),
+                    }
+                }
+                Constant::Module { name_index } | Constant::Package { name_index } => match self.pool.utf8(*name_index) {
+                    Some(_) => None,
+                    None => Some(format!("name_index {name_index} is not a Utf8 entry")),
+                },
+                _ => None,
+            };
+            if let Some(problem) = problem {
+                self.report(location, problem);
+            }
+        }
+    }
+
+    /// Checks the class and name-and-type of a field or method reference,
+    /// then its name and descriptor with `check`.
+    fn check_member<F>(&self, class_index: u16, name_and_type_index: u16, check: F) -> Option<String>
+    where
+        F: FnOnce(&str, &str) -> Option<String>,
+    {
+        if self.pool.class_name(class_index).is_none() {
+            return Some(format!("class_index {class_index} is not a valid Class entry"));
+        }
+        match self.pool.name_and_type(name_and_type_index) {
+            Some((name, descriptor)) => check(name, descriptor),
+            None => Some(format!("name_and_type_index {name_and_type_index} is not a valid NameAndType entry")),
+        }
+    }
+
+    /// Checks that a method handle refers to the kind of member its kind
+    /// requires (JVMS 4.4.8).
+    fn check_method_handle(&self, index: u16) -> Option<String> {
+        let Ok(handle) = self.pool.method_handle(index) else {
+            return Some("reference kind or referenced member is invalid".to_owned());
+        };
+        match (handle.kind, &handle.member) {
+            (ReferenceKind::NewInvokeSpecial, MemberRef::Method(method)) if method.name.as_ref() != "<init>" =>
+                Some("newInvokeSpecial must refer to <init>".to_owned()),
+            (ReferenceKind::NewInvokeSpecial, _) => None,
+            (ReferenceKind::InvokeStatic | ReferenceKind::InvokeSpecial, MemberRef::InterfaceMethod(_))
+                if self.class.version.major < 52 =>
+                Some("invokeStatic and invokeSpecial may only refer to interface methods from version 52.0".to_owned()),
+            (_, MemberRef::Method(method) | MemberRef::InterfaceMethod(method))
+                if matches!(method.name.as_ref(), "<init>" | "<clinit>") =>
+                Some(format!("{:?} cannot refer to {}", handle.kind, method.name)),
+            _ => None,
+        }
+    }
+
+    fn check_header(&mut self) {
+        let access = self.class.access;
+        if access.contains(ClassAccessFlags::Module) {
+            return;
+        }
+
+        if access.contains(ClassAccessFlags::Interface) {
+            if !access.contains(ClassAccessFlags::Abstract) {
+                self.report(Location::Class, "an interface must be ACC_ABSTRACT");
+            }
+            if access.intersects(ClassAccessFlags::Final | ClassAccessFlags::Super | ClassAccessFlags::Enum) {
+                self.report(Location::Class, "an interface cannot be ACC_FINAL, ACC_SUPER or ACC_ENUM");
+            }
+            if self.class.superclass_name.as_deref() != Some("java/lang/Object") {
+                self.report(Location::Class, "the superclass of an interface must be java/lang/Object");
+            }
+        } else {
+            if access.contains(ClassAccessFlags::Annotation) {
+                self.report(Location::Class, "only an interface can be ACC_ANNOTATION");
+            }
+            if access.contains(ClassAccessFlags::Final | ClassAccessFlags::Abstract) {
+                self.report(Location::Class, "a class cannot be both ACC_FINAL and ACC_ABSTRACT");
+            }
+        }
+
+        if !descriptor::is_binary_name(&self.class.name) {
+            self.report(Location::Class, format!("{:?} is not a valid class name", self.class.name));
+        }
+        if let Some(superclass) = self.class.superclass_name.clone() {
+            if !descriptor::is_binary_name(&superclass) {
+                self.report(Location::Class, format!("superclass {superclass:?} is not a class"));
+            }
+        }
+        for interface in &self.class.interfaces {
+            if !descriptor::is_binary_name(interface) {
+                self.report(Location::Class, format!("interface {interface:?} is not a class"));
+            }
+        }
+    }
+
+    fn check_fields(&mut self) {
+        let mut seen = HashSet::new();
+        for field in self.class.static_fields.iter().chain(&self.class.fields) {
+            let location = Location::Field {
+                name: field.name.clone(),
+                descriptor: field.typ.clone(),
+            };
+            if !seen.insert((field.name.clone(), field.typ.clone())) {
+                self.report(location.clone(), "duplicate field");
+            }
+            if !descriptor::is_unqualified_name(&field.name) {
+                self.report(location.clone(), "invalid field name");
+            }
+            for problem in self.field_flag_problems(field) {
+                self.report(location.clone(), problem);
+            }
+            if let Some(problem) = self.constant_value_problem(field) {
+                self.report(location, problem);
+            }
+        }
+    }
+
+    fn field_flag_problems(&self, field: &Field) -> Vec<&'static str> {
+        let access = field.access;
+        let mut problems = Vec::new();
+        let visibility = FieldAccessFlags::Public | FieldAccessFlags::Private | FieldAccessFlags::Protected;
+        if access.intersection(visibility).bits().count_ones() > 1 {
+            problems.push("at most one of ACC_PUBLIC, ACC_PRIVATE and ACC_PROTECTED may be set");
+        }
+        if access.contains(FieldAccessFlags::Final | FieldAccessFlags::Volatile) {
+            problems.push("a field cannot be both ACC_FINAL and ACC_VOLATILE");
+        }
+        if self.class.is_interface() {
+            let required = FieldAccessFlags::Public | FieldAccessFlags::Static | FieldAccessFlags::Final;
+            if !access.contains(required) || !(required | FieldAccessFlags::Synthetic).contains(access) {
+                problems.push("an interface field must be ACC_PUBLIC, ACC_STATIC and ACC_FINAL and nothing else");
+            }
+        }
+        problems
+    }
+
+    /// Checks that a `ConstantValue` holds a constant of the field's type.
+    fn constant_value_problem(&self, field: &Field) -> Option<String> {
+        let index = field.constant_value()?;
+        let matches = match (&field.field_type, self.pool.get(index)) {
+            (FieldType::Int | FieldType::Short | FieldType::Char | FieldType::Byte | FieldType::Boolean, Some(Constant::Integer(_)))
+            | (FieldType::Long, Some(Constant::Long(_)))
+            | (FieldType::Float, Some(Constant::Float(_)))
+            | (FieldType::Double, Some(Constant::Double(_))) => true,
+            (FieldType::Object(name), Some(Constant::String { .. })) => name.as_ref() == "java/lang/String",
+            _ => false,
+        };
+        (!matches).then(|| format!("ConstantValue {index} does not match the field type"))
+    }
+
+    fn check_methods(&mut self) {
+        let mut seen = HashSet::new();
+        for method in self.class.static_methods.iter().chain(&self.class.methods) {
+            let location = Location::Method {
+                name: method.name.clone(),
+                descriptor: method.descriptor.clone(),
+            };
+            if !seen.insert((method.name.clone(), method.descriptor.clone())) {
+                self.report(location.clone(), "duplicate method");
+            }
+            if !descriptor::is_method_name(&method.name) {
+                self.report(location.clone(), "invalid method name");
+            }
+            for problem in self.method_flag_problems(method) {
+                self.report(location.clone(), problem);
+            }
+
+            let has_body = !method.access.intersects(MethodAccessFlags::Abstract | MethodAccessFlags::Native);
+            // A malformed `Code` attribute still counts here, and is reported
+            // by `check_attributes`.
+            let code_attributes = method.attributes.iter()
+                .filter(|attr| attr.name.as_ref() == "Code")
+                .count();
+            match (has_body, code_attributes) {
+                (true, 1) => {
+                    if let Some(code) = method.code() {
+                        for problem in code_problems(method, code, self.pool) {
+                            self.report(location.clone(), problem);
+                        }
+                    }
+                }
+                (true, _) => self.report(location.clone(), "a method with a body must have exactly one Code attribute"),
+                (false, 0) => {}
+                (false, _) => self.report(location.clone(), "an abstract or native method cannot have a Code attribute"),
+            }
+        }
+    }
+
+    /// Reports every attribute whose body is malformed, which parsing the
+    /// class does not look at.
+    fn check_attributes(&mut self) {
+        self.report_invalid(Location::Class, &self.class.attributes);
+        for field in self.class.static_fields.iter().chain(&self.class.fields) {
+            let location = Location::Field {
+                name: field.name.clone(),
+                descriptor: field.typ.clone(),
+            };
+            self.report_invalid(location, &field.attributes);
+        }
+        for method in self.class.static_methods.iter().chain(&self.class.methods) {
+            let location = Location::Method {
+                name: method.name.clone(),
+                descriptor: method.descriptor.clone(),
+            };
+            self.report_invalid(location.clone(), &method.attributes);
+            if let Some(code) = method.code() {
+                self.report_invalid(location, &code.attributes);
+            }
+        }
+    }
+
+    /// Annotations are left out, as the JVM does not depend on them.
+    fn report_invalid(&mut self, location: Location, attributes: &[Attribute]) {
+        for attribute in attributes.iter().filter(|attribute| !annotation::is_annotation_attribute(&attribute.name)) {
+            if let AttributeKind::Invalid(err) = attribute.kind() {
+                self.report(location.clone(), format!("malformed {} attribute: {err}", attribute.name));
+            }
+        }
+    }
+
+    fn method_flag_problems(&self, method: &Method) -> Vec<&'static str> {
+        let access = method.access;
+        let major = self.class.version.major;
+        let mut problems = Vec::new();
+
+        let visibility = MethodAccessFlags::Public | MethodAccessFlags::Private | MethodAccessFlags::Protected;
+        if access.intersection(visibility).bits().count_ones() > 1 {
+            problems.push("at most one of ACC_PUBLIC, ACC_PRIVATE and ACC_PROTECTED may be set");
+        }
+
+        match method.name.as_ref() {
+            "<clinit>" => {
+                if method.descriptor.as_ref() != "()V" {
+                    problems.push("<clinit> must have the descriptor ()V");
+                }
+                if major >= 51 && !access.contains(MethodAccessFlags::Static) {
+                    problems.push("<clinit> must be ACC_STATIC");
+                }
+                return problems;
+            }
+            "<init>" => {
+                if self.class.is_interface() {
+                    problems.push("an interface cannot have an <init> method");
+                }
+                let forbidden = MethodAccessFlags::Static | MethodAccessFlags::Final | MethodAccessFlags::Synchronized
+                    | MethodAccessFlags::Native | MethodAccessFlags::Abstract;
+                if access.intersects(forbidden) {
+                    problems.push("<init> cannot be static, final, synchronized, native or abstract");
+                }
+                if method.method_type.return_type.is_some() {
+                    problems.push("<init> must return void");
+                }
+            }
+            _ => {}
+        }
+
+        if self.class.is_interface() {
+            if major >= 52 {
+                let forbidden = MethodAccessFlags::Protected | MethodAccessFlags::Final
+                    | MethodAccessFlags::Synchronized | MethodAccessFlags::Native;
+                if access.intersects(forbidden) {
+                    problems.push("an interface method cannot be protected, final, synchronized or native");
+                }
+                if !access.intersects(MethodAccessFlags::Public | MethodAccessFlags::Private) {
+                    problems.push("an interface method must be ACC_PUBLIC or ACC_PRIVATE");
+                }
+            } else if !access.contains(MethodAccessFlags::Public | MethodAccessFlags::Abstract) {
+                problems.push("an interface method must be ACC_PUBLIC and ACC_ABSTRACT before version 52.0");
+            }
+        }
+
+        if access.contains(MethodAccessFlags::Abstract) {
+            let forbidden = MethodAccessFlags::Private | MethodAccessFlags::Static | MethodAccessFlags::Final
+                | MethodAccessFlags::Synchronized | MethodAccessFlags::Native;
+            if access.intersects(forbidden) {
+                problems.push("an abstract method cannot be private, static, final, synchronized or native");
+            }
+            if (46..=60).contains(&major) && access.contains(MethodAccessFlags::Struct) {
+                problems.push("an abstract method cannot be ACC_STRICT");
+            }
+        }
+        problems
+    }
+}
+
+/// Checks the limits of a method's code (JVMS 4.7.3).
+fn code_problems(method: &Method, code: &Code, pool: &ConstantPool) -> Vec<String> {
+    let mut problems = Vec::new();
+
+    if code.code_length == 0 || code.code_length > u16::MAX as u32 {
+        problems.push(format!("code_length {} must be between 1 and 65535", code.code_length));
+    }
+    if (code.max_locals as usize) < method.argument_slots() {
+        problems.push(format!(
+            "max_locals {} is less than the {} slots taken by the arguments",
+            code.max_locals, method.argument_slots(),
+        ));
+    }
+
+    for (index, instr) in code.code.iter().enumerate() {
+        for delta in instr.branch_deltas() {
+            if code.branch_target(index, delta).is_none() {
+                problems.push(format!(
+                    "branch at offset {} does not target an instruction",
+                    code.offset(index),
+                ));
+            }
+        }
+    }
+
+    for entry in &code.exception_table {
+        let range_ok = entry.start_pc < entry.end_pc
+            && code.index_of(entry.start_pc as u32).is_some()
+            && code.end_index_of(entry.end_pc as u32).is_some();
+        if !range_ok {
+            problems.push(format!("exception range {}..{} is not a range of instructions", entry.start_pc, entry.end_pc));
+        }
+        if code.index_of(entry.handler_pc as u32).is_none() {
+            problems.push(format!("exception handler at {} is not an instruction", entry.handler_pc));
+        }
+        if entry.catch_type != 0 && pool.class_name(entry.catch_type).is_none() {
+            problems.push(format!("catch_type {} is not a valid Class entry", entry.catch_type));
+        }
+    }
+    problems
+}
+
+/// A class name as it may appear in a `CONSTANT_Class`: a binary name, or
+/// an array type descriptor.
+fn is_class_name(name: &str) -> bool {
+    match name.starts_with('[') {
+        true => FieldType::parse(name).is_some(),
+        false => descriptor::is_binary_name(name),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::{Bytes, BytesMut};
+    use super::*;
+
+    fn apple() -> Class {
+        Class::parse(Bytes::from_static(include_bytes!("../Apple.class"))).unwrap()
+    }
+
+    fn field(name: &str, descriptor: &str) -> Location {
+        Location::Field { name: Arc::from(name), descriptor: Arc::from(descriptor) }
+    }
+
+    fn method(name: &str, descriptor: &str) -> Location {
+        Location::Method { name: Arc::from(name), descriptor: Arc::from(descriptor) }
+    }
+
+    /// Asserts that checking `class` finds exactly `expected`, as locations
+    /// and the start of each reason.
+    fn assert_violations(class: &Class, expected: &[(Location, &str)]) {
+        let violations = check_class(class);
+        assert_eq!(violations.len(), expected.len(), "{violations:#?}");
+        for (violation, (location, reason)) in violations.iter().zip(expected) {
+            assert_eq!(&violation.location, location);
+            assert!(violation.reason.starts_with(reason), "{violation}");
+        }
+    }
+
+    #[test]
+    fn accepts_a_well_formed_class() {
+        assert_violations(&apple(), &[]);
+    }
+
+    #[test]
+    fn checks_constant_pool_cross_references() {
+        let mut class = apple();
+        let pool = Arc::make_mut(&mut class.constant_pool);
+        let integer = pool.add(Constant::Integer(1)).unwrap();
+        let class_constant = pool.add(Constant::Class { name_index: integer }).unwrap();
+        let field_ref = pool.add_field_ref("Apple", "seeds", "()I").unwrap();
+        assert_violations(&class, &[
+            (Location::Constant(class_constant), &format!("name_index {integer} is not a Utf8 entry")),
+            (Location::Constant(field_ref), "\"()I\" is not a field descriptor"),
+        ]);
+    }
+
+    #[test]
+    fn checks_access_flag_combinations() {
+        let mut class = apple();
+        class.access |= ClassAccessFlags::Final | ClassAccessFlags::Abstract;
+        let seeds = class.fields.iter_mut().find(|field| field.name.as_ref() == "seeds").unwrap();
+        seeds.access |= FieldAccessFlags::Private;
+        let bite = class.methods.iter_mut().find(|method| method.name.as_ref() == "bite").unwrap();
+        bite.access |= MethodAccessFlags::Abstract;
+        assert_violations(&class, &[
+            (Location::Class, "a class cannot be both ACC_FINAL and ACC_ABSTRACT"),
+            (field("seeds", "I"), "at most one of ACC_PUBLIC, ACC_PRIVATE and ACC_PROTECTED"),
+            (method("bite", "()V"), "an abstract or native method cannot have a Code attribute"),
+        ]);
+    }
+
+    #[test]
+    fn checks_member_names() {
+        let mut class = apple();
+        let juice = class.fields.iter_mut().find(|field| field.name.as_ref() == "juice").unwrap();
+        juice.name = Arc::from("ju.ice");
+        let bite = class.methods.iter_mut().find(|method| method.name.as_ref() == "bite").unwrap();
+        bite.name = Arc::from("<bite>");
+        assert_violations(&class, &[
+            (field("ju.ice", "I"), "invalid field name"),
+            (method("<bite>", "()V"), "invalid method name"),
+        ]);
+    }
+
+    #[test]
+    fn checks_for_duplicate_members() {
+        let mut class = apple();
+        let seeds = class.fields.iter().find(|field| field.name.as_ref() == "seeds").unwrap().clone();
+        class.fields.push(seeds);
+        let bite = class.methods.iter().find(|method| method.name.as_ref() == "bite").unwrap().clone();
+        class.methods.push(bite);
+        assert_violations(&class, &[
+            (field("seeds", "I"), "duplicate field"),
+            (method("bite", "()V"), "duplicate method"),
+        ]);
+    }
+
+    #[test]
+    fn checks_code_limits() {
+        let mut class = apple();
+        let set_juice = class.methods.iter_mut().find(|method| method.name.as_ref() == "setJuice").unwrap();
+        let code = set_juice.attributes.iter_mut().find(|attr| attr.name.as_ref() == "Code").unwrap();
+        // max_locals, which must hold `this` and the int argument.
+        let mut info = BytesMut::from(&code.info()[..]);
+        info[2..4].copy_from_slice(&1u16.to_be_bytes());
+        code.set_info(info.freeze());
+        assert_violations(&class, &[
+            (method("setJuice", "(I)V"), "max_locals 1 is less than the 2 slots taken by the arguments"),
+        ]);
+    }
+}
@@ -1,20 +1,34 @@
-use std::cell::RefCell;
+use std::cell::{Cell, OnceCell, RefCell};
 use std::sync::{Arc, Condvar, Mutex};
 use std::task::Context;
 use bitflags::{bitflags, Flags};
-use bytes::{Buf, Bytes};
-use crate::code::Code;
-use crate::constant::Constant;
-use crate::error::{Error, Result};
+use bytes::{Buf, BufMut, Bytes};
+use crate::annotation::{self, Annotation, ElementValue, TypeAnnotation};
+use crate::attribute::{Attribute, AttributeKind, RecordComponent};
+use crate::check::{self, FormatViolation};
+use crate::code::{Code, Instr, WideInstr};
+use crate::constant::{Constant, ConstantPool};
+use crate::descriptor::{FieldType, MethodDescriptor};
+use crate::error::{Error, ParseResult, Result};
 use crate::interp::{Interp, InterpContext};
-use crate::value::{Object, Type, Value};
+use crate::module::ModuleDescriptor;
+use crate::reader::Reader;
+use crate::signature::{self, ClassSignature, FieldSignature, MethodSignature};
+use crate::stack_map::{Frame, VerificationType};
+use crate::value::{Object, Value};
+use crate::version::ClassVersion;
+use crate::writer::ClassWriter;
 
 #[derive(Debug)]
 pub struct Class {
+    pub version: ClassVersion,
+    pub access: ClassAccessFlags,
     pub name: Arc<str>,
-    pub superclass_name: Arc<str>,
+    /// `None` only for `java/lang/Object` and `module-info`.
+    pub superclass_name: Option<Arc<str>>,
 
-    pub constant_pool: Vec<Constant>,
+    /// Shared with the attributes of the class, which refer to it.
+    pub constant_pool: Arc<ConstantPool>,
 
     pub static_fields: Vec<Field>,
     pub static_values: Vec<RefCell<Value>>,
@@ -23,10 +37,43 @@ pub struct Class {
     pub fields: Vec<Field>,
     pub methods: Vec<Method>,
 
-    pub interfaces: Vec<u16>,
+    /// The names of the direct superinterfaces, in declaration order.
+    pub interfaces: Vec<Arc<str>>,
     pub attributes: Vec<Attribute>,
 
     pub monitor: (Mutex<()>, Condvar),
+    /// How far the class is through loading, linking and initialization.
+    pub state: Cell<ClassState>,
+    /// The instance fields of the class and its superclasses, set when the
+    /// class is prepared.
+    field_slots: OnceCell<Vec<FieldSlot>>,
+}
+
+/// An instance field's place in the objects of a class. A class's slots
+/// start with its superclass's, so a field keeps its slot number in every
+/// subclass.
+#[derive(Clone, Debug)]
+pub struct FieldSlot {
+    /// The class that declares the field.
+    pub class: Arc<str>,
+    pub name: Arc<str>,
+    pub typ: Arc<str>,
+    /// `typ`, parsed.
+    pub field_type: FieldType,
+}
+
+/// The progress of a loaded class through linking (JVMS 5.4) and
+/// initialization (JVMS 5.5).
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ClassState {
+    Loaded,
+    /// Verified and prepared, so static fields hold their default values.
+    Linked,
+    /// Static initializers are running on the thread with this id.
+    Initializing(usize),
+    Initialized,
+    /// Linking failed, so the class cannot be used.
+    Erroneous,
 }
 
 #[derive(Copy, Clone, Debug)]
@@ -37,58 +84,69 @@ pub enum MethodIndex {
 
 #[derive(Copy, Clone, Debug)]
 pub enum FieldIndex {
+    /// A slot in the instance fields of an object, counting those of
+    /// superclasses.
     Dynamic(usize),
     Static(usize),
 }
 
 impl Class {
-    pub fn parse(bytes: &mut Bytes) -> Result<Self> {
-        let magic: u32 = bytes.get_u32();
+    pub fn parse(bytes: Bytes) -> Result<Self> {
+        let mut reader = Reader::new(bytes);
+        let reader = &mut reader;
+
+        let magic: u32 = reader.u32()?;
         if magic != 0xCAFEBABE {
-            return Err(Error::InvalidClass);
+            return Err(reader.error_at(0, format!("bad magic number {magic:#010x}")).into());
         }
 
-        let minor: u16 = bytes.get_u16();
-        let major: u16 = bytes.get_u16();
+        let minor: u16 = reader.u16()?;
+        let major: u16 = reader.u16()?;
+        let version = ClassVersion::new(major, minor);
 
-        if major != 64 || minor != 0 {
-            return Err(Error::InvalidClass);
+        if !version.is_supported() {
+            return Err(Error::UnsupportedClassVersion(version));
         }
 
-        let constant_pool = parse_constants(bytes);
-
-        let access_flags: u16 = bytes.get_u16();
-        let this_class: u16 = bytes.get_u16();
-        let super_class: u16 = bytes.get_u16();
-
-        let interfaces = parse_interfaces(bytes);
-        let all_fields = parse_fields(bytes, &constant_pool);
-        let all_methods = parse_methods(bytes, &constant_pool);
-        let attributes = parse_attributes(bytes, &constant_pool);
+        let constant_pool = Arc::new(ConstantPool::from_bytes(reader, version)?);
+
+        let header_offset = reader.offset();
+        let access = ClassAccessFlags::from_bits_retain(reader.u16()?);
+        let name = reader.class_ref(&constant_pool)?;
+        let superclass_name = reader.optional_class_ref(&constant_pool)?;
+
+        let interfaces = reader.list(|r| r.class_ref(&constant_pool))?;
+        let all_fields = parse_fields(reader, &constant_pool, version)?;
+        let all_methods = parse_methods(reader, &constant_pool, version)?;
+        let attributes = Attribute::parse_list(reader, &constant_pool, version)?;
+        reader.expect_end()?;
+
+        if access.contains(ClassAccessFlags::Module) {
+            let is_module_info = version.major >= 53
+                && name.as_ref() == "module-info"
+                && superclass_name.is_none()
+                && interfaces.is_empty()
+                && all_fields.is_empty()
+                && all_methods.is_empty()
+                && attributes.iter().any(|attr| matches!(attr.kind(), AttributeKind::Module(_)));
+            if !is_module_info {
+                return Err(reader.error_at(header_offset, format!(
+                    "{name} has ACC_MODULE set but is not a valid module-info class"
+                )).into());
+            }
+        } else if superclass_name.is_none() && name.as_ref() != "java/lang/Object" {
+            return Err(reader.error_at(header_offset, format!("{name} has no superclass")).into());
+        }
 
-        let static_fields = all_fields.iter()
-            .filter(|field| field.access.contains(FieldAccessFlags::Static))
-            .cloned()
-            .collect::<Vec<_>>();
+        let (static_fields, fields): (Vec<_>, Vec<_>) = all_fields.into_iter()
+            .partition(|field| field.access.contains(FieldAccessFlags::Static));
         let static_values = vec![RefCell::new(Value::Int(0)); static_fields.len()];
-        let static_methods = all_methods.iter()
-            .filter(|method| method.access.contains(MethodAccessFlags::Static))
-            .cloned()
-            .collect::<Vec<_>>();
-
-        let fields = all_fields.iter()
-            .filter(|field| !field.access.contains(FieldAccessFlags::Static))
-            .cloned()
-            .collect::<Vec<_>>();
-        let methods = all_methods.iter()
-            .filter(|method| !method.access.contains(MethodAccessFlags::Static))
-            .cloned()
-            .collect::<Vec<_>>();
-
-        let name = get_class_name(&constant_pool, this_class);
-        let superclass_name = get_class_name(&constant_pool, super_class);
+        let (static_methods, methods): (Vec<_>, Vec<_>) = all_methods.into_iter()
+            .partition(|method| method.access.contains(MethodAccessFlags::Static));
 
         Ok(Self {
+            version,
+            access,
             name,
             superclass_name,
             constant_pool,
@@ -100,6 +158,8 @@ impl Class {
             methods,
             attributes,
             monitor: (Mutex::new(()), Condvar::new()),
+            state: Cell::new(ClassState::Loaded),
+            field_slots: OnceCell::new(),
         })
     }
 
@@ -107,8 +167,208 @@ impl Class {
         &self.name
     }
 
-    pub fn superclass(&self, context: &InterpContext) -> Arc<Class> {
-        context.class(&self.superclass_name).unwrap()
+    /// The generic superclass, superinterfaces and type parameters from the
+    /// `Signature` attribute, if there is one.
+    pub fn signature(&self) -> Result<Option<ClassSignature>> {
+        signature::from_attributes(&self.attributes, ClassSignature::parse)
+    }
+
+    /// The visible and invisible annotations on this class.
+    pub fn annotations(&self) -> impl Iterator<Item = &Annotation> {
+        annotation::annotations(&self.attributes)
+    }
+
+    /// The annotation with the field descriptor `type_name`, e.g.
+    /// `Ljava/lang/FunctionalInterface;`.
+    pub fn annotation(&self, type_name: &str) -> Option<&Annotation> {
+        self.annotations().find(|a| a.type_name.as_ref() == type_name)
+    }
+
+    /// Type annotations on the superclass, interfaces and type parameters.
+    pub fn type_annotations(&self) -> impl Iterator<Item = &TypeAnnotation> {
+        annotation::type_annotations(&self.attributes)
+    }
+
+    /// The name of the source file this class was compiled from, without
+    /// any directory.
+    pub fn source_file(&self) -> Option<&Arc<str>> {
+        self.attributes.iter().find_map(|attr| match &attr.kind() {
+            AttributeKind::SourceFile(name) => Some(name),
+            _ => None,
+        })
+    }
+
+    /// The components of a record class, or `None` if this is not one.
+    pub fn record_components(&self) -> Option<&[RecordComponent]> {
+        self.attributes.iter().find_map(|attr| match &attr.kind() {
+            AttributeKind::Record(components) => Some(components.as_slice()),
+            _ => None,
+        })
+    }
+
+    pub fn is_record(&self) -> bool {
+        self.record_components().is_some()
+    }
+
+    /// The classes allowed to directly extend or implement this class, or
+    /// `None` if it is not sealed.
+    pub fn permitted_subclasses(&self) -> Option<&[Arc<str>]> {
+        self.attributes.iter().find_map(|attr| match &attr.kind() {
+            AttributeKind::PermittedSubclasses(classes) => Some(classes.as_slice()),
+            _ => None,
+        })
+    }
+
+    pub fn is_sealed(&self) -> bool {
+        self.permitted_subclasses().is_some()
+    }
+
+    /// Whether the class named `subclass` may directly extend or implement
+    /// this class. Only the `PermittedSubclasses` list is consulted; the
+    /// module and package checks of JVMS 5.3.5 are left to the loader.
+    pub fn permits(&self, subclass: &str) -> bool {
+        self.permitted_subclasses()
+            .is_none_or(|classes| classes.iter().any(|class| class.as_ref() == subclass))
+    }
+
+    /// The host of the nest this class claims to belong to, if it is not
+    /// itself a nest host.
+    pub fn nest_host(&self) -> Option<&Arc<str>> {
+        self.attributes.iter().find_map(|attr| match &attr.kind() {
+            AttributeKind::NestHost(host) => Some(host),
+            _ => None,
+        })
+    }
+
+    /// The classes this nest host claims as members. Empty for a class that
+    /// is not a nest host, or whose nest only contains itself.
+    pub fn nest_members(&self) -> &[Arc<str>] {
+        self.attributes.iter().find_map(|attr| match &attr.kind() {
+            AttributeKind::NestMembers(members) => Some(members.as_slice()),
+            _ => None,
+        }).unwrap_or_default()
+    }
+
+    /// The name of the nest host, which is this class itself if it has no
+    /// `NestHost` attribute.
+    pub fn nest_host_name(&self) -> &Arc<str> {
+        self.nest_host().unwrap_or(&self.name)
+    }
+
+    /// Whether `other` belongs to the same nest, and so may access this
+    /// class's private members (JVMS 5.4.4). `host` is the loaded nest host
+    /// of this class, which must list any member other than itself.
+    pub fn is_nestmate_of(&self, other: &Class, host: &Class) -> bool {
+        let belongs = |class: &Class| {
+            class.nest_host_name() == &host.name
+                && (class.name == host.name || host.nest_members().contains(&class.name))
+        };
+        belongs(self) && belongs(other)
+    }
+
+    pub fn is_public(&self) -> bool {
+        self.access.contains(ClassAccessFlags::Public)
+    }
+
+    pub fn is_final(&self) -> bool {
+        self.access.contains(ClassAccessFlags::Final)
+    }
+
+    /// Whether this is an interface, including an annotation interface.
+    pub fn is_interface(&self) -> bool {
+        self.access.contains(ClassAccessFlags::Interface)
+    }
+
+    /// Whether this class cannot be instantiated, which is the case for
+    /// every interface.
+    pub fn is_abstract(&self) -> bool {
+        self.access.contains(ClassAccessFlags::Abstract)
+    }
+
+    pub fn is_annotation(&self) -> bool {
+        self.access.contains(ClassAccessFlags::Annotation)
+    }
+
+    pub fn is_enum(&self) -> bool {
+        self.access.contains(ClassAccessFlags::Enum)
+    }
+
+    /// Whether this is a `module-info` class, which describes a module
+    /// rather than declaring a class.
+    pub fn is_module(&self) -> bool {
+        self.access.contains(ClassAccessFlags::Module)
+    }
+
+    /// The module declaration of a `module-info` class.
+    pub fn module(&self) -> Option<&ModuleDescriptor> {
+        self.attributes.iter().find_map(|attr| match &attr.kind() {
+            AttributeKind::Module(module) => Some(module),
+            _ => None,
+        })
+    }
+
+    /// Every package of the module, if this `module-info` class lists them.
+    pub fn module_packages(&self) -> Option<&[Arc<str>]> {
+        self.attributes.iter().find_map(|attr| match &attr.kind() {
+            AttributeKind::ModulePackages(packages) => Some(packages.as_slice()),
+            _ => None,
+        })
+    }
+
+    /// The main class of the module, if this `module-info` class names one.
+    pub fn module_main_class(&self) -> Option<&Arc<str>> {
+        self.attributes.iter().find_map(|attr| match &attr.kind() {
+            AttributeKind::ModuleMainClass(class) => Some(class),
+            _ => None,
+        })
+    }
+
+    /// Checks this class against the static constraints of the class file
+    /// format (JVMS 4.8) that parsing does not enforce, returning every
+    /// violation found.
+    pub fn check_format(&self) -> Vec<FormatViolation> {
+        check::check_class(self)
+    }
+
+    /// Serializes this class back into a class file.
+    ///
+    /// The constant pool keeps every existing entry at its original index, so
+    /// raw attribute contents stay valid, and only gains entries for names
+    /// that are missing from it. Fields and methods are written in order of
+    /// their `position`, which is their declaration order unless changed.
+    pub fn write(&self) -> Result<Vec<u8>> {
+        let mut writer = ClassWriter::new(self.version, ConstantPool::clone(&self.constant_pool));
+        writer.set_access_flags(self.access);
+        writer.set_name(&self.name)?;
+        writer.set_superclass_name(self.superclass_name.as_deref())?;
+        for interface in &self.interfaces {
+            writer.add_interface(interface)?;
+        }
+        let mut fields = self.static_fields.iter().chain(&self.fields).collect::<Vec<_>>();
+        fields.sort_by_key(|field| field.position);
+        for field in fields {
+            writer.add_field(field)?;
+        }
+        let mut methods = self.static_methods.iter().chain(&self.methods).collect::<Vec<_>>();
+        methods.sort_by_key(|method| method.position);
+        for method in methods {
+            writer.add_method(method)?;
+        }
+        for attribute in &self.attributes {
+            writer.add_attribute(attribute)?;
+        }
+        writer.finish()
+    }
+
+    pub fn superclass(&self, context: &InterpContext) -> Result<Option<Arc<Class>>> {
+        self.superclass_name.as_deref()
+            .map(|name| context.class(name))
+            .transpose()
+    }
+
+    /// The index of the static initializer `<clinit>`, if the class has one.
+    pub fn initializer_index(&self) -> Option<MethodIndex> {
+        self.static_method_index("<clinit>", "()V")
     }
 
     pub fn method(&self, index: MethodIndex) -> &Method {
@@ -138,12 +398,57 @@ impl Class {
         ).map(MethodIndex::Static)
     }
 
+    /// Lays out the instance fields of the class after those of its
+    /// superclass, which must have been laid out already.
+    pub fn lay_out_fields(&self, superclass: Option<&Class>) {
+        let mut slots = superclass.map_or_else(Vec::new, |superclass| superclass.field_slots().to_vec());
+        slots.extend(self.fields.iter().map(|field| FieldSlot {
+            class: self.name.clone(),
+            name: field.name.clone(),
+            typ: field.typ.clone(),
+            field_type: field.field_type.clone(),
+        }));
+        // Linking a class a second time leaves the layout it already has.
+        let _ = self.field_slots.set(slots);
+    }
+
+    /// Every instance field of the class and its superclasses, indexed by
+    /// slot. Empty until the class is linked.
+    pub fn field_slots(&self) -> &[FieldSlot] {
+        self.field_slots.get().map_or(&[], Vec::as_slice)
+    }
+
+    /// The slot of the instance field named `name`, which may be inherited.
+    /// A field of the class hides a superclass field with the same name and
+    /// descriptor.
     pub fn field_index(&self, name: &str, descriptor: &str) -> Option<FieldIndex> {
-        self.fields.iter().position(|field|
-            field.name.as_ref() == name && field.typ.as_ref() == descriptor
+        self.field_slots().iter().rposition(|slot|
+            slot.name.as_ref() == name && slot.typ.as_ref() == descriptor
         ).map(FieldIndex::Dynamic)
     }
 
+    /// The field the class itself declares with `name` and `field_type`,
+    /// static or not.
+    pub fn declared_field_index(&self, name: &str, field_type: &FieldType) -> Option<FieldIndex> {
+        let matches = |field: &Field| field.name.as_ref() == name && &field.field_type == field_type;
+        if let Some(index) = self.static_fields.iter().position(matches) {
+            return Some(FieldIndex::Static(index));
+        }
+        let first_slot = self.field_slots().len().checked_sub(self.fields.len())?;
+        self.fields.iter().position(matches).map(|index| FieldIndex::Dynamic(first_slot + index))
+    }
+
+    /// The declaration of the field at `index` in this class.
+    pub fn declared_field(&self, index: FieldIndex) -> Option<&Field> {
+        match index {
+            FieldIndex::Static(index) => self.static_fields.get(index),
+            FieldIndex::Dynamic(slot) => {
+                let first_slot = self.field_slots().len().checked_sub(self.fields.len())?;
+                self.fields.get(slot.checked_sub(first_slot)?)
+            }
+        }
+    }
+
     pub fn static_field_index(&self, name: &str, descriptor: &str) -> Option<FieldIndex> {
         self.static_fields.iter().position(|field|
             field.name.as_ref() == name && field.typ.as_ref() == descriptor
@@ -176,53 +481,35 @@ impl Class {
     }
 
     pub fn constant(&self, n: usize) -> Option<&Constant> {
-        if n == 0 {
-            return None
-        }
-        self.constant_pool.get(n - 1)
+        self.constant_pool.get(n.try_into().ok()?)
     }
 
-    pub fn get_static_from_constant(&self, context: &InterpContext, constant_index: usize) -> Result<Value> {
-        let Some(Constant::FieldRef { class_index, name_and_type_index }) = self.constant(constant_index) else {
-            return Err(Error::InvalidClass)
-        };
-        let Some(Constant::Class { name_index }) = self.constant(*class_index as usize) else {
-            return Err(Error::InvalidClass)
-        };
-        let Some(Constant::NameAndType { name_index, descriptor_index }) = self.constant(*name_and_type_index as usize) else {
-            return Err(Error::InvalidClass)
-        };
-        let Some(Constant::Utf8(name)) = self.constant(*name_index as usize) else {
-            return Err(Error::InvalidClass)
-        };
-        let Some(Constant::Utf8(descriptor)) = self.constant(*descriptor_index as usize) else {
-            return Err(Error::InvalidClass)
-        };
-
-        let Some(class) = context.class(name) else {
-            eprintln!("Class not found: name: {name}, descriptor: {descriptor}");
-            return Err(Error::ClassNotFound);
-        };
-        let field_index = class.static_field_index(name, descriptor.as_ref()).unwrap();
+    pub fn get_static_from_constant(&self, context: &InterpContext, constant_index: u16) -> Result<Value> {
+        let field = self.constant_pool.field_ref(constant_index)?;
+        let (class, field_index) = context.resolve_field(self, &field)?;
+        if !matches!(field_index, FieldIndex::Static(_)) {
+            return Err(Error::IncompatibleClassChange(format!(
+                "{}.{} is not a static field", field.class, field.name,
+            )));
+        }
         Ok(class.static_field_value(field_index).unwrap())
     }
 }
 
-fn get_class_name(constant_pool: &[Constant], class_index: u16) -> Arc<str> {
-    let class_info = &constant_pool[class_index as usize - 1];
-    if let Constant::Class { name_index } = class_info {
-        let utf8_info = &constant_pool[*name_index as usize - 1];
-        if let Constant::Utf8(name) = utf8_info {
-            name.clone()
-        } else {
-            panic!("Invalid class name");
-        }
-    } else {
-        panic!("Invalid class info");
+bitflags! {
+    #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+    pub struct ClassAccessFlags: u16 {
+        const Public = 0x0001;
+        const Final = 0x0010;
+        const Super = 0x0020;
+        const Interface = 0x0200;
+        const Abstract = 0x0400;
+        const Synthetic = 0x1000;
+        const Annotation = 0x2000;
+        const Enum = 0x4000;
+        const Module = 0x8000;
     }
-}
 
-bitflags! {
     #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
     pub struct FieldAccessFlags: u16 {
         const Public = 0x0001;
@@ -257,33 +544,69 @@ bitflags! {
 pub struct Field {
     pub name: Arc<str>,
     pub typ: Arc<str>,
+    /// `typ`, parsed.
+    pub field_type: FieldType,
     pub access: FieldAccessFlags,
     pub attributes: Vec<Attribute>,
+    /// Where the field appears among the fields of the class file, so that
+    /// `Class::write` can keep the declaration order.
+    pub position: usize,
 }
 
 impl Field {
-    pub fn from_bytes(bytes: &mut Bytes, constant_pool: &[Constant]) -> Self {
-        let access_flags: u16 = bytes.get_u16();
-        let name_index: u16 = bytes.get_u16();
-        let descriptor_index: u16 = bytes.get_u16();
-
-        let name = match &constant_pool[name_index as usize - 1] {
-            Constant::Utf8(name) => name.clone(),
-            _ => panic!("Invalid field name"),
-        };
-        let typ = match &constant_pool[descriptor_index as usize - 1] {
-            Constant::Utf8(typ) => name.clone(),
-            _ => panic!("Invalid field type"),
-        };
+    pub fn from_bytes(reader: &mut Reader, constant_pool: &Arc<ConstantPool>, version: ClassVersion) -> ParseResult<Self> {
+        reader.within("field_info", |reader| {
+            let access_flags: u16 = reader.u16()?;
+            let name = reader.utf8_ref(constant_pool)?;
+            let offset = reader.offset();
+            let typ = reader.utf8_ref(constant_pool)?;
+            let field_type = FieldType::parse(&typ)
+                .ok_or_else(|| reader.error_at(offset, format!("invalid field descriptor {typ}")))?;
+            let attributes = Attribute::parse_list(reader, constant_pool, version)?;
+
+            Ok(Self {
+                name,
+                typ,
+                field_type,
+                access: FieldAccessFlags::from_bits_retain(access_flags),
+                attributes,
+                position: 0,
+            })
+        })
+    }
+
+    /// The index of the constant that initializes this field, from its
+    /// `ConstantValue` attribute.
+    pub fn constant_value(&self) -> Option<u16> {
+        self.attributes.iter().find_map(|attr| match attr.kind() {
+            AttributeKind::ConstantValue(index) => Some(*index),
+            _ => None,
+        })
+    }
 
-        let attributes = parse_attributes(bytes, &constant_pool);
+    /// The generic type from the `Signature` attribute, if there is one.
+    pub fn signature(&self) -> Result<Option<FieldSignature>> {
+        signature::from_attributes(&self.attributes, FieldSignature::parse)
+    }
 
-        Self {
-            name,
-            typ,
-            access: FieldAccessFlags::from_bits_retain(access_flags),
-            attributes,
-        }
+    pub fn annotations(&self) -> impl Iterator<Item = &Annotation> {
+        annotation::annotations(&self.attributes)
+    }
+
+    pub fn annotation(&self, type_name: &str) -> Option<&Annotation> {
+        self.annotations().find(|a| a.type_name.as_ref() == type_name)
+    }
+
+    pub fn type_annotations(&self) -> impl Iterator<Item = &TypeAnnotation> {
+        annotation::type_annotations(&self.attributes)
+    }
+
+    /// Appends this field as a `field_info` structure.
+    pub fn to_bytes(&self, constant_pool: &mut ConstantPool, buf: &mut Vec<u8>) -> Result<()> {
+        buf.put_u16(self.access.bits());
+        buf.put_u16(constant_pool.add_utf8(&self.name)?);
+        buf.put_u16(constant_pool.add_utf8(&self.typ)?);
+        put_attributes(&self.attributes, constant_pool, buf)
     }
 }
 
@@ -291,121 +614,220 @@ impl Field {
 pub struct Method {
     pub name: Arc<str>,
     pub descriptor: Arc<str>,
+    /// `descriptor`, parsed.
+    pub method_type: MethodDescriptor,
     pub access: MethodAccessFlags,
     pub attributes: Vec<Attribute>,
-    pub code: Option<Code>,
+    /// Where the method appears among the methods of the class file, so
+    /// that `Class::write` can keep the declaration order.
+    pub position: usize,
 }
 
 impl Method {
-    pub fn from_bytes(bytes: &mut Bytes, constant_pool: &[Constant]) -> Self {
-        let access_flags: u16 = bytes.get_u16();
-        let name_index: u16 = bytes.get_u16();
-        let descriptor_index: u16 = bytes.get_u16();
-
-        let name = match &constant_pool[name_index as usize - 1] {
-            Constant::Utf8(name) => name.clone(),
-            _ => panic!("Invalid method name"),
-        };
-        let descriptor = match &constant_pool[descriptor_index as usize - 1] {
-            Constant::Utf8(descriptor) => descriptor.clone(),
-            _ => panic!("Invalid method descriptor"),
-        };
+    pub fn from_bytes(reader: &mut Reader, constant_pool: &Arc<ConstantPool>, version: ClassVersion) -> ParseResult<Self> {
+        reader.within("method_info", |reader| {
+            let offset = reader.offset();
+            let access_flags: u16 = reader.u16()?;
+            let name = reader.utf8_ref(constant_pool)?;
+            let descriptor_offset = reader.offset();
+            let descriptor = reader.utf8_ref(constant_pool)?;
+            let method_type = MethodDescriptor::parse(&descriptor)
+                .ok_or_else(|| reader.error_at(descriptor_offset, format!("invalid method descriptor {descriptor}")))?;
+            let attributes = Attribute::parse_list(reader, constant_pool, version)?;
+
+            let method = Self {
+                name,
+                descriptor,
+                method_type,
+                access: MethodAccessFlags::from_bits_retain(access_flags),
+                attributes,
+                position: 0,
+            };
+
+            if method.argument_slots() > 255 {
+                return Err(reader.error_at(descriptor_offset, format!(
+                    "method descriptor {} has more than 255 parameter slots", method.descriptor,
+                )));
+            }
+
+            Ok(method)
+        })
+    }
 
-        let attributes = parse_attributes(bytes, &constant_pool);
+    /// The generic parameter, return and exception types and type
+    /// parameters from the `Signature` attribute, if there is one.
+    pub fn signature(&self) -> Result<Option<MethodSignature>> {
+        signature::from_attributes(&self.attributes, MethodSignature::parse)
+    }
 
-        let code = attributes.iter()
-            .find(|attr| attr.name.as_ref() == "Code")
-            .map(|attr| Code::from_bytes(
-                &mut Bytes::from(attr.info.clone()), constant_pool)
-            );
+    pub fn annotations(&self) -> impl Iterator<Item = &Annotation> {
+        annotation::annotations(&self.attributes)
+    }
 
-        Self {
-            name,
-            descriptor,
-            access: MethodAccessFlags::from_bits_retain(access_flags),
-            attributes,
-            code,
-        }
+    /// The annotation with the field descriptor `type_name`, e.g.
+    /// `Lorg/junit/Test;`.
+    pub fn annotation(&self, type_name: &str) -> Option<&Annotation> {
+        self.annotations().find(|a| a.type_name.as_ref() == type_name)
+    }
+
+    /// The visible and invisible annotations on the formal parameter at
+    /// `index`. Compilers may leave out synthetic parameters, so `index`
+    /// counts only the parameters that are annotated.
+    pub fn parameter_annotations(&self, index: usize) -> impl Iterator<Item = &Annotation> {
+        self.attributes.iter().flat_map(move |attr| match &attr.kind() {
+            AttributeKind::RuntimeVisibleParameterAnnotations(parameters)
+            | AttributeKind::RuntimeInvisibleParameterAnnotations(parameters) =>
+                parameters.get(index).map_or(&[][..], Vec::as_slice),
+            _ => &[],
+        })
+    }
+
+    /// Type annotations on the signature of this method. Those inside its
+    /// body are on the `Code` attribute.
+    pub fn type_annotations(&self) -> impl Iterator<Item = &TypeAnnotation> {
+        annotation::type_annotations(&self.attributes)
+    }
+
+    /// The default value of this element, if this method is an element of
+    /// an annotation interface and has one.
+    pub fn annotation_default(&self) -> Option<&ElementValue> {
+        self.attributes.iter().find_map(|attr| match &attr.kind() {
+            AttributeKind::AnnotationDefault(value) => Some(value),
+            _ => None,
+        })
+    }
+
+    /// Appends this method as a `method_info` structure.
+    pub fn to_bytes(&self, constant_pool: &mut ConstantPool, buf: &mut Vec<u8>) -> Result<()> {
+        buf.put_u16(self.access.bits());
+        buf.put_u16(constant_pool.add_utf8(&self.name)?);
+        buf.put_u16(constant_pool.add_utf8(&self.descriptor)?);
+        put_attributes(&self.attributes, constant_pool, buf)
     }
 
     pub fn has_code(&self) -> bool {
-        self.code.is_some()
+        self.code().is_some()
     }
 
     pub fn max_locals(&self) -> Option<usize> {
-        self.code.as_ref().map(|c| c.max_locals as usize)
+        self.code().map(|c| c.max_locals as usize)
     }
 
     pub fn max_stack(&self) -> Option<usize> {
-        self.code.as_ref().map(|c| c.max_stack as usize)
+        self.code().map(|c| c.max_stack as usize)
     }
 
     pub fn code(&self) -> Option<&Code> {
-        self.code.as_ref()
+        self.attributes.iter().find_map(|attr| match attr.kind() {
+            AttributeKind::Code(code) => Some(code),
+            _ => None,
+        })
     }
-}
 
-#[derive(Clone, Debug)]
-pub struct Attribute {
-    pub name: Arc<str>,
-    pub info: Vec<u8>,
-}
+    /// Rewrites the method's code with `Code::rewrite` and stores the result
+    /// in its `Code` attribute, so that it is kept when the class is
+    /// written. Fails with `Error::MissingCode` if the method has no code.
+    pub fn rewrite_code<F>(&mut self, f: F) -> Result<()>
+    where
+        F: FnMut(usize, &Instr) -> Vec<Instr>,
+    {
+        let attribute = self.attributes.iter_mut()
+            .find(|attr| matches!(attr.kind(), AttributeKind::Code(_)))
+            .ok_or_else(|| Error::MissingCode(format!("{}{}", self.name, self.descriptor)))?;
+        let AttributeKind::Code(code) = attribute.kind() else { unreachable!() };
+        let mut code = code.clone();
+        code.rewrite(f)?;
+        attribute.set_info(Bytes::from(code.to_bytes()));
+        Ok(())
+    }
 
-impl Attribute {
-    pub fn from_bytes(bytes: &mut Bytes, constant_pool: &[Constant]) -> Self {
-        let name_index: u16 = bytes.get_u16();
-        let length: u32 = bytes.get_u32();
-        let info = bytes.copy_to_bytes(length as usize);
+    pub fn is_static(&self) -> bool {
+        self.access.contains(MethodAccessFlags::Static)
+    }
 
-        let name = match &constant_pool[name_index as usize - 1] {
-            Constant::Utf8(name) => name.clone(),
-            _ => panic!("Invalid attribute name"),
-        };
+    /// The number of local variable slots the arguments occupy, including
+    /// `this` for an instance method.
+    pub fn argument_slots(&self) -> usize {
+        self.method_type.parameter_slots() + if self.is_static() { 0 } else { 1 }
+    }
 
-        Self {
-            name,
-            info: info.to_vec(),
+    /// The implicit frame at the start of the method, which the
+    /// `StackMapTable` is relative to (JVMS 4.10.1.6). `class` is the name
+    /// of the class declaring the method.
+    pub fn initial_frame(&self, class: &str) -> Frame {
+        let mut locals = Vec::new();
+        if !self.is_static() {
+            locals.push(if self.name.as_ref() == "<init>" && class != "java/lang/Object" {
+                VerificationType::UninitializedThis
+            } else {
+                VerificationType::Object(Arc::from(class))
+            });
         }
+        locals.extend(self.method_type.parameters.iter().map(VerificationType::from_field_type));
+        Frame::initial(&locals)
     }
 
-    pub fn parse_list(bytes: &mut Bytes, constant_pool: &[Constant]) -> Vec<Self> {
-        parse_attributes(bytes, constant_pool)
+    /// The expanded `StackMapTable` frames of this method's code, or an
+    /// empty list if it has none.
+    pub fn stack_map_frames(&self, class: &str) -> Result<Vec<Frame>> {
+        match self.code() {
+            Some(code) => code.stack_map_frames(&self.initial_frame(class)),
+            None => Ok(Vec::new()),
+        }
     }
-}
 
-fn parse_constants(bytes: &mut Bytes) -> Vec<Constant> {
-    let constants_count: u16 = bytes.get_u16();
-    (0..constants_count - 1)
-        .map(|_| Constant::from_bytes(bytes))
-        .collect::<Vec<_>>()
+    /// Whether this method contains `jsr`, `jsr_w` or `ret`, which are only
+    /// legal in class files older than 51.0.
+    pub fn uses_jsr(&self) -> bool {
+        self.code().iter()
+            .flat_map(|code| code.code.iter())
+            .any(|instr| matches!(
+                instr,
+                Instr::Jsr(_) | Instr::JsrW(_) | Instr::Ret(_) | Instr::Wide(WideInstr::Ret(_))
+            ))
+    }
 }
 
-fn parse_interfaces(bytes: &mut Bytes) -> Vec<u16> {
-    let interfaces_count: u16 = bytes.get_u16();
-    (0..interfaces_count)
-        .map(|_| bytes.get_u16())
-        .collect::<Vec<_>>()
+fn parse_fields(reader: &mut Reader, constant_pool: &Arc<ConstantPool>, version: ClassVersion) -> ParseResult<Vec<Field>> {
+    let fields_count: u16 = reader.u16()?;
+    (0..fields_count as usize)
+        .map(|position| Ok(Field { position, ..Field::from_bytes(reader, constant_pool, version)? }))
+        .collect()
 }
 
-fn parse_fields(bytes: &mut Bytes, constant_pool: &[Constant]) -> Vec<Field> {
-    let fields_count: u16 = bytes.get_u16();
-    (0..fields_count)
-        .map(|_| Field::from_bytes(bytes, constant_pool))
-        .collect::<Vec<_>>()
+fn parse_methods(reader: &mut Reader, constant_pool: &Arc<ConstantPool>, version: ClassVersion) -> ParseResult<Vec<Method>> {
+    let methods_count: u16 = reader.u16()?;
+    (0..methods_count as usize)
+        .map(|position| Ok(Method { position, ..Method::from_bytes(reader, constant_pool, version)? }))
+        .collect()
 }
 
-fn parse_methods(bytes: &mut Bytes, constant_pool: &[Constant]) -> Vec<Method> {
-    let methods_count: u16 = bytes.get_u16();
-    (0..methods_count)
-        .map(|_| Method::from_bytes(bytes, constant_pool))
-        .collect::<Vec<_>>()
-}
 
-fn parse_attributes(bytes: &mut Bytes, constant_pool: &[Constant]) -> Vec<Attribute> {
-    let attributes_count: u16 = bytes.get_u16();
-    (0..attributes_count)
-        .map(|_| Attribute::from_bytes(bytes, constant_pool))
-        .collect::<Vec<_>>()
+fn put_attributes(attributes: &[Attribute], constant_pool: &mut ConstantPool, buf: &mut Vec<u8>) -> Result<()> {
+    buf.put_u16(attributes.len() as u16);
+    for attribute in attributes {
+        attribute.to_bytes(constant_pool, buf)?;
+    }
+    Ok(())
 }
 
-
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+    use super::*;
+
+    #[test]
+    fn writes_classes_back_unchanged() {
+        let classes: [&'static [u8]; 5] = [
+            include_bytes!("../Hello.class"),
+            include_bytes!("../Apple.class"),
+            include_bytes!("../tests/fixtures/Switches.class"),
+            include_bytes!("../tests/fixtures/Invokes.class"),
+            include_bytes!("../tests/fixtures/Locals.class"),
+        ];
+        for bytes in classes {
+            let class = Class::parse(Bytes::from_static(bytes)).unwrap();
+            assert_eq!(class.write().unwrap(), bytes, "{}", class.name);
+        }
+    }
+}
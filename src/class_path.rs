@@ -0,0 +1,249 @@
+//! Finding class files by binary name, like the `-classpath` option of
+//! `java`.
+
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use bytes::Bytes;
+use crate::descriptor;
+use crate::error::Result;
+use crate::jar::{Jar, Manifest};
+use crate::jimage::JImage;
+
+/// An ordered list of places to look for classes. The first entry that has
+/// a class wins.
+#[derive(Clone, Debug, Default)]
+pub struct ClassPath {
+    pub entries: Vec<ClassPathEntry>,
+}
+
+#[derive(Clone, Debug)]
+pub enum ClassPathEntry {
+    /// A directory holding class files in subdirectories named after their
+    /// packages, e.g. `com/acme/Foo.class`.
+    Directory(PathBuf),
+    /// A JAR or zip file laid out the same way.
+    Jar(Arc<Jar>),
+    /// The `lib/modules` image of a JDK, holding the classes of every
+    /// module in it.
+    JImage(Arc<JImage>),
+    /// A JMOD file from the `jmods` directory of a JDK, which keeps its
+    /// classes under `classes/`.
+    Jmod(Arc<Jar>),
+}
+
+impl ClassPath {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A class path from a list of paths joined by the platform's path
+    /// separator, as in the `CLASSPATH` environment variable.
+    pub fn parse(paths: &str) -> Result<Self> {
+        let mut class_path = Self::new();
+        for path in std::env::split_paths(paths) {
+            class_path.push_path(path)?;
+        }
+        Ok(class_path)
+    }
+
+    pub fn push(&mut self, entry: ClassPathEntry) {
+        self.entries.push(entry);
+    }
+
+    /// Adds the entry for `path`: a JAR if it is a file and a directory
+    /// otherwise. An empty path means the current directory, and a path
+    /// ending in `*` means every JAR in that directory.
+    pub fn push_path<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        if path.as_os_str().is_empty() {
+            self.push(ClassPathEntry::Directory(PathBuf::from(".")));
+        } else if path.file_name().is_some_and(|name| name == "*") {
+            let directory = path.parent().unwrap_or(Path::new("."));
+            // Like `java`, treat a wildcard in a missing directory as
+            // matching nothing.
+            let entries = match std::fs::read_dir(directory) {
+                Ok(entries) => entries,
+                Err(err) if matches!(err.kind(), ErrorKind::NotFound | ErrorKind::NotADirectory) => return Ok(()),
+                Err(err) => return Err(err.into()),
+            };
+            let mut jars = Vec::new();
+            for entry in entries {
+                let jar = entry?.path();
+                let is_jar = jar.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("jar"));
+                if is_jar && jar.is_file() {
+                    jars.push(jar);
+                }
+            }
+            jars.sort();
+            for jar in jars {
+                self.push_jar(Arc::new(Jar::open(jar)?))?;
+            }
+        } else if path.is_file() {
+            self.push_jar(Arc::new(Jar::open(path)?))?;
+        } else {
+            self.push(ClassPathEntry::Directory(path.to_owned()));
+        }
+        Ok(())
+    }
+
+    /// Adds the platform classes of the JDK installed at `java_home`, from
+    /// its `lib/modules` image or, failing that, its `jmods` directory.
+    pub fn push_java_home<P: AsRef<Path>>(&mut self, java_home: P) -> Result<()> {
+        let java_home = java_home.as_ref();
+        let image = java_home.join("lib").join("modules");
+        if image.is_file() {
+            self.push(ClassPathEntry::JImage(Arc::new(JImage::open(image)?)));
+            return Ok(());
+        }
+
+        let mut jmods = Vec::new();
+        for entry in std::fs::read_dir(java_home.join("jmods"))? {
+            let jmod = entry?.path();
+            if jmod.extension().is_some_and(|extension| extension == "jmod") {
+                jmods.push(jmod);
+            }
+        }
+        jmods.sort();
+        for jmod in jmods {
+            self.push(ClassPathEntry::Jmod(Arc::new(Jar::open_on_demand(jmod)?)));
+        }
+        Ok(())
+    }
+
+    /// Adds `jar`, followed by the entries named by the `Class-Path` of its
+    /// manifest, which are relative to the directory the JAR is in unless
+    /// they are absolute `file:` URLs. JARs that are already on the class
+    /// path are skipped.
+    pub fn push_jar(&mut self, jar: Arc<Jar>) -> Result<()> {
+        // Compare canonical paths, so that manifests naming each other
+        // through different relative paths cannot recurse forever.
+        let canonical = |path: &Path| std::fs::canonicalize(path).unwrap_or_else(|_| path.to_owned());
+        let jar_path = canonical(&jar.path);
+        let already_added = self.entries.iter().any(|entry| match entry {
+            ClassPathEntry::Jar(existing) => canonical(&existing.path) == jar_path,
+            _ => false,
+        });
+        if already_added {
+            return Ok(());
+        }
+
+        self.push(ClassPathEntry::Jar(jar.clone()));
+        let base = jar.path.parent().unwrap_or(Path::new("."));
+        for path in jar.manifest().into_iter().flat_map(Manifest::class_path) {
+            let path = base.join(path);
+            // Like `java`, ignore manifest entries that do not exist.
+            if path.exists() {
+                self.push_path(path)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The contents of the class file for the class with binary name `name`,
+    /// such as `com/acme/Foo`, or `None` if no entry has it.
+    pub fn find_class(&self, name: &str) -> Result<Option<Bytes>> {
+        if !descriptor::is_binary_name(name) {
+            return Ok(None);
+        }
+        self.find_resource(&format!("{name}.class"))
+    }
+
+    /// The contents of the resource at `path`, a `/`-separated path relative
+    /// to the root of each entry, or `None` if no entry has it.
+    pub fn find_resource(&self, path: &str) -> Result<Option<Bytes>> {
+        for entry in &self.entries {
+            if let Some(bytes) = entry.find_resource(path)? {
+                return Ok(Some(bytes));
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl ClassPathEntry {
+    pub fn find_resource(&self, path: &str) -> Result<Option<Bytes>> {
+        match self {
+            Self::Directory(directory) => match std::fs::read(directory.join(path)) {
+                Ok(bytes) => Ok(Some(Bytes::from(bytes))),
+                Err(err) if matches!(err.kind(), ErrorKind::NotFound | ErrorKind::NotADirectory) => Ok(None),
+                Err(err) => Err(err.into()),
+            },
+            Self::Jar(jar) => jar.find_resource(path),
+            Self::JImage(image) => image.find_resource(path),
+            Self::Jmod(jmod) => jmod.entry(&format!("classes/{path}")),
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use crate::jar::tests::zip;
+    use super::*;
+
+    /// An empty directory of its own for the test called `name`.
+    pub(crate) fn scratch_directory(name: &str) -> PathBuf {
+        let directory = std::env::temp_dir().join(format!("jvm-{}-{name}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&directory);
+        std::fs::create_dir_all(&directory).unwrap();
+        directory
+    }
+
+    fn jar_paths(class_path: &ClassPath) -> Vec<&Path> {
+        class_path.entries.iter()
+            .map(|entry| match entry {
+                ClassPathEntry::Jar(jar) => jar.path.as_path(),
+                entry => panic!("expected a jar, found {entry:?}"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn follows_manifest_class_paths_around_a_cycle_once() {
+        let directory = scratch_directory("cycle");
+        let a = directory.join("a.jar");
+        let b = directory.join("b.jar");
+        std::fs::write(&a, zip(&[("META-INF/MANIFEST.MF", b"Class-Path: b.jar\n", false), ("A.class", b"A", false)], false)).unwrap();
+        std::fs::write(&b, zip(&[("META-INF/MANIFEST.MF", b"Class-Path: ./a.jar\n", true), ("B.class", b"B", true)], false)).unwrap();
+
+        let mut class_path = ClassPath::new();
+        class_path.push_path(&a).unwrap();
+        assert_eq!(jar_paths(&class_path), [a.as_path(), b.as_path()]);
+        assert_eq!(class_path.find_class("B").unwrap().unwrap(), &b"B"[..]);
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn decodes_manifest_class_path_urls() {
+        let directory = scratch_directory("encoded");
+        std::fs::create_dir(directory.join("lib")).unwrap();
+        let a = directory.join("a.jar");
+        let b = directory.join("lib").join("my lib.jar");
+        std::fs::write(&a, zip(&[("META-INF/MANIFEST.MF", b"Class-Path: lib/my%20lib.jar\n", false)], false)).unwrap();
+        std::fs::write(&b, zip(&[("B.class", b"B", false)], false)).unwrap();
+
+        let mut class_path = ClassPath::new();
+        class_path.push_path(&a).unwrap();
+        assert_eq!(jar_paths(&class_path), [a, b]);
+        assert_eq!(class_path.find_class("B").unwrap().unwrap(), &b"B"[..]);
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn expands_wildcards_to_the_jars_in_a_directory() {
+        let directory = scratch_directory("wildcard");
+        for name in ["b.jar", "a.JAR"] {
+            std::fs::write(directory.join(name), zip(&[], false)).unwrap();
+        }
+        std::fs::write(directory.join("c.txt"), "").unwrap();
+
+        let mut class_path = ClassPath::new();
+        class_path.push_path(directory.join("*")).unwrap();
+        assert_eq!(jar_paths(&class_path), [directory.join("a.JAR"), directory.join("b.jar")]);
+
+        let mut class_path = ClassPath::new();
+        class_path.push_path(directory.join("missing").join("*")).unwrap();
+        assert!(class_path.entries.is_empty());
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+}
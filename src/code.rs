@@ -1,41 +1,405 @@
-use bytes::{Buf, Bytes};
-use crate::class::{Attribute};
-use crate::constant::Constant;
+use std::cell::OnceCell;
+use std::sync::Arc;
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use crate::annotation::{self, TypeAnnotation};
+use crate::attribute::{Attribute, AttributeKind, LineNumber};
+use crate::constant::ConstantPool;
+use crate::error::{Error, ParseResult, Result};
+use crate::reader::Reader;
+use crate::stack_map::{self, Frame, StackMapFrame};
+use crate::version::ClassVersion;
 
 #[derive(Clone, Debug)]
 pub struct Code {
     pub max_stack: u16,
     pub max_locals: u16,
     pub code: Vec<Instr>,
+    /// The byte offset of each instruction in `code`, in increasing order.
+    /// Branch operands, exception handlers and debug attributes all refer to
+    /// instructions by byte offset rather than by index.
+    pub offsets: Vec<u32>,
+    pub code_length: u32,
     pub exception_table: Vec<ExceptionTableEntry>,
     pub attributes: Vec<Attribute>,
+    /// Built from the debug attributes the first time it is needed, since
+    /// most methods are never asked for line numbers or locals.
+    debug_index: OnceCell<DebugIndex>,
+}
+
+#[derive(Clone, Debug)]
+struct DebugIndex {
+    line_numbers: Vec<LineNumber>,
+    local_variables: Vec<DebugLocal>,
+}
+
+/// A local variable as described by the debug attributes.
+#[derive(Clone, Debug)]
+pub struct DebugLocal {
+    pub start_pc: u16,
+    pub length: u16,
+    pub index: u16,
+    pub name: Arc<str>,
+    pub descriptor: Arc<str>,
+    pub signature: Option<Arc<str>>,
+}
+
+impl DebugLocal {
+    /// Whether the variable has a value at byte offset `offset`.
+    pub fn is_live_at(&self, offset: u32) -> bool {
+        let start = self.start_pc as u32;
+        (start..start + self.length as u32).contains(&offset)
+    }
 }
 
 impl Code {
-    pub fn from_bytes(bytes: &mut Bytes, constant_pool: &[Constant]) -> Self {
-        let max_stack = bytes.get_u16();
-        let max_locals = bytes.get_u16();
-        let code_length = bytes.get_u32() as usize;
-        let mut code_bytes = bytes.copy_to_bytes(code_length);
-        let exception_table = parse_exception_table(bytes);
-        let attributes = Attribute::parse_list(bytes, constant_pool);
+    pub fn from_bytes(reader: &mut Reader, constant_pool: &Arc<ConstantPool>, version: ClassVersion) -> ParseResult<Self> {
+        reader.within("Code", |reader| {
+            let max_stack = reader.u16()?;
+            let max_locals = reader.u16()?;
+            let code_length = reader.u32()? as usize;
+            let mut code_reader = reader.sub(code_length)?;
+            let exception_table = parse_exception_table(reader)?;
+            let attributes = Attribute::parse_list(reader, constant_pool, version)?;
+            reader.expect_end()?;
 
+            let code_start = code_reader.offset();
+            let mut code = Vec::new();
+            let mut offsets = Vec::new();
+
+            while !code_reader.is_empty() {
+                let pc = (code_reader.offset() - code_start) as u32;
+                offsets.push(pc);
+                code.push(Instr::from_bytes(&mut code_reader, pc)?);
+            }
+
+            Ok(Self {
+                max_stack,
+                max_locals,
+                code,
+                offsets,
+                code_length: code_length as u32,
+                exception_table,
+                attributes,
+                debug_index: OnceCell::new(),
+            })
+        })
+    }
+
+    /// The byte offset of the instruction at `index`.
+    pub fn offset(&self, index: usize) -> u32 {
+        self.offsets[index]
+    }
+
+    /// The index of the instruction starting at byte offset `offset`, or
+    /// `None` if no instruction starts there.
+    pub fn index_of(&self, offset: u32) -> Option<usize> {
+        self.offsets.binary_search(&offset).ok()
+    }
+
+    /// Like `index_of`, but also accepts `code_length`, the exclusive end of
+    /// the code array used by ranges such as `end_pc`.
+    pub fn end_index_of(&self, offset: u32) -> Option<usize> {
+        if offset == self.code_length {
+            return Some(self.code.len());
+        }
+        self.index_of(offset)
+    }
+
+    /// The index of the instruction a branch at `index` with the relative
+    /// byte offset `delta` jumps to.
+    pub fn branch_target(&self, index: usize, delta: i32) -> Option<usize> {
+        let target = self.offset(index).checked_add_signed(delta)?;
+        self.index_of(target)
+    }
+
+    fn debug_index(&self) -> &DebugIndex {
+        self.debug_index.get_or_init(|| DebugIndex::new(&self.attributes))
+    }
+
+    /// Every `LineNumberTable` entry, sorted by `start_pc`.
+    pub fn line_numbers(&self) -> &[LineNumber] {
+        &self.debug_index().line_numbers
+    }
+
+    /// Every `LocalVariableTable` entry, with its generic signature from the
+    /// `LocalVariableTypeTable` if there is one.
+    pub fn local_variables(&self) -> &[DebugLocal] {
+        &self.debug_index().local_variables
+    }
+
+    /// The source line of the instruction at byte offset `offset`, if the
+    /// code has a `LineNumberTable` covering it.
+    pub fn line_number(&self, offset: u32) -> Option<u16> {
+        let line_numbers = self.line_numbers();
+        let end = line_numbers.partition_point(|line| line.start_pc as u32 <= offset);
+        line_numbers[..end].last().map(|line| line.line_number)
+    }
+
+    /// The local variables that have a value at byte offset `offset`.
+    pub fn live_locals(&self, offset: u32) -> impl Iterator<Item = &DebugLocal> {
+        self.local_variables().iter().filter(move |local| local.is_live_at(offset))
+    }
+
+    /// The local variable in slot `index` at byte offset `offset`, if the
+    /// debug attributes describe one.
+    pub fn local_variable(&self, offset: u32, index: u16) -> Option<&DebugLocal> {
+        self.live_locals(offset).find(|local| local.index == index)
+    }
+
+    /// The frames of the `StackMapTable` attribute, if there is one.
+    pub fn stack_map_table(&self) -> Option<&[StackMapFrame]> {
+        self.attributes.iter().find_map(|attr| match &attr.kind() {
+            AttributeKind::StackMapTable(frames) => Some(frames.as_slice()),
+            _ => None,
+        })
+    }
+
+    /// The declared frames of the `StackMapTable`, each with the full set of
+    /// locals and stack at its offset, given the implicit frame `initial`
+    /// at the start of the method. Empty if there is no `StackMapTable`.
+    pub fn stack_map_frames(&self, initial: &Frame) -> Result<Vec<Frame>> {
+        let frames = stack_map::expand(initial, self.stack_map_table().unwrap_or_default())?;
+        if let Some(index) = frames.iter().position(|frame| self.index_of(frame.offset).is_none()) {
+            return Err(Error::InvalidStackMapFrame { index });
+        }
+        Ok(frames)
+    }
+
+    /// Type annotations on types used in the method body, such as local
+    /// variables, casts and `new` expressions.
+    pub fn type_annotations(&self) -> impl Iterator<Item = &TypeAnnotation> {
+        annotation::type_annotations(&self.attributes)
+    }
+
+    /// Encodes the instructions into a code array.
+    pub fn code_bytes(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(self.code_length as usize);
+        for instr in &self.code {
+            instr.to_bytes(buf.len() as u32, &mut buf);
+        }
+        buf
+    }
+
+    /// Encodes the body of the `Code` attribute. Its attributes are written
+    /// as they are, so they must belong to the same class.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let code = self.code_bytes();
+        let mut buf = Vec::new();
+        buf.put_u16(self.max_stack);
+        buf.put_u16(self.max_locals);
+        buf.put_u32(code.len() as u32);
+        buf.put_slice(&code);
+        buf.put_u16(self.exception_table.len() as u16);
+        for entry in &self.exception_table {
+            entry.to_bytes(&mut buf);
+        }
+        buf.put_u16(self.attributes.len() as u16);
+        for attribute in &self.attributes {
+            attribute.put(&mut buf);
+        }
+        buf
+    }
+
+    /// Rebuilds the code by replacing each instruction with the instructions
+    /// `f` returns for it, then lays out the result again.
+    ///
+    /// Branch offsets in the returned instructions are relative to the byte
+    /// offset of the *original* instruction, so untouched branches keep their
+    /// meaning and inserted ones can target any original instruction. A
+    /// branch to an original instruction lands on the first instruction that
+    /// replaced it. `goto` and `jsr` are widened as needed, conditional
+    /// branches that no longer reach are turned into a branch over a
+    /// `goto_w`, and switch padding is recomputed.
+    ///
+    /// The exception table, `StackMapTable`, `LineNumberTable`,
+    /// `LocalVariableTable`, `LocalVariableTypeTable` and the targets of
+    /// type annotations on instructions and locals are remapped. Frames
+    /// are moved, not recomputed, so instructions that change the types of
+    /// locals or the stack at a frame need the caller to fix the frame up.
+    /// `max_stack` and `max_locals` are left for the caller to adjust. Code
+    /// with a `StackMapTable` cannot have its conditional branches expanded,
+    /// as that would need new frames, and fails with `BranchOutOfRange`.
+    pub fn rewrite<F>(&mut self, mut f: F) -> Result<()>
+    where
+        F: FnMut(usize, &Instr) -> Vec<Instr>,
+    {
         let mut code = Vec::new();
+        let mut origins = Vec::new();
+        let mut starts = Vec::with_capacity(self.code.len() + 1);
+        for (index, instr) in self.code.iter().enumerate() {
+            starts.push(code.len());
+            for new in f(index, instr) {
+                code.push(new);
+                origins.push(self.offsets[index]);
+            }
+        }
+        starts.push(code.len());
+
+        let mut targets = code.iter()
+            .zip(&origins)
+            .map(|(instr, origin)| instr.branch_deltas()
+                .into_iter()
+                .map(|delta| origin.checked_add_signed(delta)
+                    .and_then(|target| self.index_of(target))
+                    .map(|index| starts[index])
+                    .ok_or(Error::InvalidBranch))
+                .collect::<Result<Vec<_>>>())
+            .collect::<Result<Vec<_>>>()?;
+
+        // Widening a branch moves everything after it, which can push
+        // other branches out of range, so repeat until nothing changes.
+        // Instructions are only ever widened, so this terminates.
+        //
+        // A conditional branch has no wide form, so one that is out of range
+        // becomes the opposite condition jumping over a `goto_w` to the
+        // original target.
+        let offsets = loop {
+            let mut offsets = Vec::with_capacity(code.len() + 1);
+            let mut pc = 0;
+            for instr in &code {
+                offsets.push(pc);
+                pc += instr.len(pc);
+            }
+            offsets.push(pc);
+
+            let mut widened = false;
+            let mut out_of_range = Vec::new();
+            for (index, instr) in code.iter_mut().enumerate() {
+                if targets[index].is_empty() {
+                    continue;
+                }
+                let deltas = targets[index].iter()
+                    .map(|&target| offsets[target] as i32 - offsets[index] as i32)
+                    .collect::<Vec<_>>();
+                let opcode = instr.opcode();
+                match instr.set_branch_deltas(&deltas) {
+                    Err(Error::BranchOutOfRange) => out_of_range.push(index),
+                    result => result?,
+                }
+                widened |= instr.opcode() != opcode;
+            }
+
+            if !out_of_range.is_empty() && self.stack_map_table().is_some() {
+                // The instruction after the `goto_w` would need a frame of
+                // its own, which cannot be made up from the existing ones.
+                return Err(Error::BranchOutOfRange);
+            }
+            // From the end, so that the indices still to be expanded stay put.
+            for &index in out_of_range.iter().rev() {
+                let shift = |target: &mut usize| if *target > index {
+                    *target += 1;
+                };
+                targets.iter_mut().flatten().for_each(shift);
+                starts.iter_mut().for_each(shift);
+                let target = targets[index][0];
+                code[index] = code[index].negated().ok_or(Error::BranchOutOfRange)?;
+                targets[index] = vec![index + 2];
+                code.insert(index + 1, Instr::GotoW(0));
+                targets.insert(index + 1, vec![target]);
+            }
+
+            if !widened && out_of_range.is_empty() {
+                break offsets;
+            }
+        };
+
+        let code_length = *offsets.last().unwrap();
+        if code_length > u16::MAX as u32 {
+            return Err(Error::CodeTooLarge);
+        }
+
+        // Offsets are taken as u32, since the end of a range such as
+        // `start_pc + length` may not fit in a u16 in a malformed class.
+        let remap = |offset: u32| -> Result<u16> {
+            let index = self.end_index_of(offset).ok_or(Error::InvalidBranch)?;
+            Ok(offsets[starts[index]] as u16)
+        };
+
+        let mut exception_table = self.exception_table.clone();
+        for entry in &mut exception_table {
+            entry.start_pc = remap(entry.start_pc as u32)?;
+            entry.end_pc = remap(entry.end_pc as u32)?;
+            entry.handler_pc = remap(entry.handler_pc as u32)?;
+        }
+
+        let mut attributes = self.attributes.clone();
+        for attribute in &mut attributes {
+            let mut info = BytesMut::from(&attribute.info()[..]);
+            match attribute.kind() {
+                AttributeKind::StackMapTable(_) => {
+                    info = BytesMut::from(&stack_map::remap(attribute.info(), remap)?[..]);
+                }
+                AttributeKind::RuntimeVisibleTypeAnnotations(_) | AttributeKind::RuntimeInvisibleTypeAnnotations(_) => {
+                    let remapped = annotation::remap_type_annotations(attribute.info(), attribute.constant_pool(), remap)?;
+                    info = BytesMut::from(&remapped[..]);
+                }
+                AttributeKind::LineNumberTable(lines) => {
+                    for (n, line) in lines.iter().enumerate() {
+                        patch_u16(&mut info, 2 + n * 4, remap(line.start_pc as u32)?);
+                    }
+                }
+                AttributeKind::LocalVariableTable(locals) | AttributeKind::LocalVariableTypeTable(locals) => {
+                    for (n, local) in locals.iter().enumerate() {
+                        let end_pc = remap(local.start_pc as u32 + local.length as u32)?;
+                        let start_pc = remap(local.start_pc as u32)?;
+                        patch_u16(&mut info, 2 + n * 10, start_pc);
+                        patch_u16(&mut info, 2 + n * 10 + 2, end_pc - start_pc);
+                    }
+                }
+                _ => continue,
+            }
+            attribute.set_info(info.freeze());
+        }
 
-        while !code_bytes.is_empty() {
-            code.push(Instr::from_bytes(&mut code_bytes));
+        self.code = code;
+        self.offsets = offsets[..offsets.len() - 1].to_vec();
+        self.code_length = code_length;
+        self.exception_table = exception_table;
+        self.attributes = attributes;
+        self.debug_index = OnceCell::new();
+        Ok(())
+    }
+}
+
+impl DebugIndex {
+    /// Collects the entries of the debug attributes of a `Code` attribute.
+    fn new(attributes: &[Attribute]) -> Self {
+        let mut line_numbers = Vec::new();
+        let mut local_variables = Vec::new();
+        let mut signatures = Vec::new();
+        for attribute in attributes {
+            match attribute.kind() {
+                AttributeKind::LineNumberTable(lines) => line_numbers.extend_from_slice(lines),
+                AttributeKind::LocalVariableTable(locals) => local_variables.extend(locals.iter().map(|local| DebugLocal {
+                    start_pc: local.start_pc,
+                    length: local.length,
+                    index: local.index,
+                    name: local.name.clone(),
+                    descriptor: local.descriptor.clone(),
+                    signature: None,
+                })),
+                AttributeKind::LocalVariableTypeTable(locals) => signatures.extend(locals),
+                _ => {}
+            }
         }
+        line_numbers.sort_by_key(|line| line.start_pc);
 
-        Self {
-            max_stack,
-            max_locals,
-            code,
-            exception_table,
-            attributes,
+        for typed in signatures {
+            let local = local_variables.iter_mut().find(|local| {
+                local.start_pc == typed.start_pc && local.length == typed.length && local.index == typed.index
+            });
+            if let Some(local) = local {
+                local.signature = Some(typed.descriptor.clone());
+            }
         }
+        Self { line_numbers, local_variables }
     }
 }
 
+fn patch_u16(info: &mut [u8], at: usize, value: u16) {
+    info[at..at + 2].copy_from_slice(&value.to_be_bytes());
+}
+
 #[derive(Clone, Debug)]
 pub enum Instr {
     Aaload,
@@ -208,9 +572,9 @@ pub enum Instr {
     Lload3,
     Lmul,
     Lneg,
+    /// `npairs` is the length of `match_offsets`, so it is not kept.
     Lookupswitch {
         default: i32,
-        npairs: u32,
         match_offsets: Vec<(i32, i32)>,
     },
     Lor,
@@ -248,27 +612,464 @@ pub enum Instr {
         high: i32,
         jump_offsets: Vec<i32>,
     },
-    Wide {
-        instr: Box<Instr>,
-        index: u16,
-    },
+    Wide(WideInstr),
+}
+
+/// An instruction modified by a `wide` prefix, which widens its local
+/// variable index (and for `iinc`, its constant) to 16 bits.
+#[derive(Clone, Debug)]
+pub enum WideInstr {
+    Iload(u16),
+    Lload(u16),
+    Fload(u16),
+    Dload(u16),
+    Aload(u16),
+    Istore(u16),
+    Lstore(u16),
+    Fstore(u16),
+    Dstore(u16),
+    Astore(u16),
+    Ret(u16),
+    Iinc(u16, i16),
 }
 
 impl Instr {
-    pub fn from_bytes(bytes: &mut Bytes) -> Self {
-        match bytes.get_u8() {
+    /// The opcode byte this instruction is encoded with.
+    pub fn opcode(&self) -> u8 {
+        match self {
+            Self::Aaload => 0x32,
+            Self::Aastore => 0x53,
+            Self::AconstNull => 0x01,
+            Self::Aload(..) => 0x19,
+            Self::Aload0 => 0x2a,
+            Self::Aload1 => 0x2b,
+            Self::Aload2 => 0x2c,
+            Self::Aload3 => 0x2d,
+            Self::Anewarray(..) => 0xbd,
+            Self::Areturn => 0xb0,
+            Self::Arraylength => 0xbe,
+            Self::Astore(..) => 0x3a,
+            Self::Astore0 => 0x4b,
+            Self::Astore1 => 0x4c,
+            Self::Astore2 => 0x4d,
+            Self::Astore3 => 0x4e,
+            Self::Athrow => 0xbf,
+            Self::Baload => 0x33,
+            Self::Bastore => 0x54,
+            Self::Bipush(..) => 0x10,
+            Self::Caload => 0x34,
+            Self::Castore => 0x55,
+            Self::Checkcast(..) => 0xc0,
+            Self::D2f => 0x90,
+            Self::D2i => 0x8e,
+            Self::D2l => 0x8f,
+            Self::Dadd => 0x63,
+            Self::Daload => 0x31,
+            Self::Dastore => 0x52,
+            Self::Dcmpg => 0x98,
+            Self::Dcmpl => 0x97,
+            Self::Dconst0 => 0x0e,
+            Self::Dconst1 => 0x0f,
+            Self::Ddiv => 0x6f,
+            Self::Dload(..) => 0x18,
+            Self::Dload0 => 0x26,
+            Self::Dload1 => 0x27,
+            Self::Dload2 => 0x28,
+            Self::Dload3 => 0x29,
+            Self::Dmul => 0x6b,
+            Self::Dneg => 0x77,
+            Self::Drem => 0x73,
+            Self::Dreturn => 0xaf,
+            Self::Dstore(..) => 0x39,
+            Self::Dstore0 => 0x47,
+            Self::Dstore1 => 0x48,
+            Self::Dstore2 => 0x49,
+            Self::Dstore3 => 0x4a,
+            Self::Dsub => 0x67,
+            Self::Dup => 0x59,
+            Self::DupX1 => 0x5a,
+            Self::DupX2 => 0x5b,
+            Self::Dup2 => 0x5c,
+            Self::Dup2X1 => 0x5d,
+            Self::Dup2X2 => 0x5e,
+            Self::F2d => 0x8d,
+            Self::F2i => 0x8b,
+            Self::F2l => 0x8c,
+            Self::Fadd => 0x62,
+            Self::Faload => 0x30,
+            Self::Fastore => 0x51,
+            Self::Fcmpg => 0x96,
+            Self::Fcmpl => 0x95,
+            Self::Fconst0 => 0x0b,
+            Self::Fconst1 => 0x0c,
+            Self::Fconst2 => 0x0d,
+            Self::Fdiv => 0x6e,
+            Self::Fload(..) => 0x17,
+            Self::Fload0 => 0x22,
+            Self::Fload1 => 0x23,
+            Self::Fload2 => 0x24,
+            Self::Fload3 => 0x25,
+            Self::Fmul => 0x6a,
+            Self::Fneg => 0x76,
+            Self::Frem => 0x72,
+            Self::Freturn => 0xae,
+            Self::Fstore(..) => 0x38,
+            Self::Fstore0 => 0x43,
+            Self::Fstore1 => 0x44,
+            Self::Fstore2 => 0x45,
+            Self::Fstore3 => 0x46,
+            Self::Fsub => 0x66,
+            Self::Getfield(..) => 0xb4,
+            Self::Getstatic(..) => 0xb2,
+            Self::Goto(..) => 0xa7,
+            Self::GotoW(..) => 0xc8,
+            Self::I2b => 0x91,
+            Self::I2c => 0x92,
+            Self::I2d => 0x87,
+            Self::I2f => 0x86,
+            Self::I2l => 0x85,
+            Self::I2s => 0x93,
+            Self::Iadd => 0x60,
+            Self::Iaload => 0x2e,
+            Self::Iand => 0x7e,
+            Self::Iastore => 0x4f,
+            Self::IconstM1 => 0x02,
+            Self::Iconst0 => 0x03,
+            Self::Iconst1 => 0x04,
+            Self::Iconst2 => 0x05,
+            Self::Iconst3 => 0x06,
+            Self::Iconst4 => 0x07,
+            Self::Iconst5 => 0x08,
+            Self::Idiv => 0x6c,
+            Self::IfAcmpeq(..) => 0xa5,
+            Self::IfAcmpne(..) => 0xa6,
+            Self::IfIcmpeq(..) => 0x9f,
+            Self::IfIcmpne(..) => 0xa0,
+            Self::IfIcmplt(..) => 0xa1,
+            Self::IfIcmpge(..) => 0xa2,
+            Self::IfIcmpgt(..) => 0xa3,
+            Self::IfIcmple(..) => 0xa4,
+            Self::Ifeq(..) => 0x99,
+            Self::Ifne(..) => 0x9a,
+            Self::Iflt(..) => 0x9b,
+            Self::Ifge(..) => 0x9c,
+            Self::Ifgt(..) => 0x9d,
+            Self::Ifle(..) => 0x9e,
+            Self::Ifnonnull(..) => 0xc7,
+            Self::Ifnull(..) => 0xc6,
+            Self::Iinc(..) => 0x84,
+            Self::Iload(..) => 0x15,
+            Self::Iload0 => 0x1a,
+            Self::Iload1 => 0x1b,
+            Self::Iload2 => 0x1c,
+            Self::Iload3 => 0x1d,
+            Self::Imul => 0x68,
+            Self::Ineg => 0x74,
+            Self::Instanceof(..) => 0xc1,
+            Self::Invokedynamic(..) => 0xba,
+            Self::Invokeinterface(..) => 0xb9,
+            Self::Invokespecial(..) => 0xb7,
+            Self::Invokestatic(..) => 0xb8,
+            Self::Invokevirtual(..) => 0xb6,
+            Self::Ior => 0x80,
+            Self::Irem => 0x70,
+            Self::Ireturn => 0xac,
+            Self::Ishl => 0x78,
+            Self::Ishr => 0x7a,
+            Self::Istore(..) => 0x36,
+            Self::Istore0 => 0x3b,
+            Self::Istore1 => 0x3c,
+            Self::Istore2 => 0x3d,
+            Self::Istore3 => 0x3e,
+            Self::Isub => 0x64,
+            Self::Iushr => 0x7c,
+            Self::Ixor => 0x82,
+            Self::Jsr(..) => 0xa8,
+            Self::JsrW(..) => 0xc9,
+            Self::L2d => 0x8a,
+            Self::L2f => 0x89,
+            Self::L2i => 0x88,
+            Self::Ladd => 0x61,
+            Self::Laload => 0x2f,
+            Self::Land => 0x7f,
+            Self::Lastore => 0x50,
+            Self::Lcmp => 0x94,
+            Self::Lconst0 => 0x09,
+            Self::Lconst1 => 0x0a,
+            Self::Ldc(..) => 0x12,
+            Self::LdcW(..) => 0x13,
+            Self::Ldc2W(..) => 0x14,
+            Self::Ldiv => 0x6d,
+            Self::Lload(..) => 0x16,
+            Self::Lload0 => 0x1e,
+            Self::Lload1 => 0x1f,
+            Self::Lload2 => 0x20,
+            Self::Lload3 => 0x21,
+            Self::Lmul => 0x69,
+            Self::Lneg => 0x75,
+            Self::Lookupswitch { .. } => 0xab,
+            Self::Lor => 0x81,
+            Self::Lrem => 0x71,
+            Self::Lreturn => 0xad,
+            Self::Lshl => 0x79,
+            Self::Lshr => 0x7b,
+            Self::Lstore(..) => 0x37,
+            Self::Lstore0 => 0x3f,
+            Self::Lstore1 => 0x40,
+            Self::Lstore2 => 0x41,
+            Self::Lstore3 => 0x42,
+            Self::Lsub => 0x65,
+            Self::Lushr => 0x7d,
+            Self::Lxor => 0x83,
+            Self::Monitorenter => 0xc2,
+            Self::Monitorexit => 0xc3,
+            Self::Multianewarray(..) => 0xc5,
+            Self::New(..) => 0xbb,
+            Self::Newarray(..) => 0xbc,
+            Self::Nop => 0x00,
+            Self::Pop => 0x57,
+            Self::Pop2 => 0x58,
+            Self::Putfield(..) => 0xb5,
+            Self::Putstatic(..) => 0xb3,
+            Self::Ret(..) => 0xa9,
+            Self::Return => 0xb1,
+            Self::Saload => 0x35,
+            Self::Sastore => 0x56,
+            Self::Sipush(..) => 0x11,
+            Self::Swap => 0x5f,
+            Self::Tableswitch { .. } => 0xaa,
+            Self::Wide(..) => 0xc4,
+        }
+    }
+
+    /// Appends the encoding of this instruction, located at byte offset `pc`
+    /// of the code array, to `buf`.
+    pub fn to_bytes(&self, pc: u32, buf: &mut Vec<u8>) {
+        buf.put_u8(self.opcode());
+        match self {
+            Self::Aload(index) | Self::Astore(index)
+            | Self::Dload(index) | Self::Dstore(index)
+            | Self::Fload(index) | Self::Fstore(index)
+            | Self::Iload(index) | Self::Istore(index)
+            | Self::Lload(index) | Self::Lstore(index)
+            | Self::Ldc(index) | Self::Ret(index) | Self::Newarray(index) => buf.put_u8(*index),
+            Self::Bipush(value) => buf.put_i8(*value),
+            Self::Anewarray(index) | Self::Checkcast(index)
+            | Self::Getfield(index) | Self::Getstatic(index)
+            | Self::Putfield(index) | Self::Putstatic(index)
+            | Self::Instanceof(index) | Self::New(index)
+            | Self::Invokespecial(index) | Self::Invokestatic(index) | Self::Invokevirtual(index)
+            | Self::LdcW(index) | Self::Ldc2W(index) => buf.put_u16(*index),
+            Self::Sipush(value) => buf.put_i16(*value),
+            Self::GotoW(delta) | Self::JsrW(delta) => buf.put_i32(*delta),
+            Self::Iinc(index, value) => {
+                buf.put_u8(*index);
+                buf.put_i8(*value);
+            }
+            Self::Invokedynamic(index) => {
+                buf.put_u16(*index);
+                buf.put_u16(0);
+            }
+            Self::Invokeinterface(index, count) => {
+                buf.put_u16(*index);
+                buf.put_u8(*count);
+                buf.put_u8(0);
+            }
+            Self::Multianewarray(index, dimensions) => {
+                buf.put_u16(*index);
+                buf.put_u8(*dimensions);
+            }
+            Self::Lookupswitch { default, match_offsets, .. } => {
+                put_switch_padding(buf, pc);
+                buf.put_i32(*default);
+                buf.put_i32(match_offsets.len() as i32);
+                for (key, delta) in match_offsets {
+                    buf.put_i32(*key);
+                    buf.put_i32(*delta);
+                }
+            }
+            Self::Tableswitch { default, low, high, jump_offsets } => {
+                put_switch_padding(buf, pc);
+                buf.put_i32(*default);
+                buf.put_i32(*low);
+                buf.put_i32(*high);
+                for delta in jump_offsets {
+                    buf.put_i32(*delta);
+                }
+            }
+            Self::Wide(instr) => instr.to_bytes(buf),
+            _ => {
+                if let Some(delta) = self.branch_deltas().first() {
+                    buf.put_i16(*delta as i16);
+                }
+            }
+        }
+    }
+
+    /// The encoded length of this instruction at byte offset `pc`, which
+    /// only matters for the padding of `tableswitch` and `lookupswitch`.
+    pub fn len(&self, pc: u32) -> u32 {
+        match self {
+            Self::Aload(_) | Self::Astore(_)
+            | Self::Dload(_) | Self::Dstore(_)
+            | Self::Fload(_) | Self::Fstore(_)
+            | Self::Iload(_) | Self::Istore(_)
+            | Self::Lload(_) | Self::Lstore(_)
+            | Self::Ldc(_) | Self::Ret(_) | Self::Newarray(_) | Self::Bipush(_) => 2,
+            Self::Anewarray(_) | Self::Checkcast(_)
+            | Self::Getfield(_) | Self::Getstatic(_)
+            | Self::Putfield(_) | Self::Putstatic(_)
+            | Self::Instanceof(_) | Self::New(_)
+            | Self::Invokespecial(_) | Self::Invokestatic(_) | Self::Invokevirtual(_)
+            | Self::LdcW(_) | Self::Ldc2W(_) | Self::Sipush(_) | Self::Iinc(..)
+            | Self::Goto(_) | Self::Jsr(_)
+            | Self::IfAcmpeq(_) | Self::IfAcmpne(_)
+            | Self::IfIcmpeq(_) | Self::IfIcmpne(_)
+            | Self::IfIcmplt(_) | Self::IfIcmpge(_)
+            | Self::IfIcmpgt(_) | Self::IfIcmple(_)
+            | Self::Ifeq(_) | Self::Ifne(_)
+            | Self::Iflt(_) | Self::Ifge(_)
+            | Self::Ifgt(_) | Self::Ifle(_)
+            | Self::Ifnonnull(_) | Self::Ifnull(_) => 3,
+            Self::Multianewarray(..) => 4,
+            Self::GotoW(_) | Self::JsrW(_) | Self::Invokedynamic(_) | Self::Invokeinterface(..) => 5,
+            Self::Lookupswitch { match_offsets, .. } => 1 + switch_padding(pc) + 8 + 8 * match_offsets.len() as u32,
+            Self::Tableswitch { jump_offsets, .. } => 1 + switch_padding(pc) + 12 + 4 * jump_offsets.len() as u32,
+            Self::Wide(WideInstr::Iinc(..)) => 6,
+            Self::Wide(_) => 4,
+            _ => 1,
+        }
+    }
+
+    /// The relative byte offsets this instruction can transfer control to,
+    /// other than falling through to the next instruction.
+    pub fn branch_deltas(&self) -> Vec<i32> {
+        match self {
+            Self::Goto(delta) | Self::Jsr(delta)
+            | Self::IfAcmpeq(delta) | Self::IfAcmpne(delta)
+            | Self::IfIcmpeq(delta) | Self::IfIcmpne(delta)
+            | Self::IfIcmplt(delta) | Self::IfIcmpge(delta)
+            | Self::IfIcmpgt(delta) | Self::IfIcmple(delta)
+            | Self::Ifeq(delta) | Self::Ifne(delta)
+            | Self::Iflt(delta) | Self::Ifge(delta)
+            | Self::Ifgt(delta) | Self::Ifle(delta)
+            | Self::Ifnonnull(delta) | Self::Ifnull(delta) => vec![*delta as i32],
+            Self::GotoW(delta) | Self::JsrW(delta) => vec![*delta],
+            Self::Lookupswitch { default, match_offsets, .. } => std::iter::once(*default)
+                .chain(match_offsets.iter().map(|(_, delta)| *delta))
+                .collect(),
+            Self::Tableswitch { default, jump_offsets, .. } => std::iter::once(*default)
+                .chain(jump_offsets.iter().copied())
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// The number of offsets `branch_deltas` returns, without collecting
+    /// them.
+    pub fn branch_count(&self) -> usize {
+        match self {
+            Self::Lookupswitch { match_offsets, .. } => match_offsets.len() + 1,
+            Self::Tableswitch { jump_offsets, .. } => jump_offsets.len() + 1,
+            Self::Goto(_) | Self::Jsr(_) | Self::GotoW(_) | Self::JsrW(_)
+            | Self::IfAcmpeq(_) | Self::IfAcmpne(_)
+            | Self::IfIcmpeq(_) | Self::IfIcmpne(_)
+            | Self::IfIcmplt(_) | Self::IfIcmpge(_)
+            | Self::IfIcmpgt(_) | Self::IfIcmple(_)
+            | Self::Ifeq(_) | Self::Ifne(_)
+            | Self::Iflt(_) | Self::Ifge(_)
+            | Self::Ifgt(_) | Self::Ifle(_)
+            | Self::Ifnonnull(_) | Self::Ifnull(_) => 1,
+            _ => 0,
+        }
+    }
+
+    /// The conditional branch that jumps when this one falls through, with
+    /// a zero offset, or `None` if this is not a conditional branch.
+    pub fn negated(&self) -> Option<Self> {
+        Some(match self {
+            Self::IfAcmpeq(_) => Self::IfAcmpne(0),
+            Self::IfAcmpne(_) => Self::IfAcmpeq(0),
+            Self::IfIcmpeq(_) => Self::IfIcmpne(0),
+            Self::IfIcmpne(_) => Self::IfIcmpeq(0),
+            Self::IfIcmplt(_) => Self::IfIcmpge(0),
+            Self::IfIcmpge(_) => Self::IfIcmplt(0),
+            Self::IfIcmpgt(_) => Self::IfIcmple(0),
+            Self::IfIcmple(_) => Self::IfIcmpgt(0),
+            Self::Ifeq(_) => Self::Ifne(0),
+            Self::Ifne(_) => Self::Ifeq(0),
+            Self::Iflt(_) => Self::Ifge(0),
+            Self::Ifge(_) => Self::Iflt(0),
+            Self::Ifgt(_) => Self::Ifle(0),
+            Self::Ifle(_) => Self::Ifgt(0),
+            Self::Ifnonnull(_) => Self::Ifnull(0),
+            Self::Ifnull(_) => Self::Ifnonnull(0),
+            _ => return None,
+        })
+    }
+
+    /// Replaces the branch offsets of this instruction, in the order
+    /// returned by `branch_deltas`. `goto` and `jsr` are widened to `goto_w`
+    /// and `jsr_w` when the offset does not fit in 16 bits; a conditional
+    /// branch that does not fit is an error, which `Code::rewrite` handles
+    /// by branching over a `goto_w`.
+    pub fn set_branch_deltas(&mut self, deltas: &[i32]) -> Result<()> {
+        let expected = self.branch_count();
+        if deltas.len() != expected {
+            return Err(Error::WrongBranchCount { expected, actual: deltas.len() });
+        }
+        match self {
+            Self::Goto(_) => *self = match i16::try_from(deltas[0]) {
+                Ok(delta) => Self::Goto(delta),
+                Err(_) => Self::GotoW(deltas[0]),
+            },
+            Self::Jsr(_) => *self = match i16::try_from(deltas[0]) {
+                Ok(delta) => Self::Jsr(delta),
+                Err(_) => Self::JsrW(deltas[0]),
+            },
+            Self::GotoW(delta) | Self::JsrW(delta) => *delta = deltas[0],
+            Self::IfAcmpeq(delta) | Self::IfAcmpne(delta)
+            | Self::IfIcmpeq(delta) | Self::IfIcmpne(delta)
+            | Self::IfIcmplt(delta) | Self::IfIcmpge(delta)
+            | Self::IfIcmpgt(delta) | Self::IfIcmple(delta)
+            | Self::Ifeq(delta) | Self::Ifne(delta)
+            | Self::Iflt(delta) | Self::Ifge(delta)
+            | Self::Ifgt(delta) | Self::Ifle(delta)
+            | Self::Ifnonnull(delta) | Self::Ifnull(delta) => {
+                *delta = i16::try_from(deltas[0]).map_err(|_| Error::BranchOutOfRange)?;
+            }
+            Self::Lookupswitch { default, match_offsets, .. } => {
+                *default = deltas[0];
+                for ((_, delta), new) in match_offsets.iter_mut().zip(&deltas[1..]) {
+                    *delta = *new;
+                }
+            }
+            Self::Tableswitch { default, jump_offsets, .. } => {
+                *default = deltas[0];
+                jump_offsets.copy_from_slice(&deltas[1..]);
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Decodes the instruction at byte offset `pc` of the code array, which
+    /// determines the alignment padding of `tableswitch` and `lookupswitch`.
+    pub fn from_bytes(reader: &mut Reader, pc: u32) -> ParseResult<Self> {
+        let offset = reader.offset();
+        let instr = match reader.u8()? {
             0x32 => Self::Aaload,
             0x53 => Self::Aastore,
             0x01 => Self::AconstNull,
-            0x19 => Self::Aload(bytes.get_u8()),
+            0x19 => Self::Aload(reader.u8()?),
             0x2a => Self::Aload0,
             0x2b => Self::Aload1,
             0x2c => Self::Aload2,
             0x2d => Self::Aload3,
-            0xbd => Self::Anewarray(bytes.get_u16()),
+            0xbd => Self::Anewarray(reader.u16()?),
             0xb0 => Self::Areturn,
             0xbe => Self::Arraylength,
-            0x3a => Self::Astore(bytes.get_u8()),
+            0x3a => Self::Astore(reader.u8()?),
             0x4b => Self::Astore0,
             0x4c => Self::Astore1,
             0x4d => Self::Astore2,
@@ -276,12 +1077,12 @@ impl Instr {
             0xbf => Self::Athrow,
             0x33 => Self::Baload,
             0x54 => Self::Bastore,
-            0x10 => Self::Bipush(bytes.get_i8()),
+            0x10 => Self::Bipush(reader.i8()?),
             0x34 => Self::Caload,
             0x55 => Self::Castore,
-            0xc0 => Self::Checkcast(bytes.get_u16()),
-            0x8e => Self::D2f,
-            0x8d => Self::D2i,
+            0xc0 => Self::Checkcast(reader.u16()?),
+            0x90 => Self::D2f,
+            0x8e => Self::D2i,
             0x8f => Self::D2l,
             0x63 => Self::Dadd,
             0x31 => Self::Daload,
@@ -291,7 +1092,7 @@ impl Instr {
             0x0e => Self::Dconst0,
             0x0f => Self::Dconst1,
             0x6f => Self::Ddiv,
-            0x18 => Self::Dload(bytes.get_u8()),
+            0x18 => Self::Dload(reader.u8()?),
             0x26 => Self::Dload0,
             0x27 => Self::Dload1,
             0x28 => Self::Dload2,
@@ -300,7 +1101,7 @@ impl Instr {
             0x77 => Self::Dneg,
             0x73 => Self::Drem,
             0xaf => Self::Dreturn,
-            0x39 => Self::Dstore(bytes.get_u8()),
+            0x39 => Self::Dstore(reader.u8()?),
             0x47 => Self::Dstore0,
             0x48 => Self::Dstore1,
             0x49 => Self::Dstore2,
@@ -312,7 +1113,7 @@ impl Instr {
             0x5c => Self::Dup2,
             0x5d => Self::Dup2X1,
             0x5e => Self::Dup2X2,
-            0x8e => Self::F2d,
+            0x8d => Self::F2d,
             0x8b => Self::F2i,
             0x8c => Self::F2l,
             0x62 => Self::Fadd,
@@ -324,7 +1125,7 @@ impl Instr {
             0x0c => Self::Fconst1,
             0x0d => Self::Fconst2,
             0x6e => Self::Fdiv,
-            0x17 => Self::Fload(bytes.get_u8()),
+            0x17 => Self::Fload(reader.u8()?),
             0x22 => Self::Fload0,
             0x23 => Self::Fload1,
             0x24 => Self::Fload2,
@@ -333,16 +1134,16 @@ impl Instr {
             0x76 => Self::Fneg,
             0x72 => Self::Frem,
             0xae => Self::Freturn,
-            0x38 => Self::Fstore(bytes.get_u8()),
+            0x38 => Self::Fstore(reader.u8()?),
             0x43 => Self::Fstore0,
             0x44 => Self::Fstore1,
             0x45 => Self::Fstore2,
             0x46 => Self::Fstore3,
             0x66 => Self::Fsub,
-            0xb4 => Self::Getfield(bytes.get_u16()),
-            0xb2 => Self::Getstatic(bytes.get_u16()),
-            0xa7 => Self::Goto(bytes.get_i16()),
-            0xc8 => Self::GotoW(bytes.get_i32()),
+            0xb4 => Self::Getfield(reader.u16()?),
+            0xb2 => Self::Getstatic(reader.u16()?),
+            0xa7 => Self::Goto(reader.i16()?),
+            0xc8 => Self::GotoW(reader.i32()?),
             0x91 => Self::I2b,
             0x92 => Self::I2c,
             0x87 => Self::I2d,
@@ -361,42 +1162,58 @@ impl Instr {
             0x07 => Self::Iconst4,
             0x08 => Self::Iconst5,
             0x6c => Self::Idiv,
-            0xa5 => Self::IfAcmpeq(bytes.get_i16()),
-            0xa6 => Self::IfAcmpne(bytes.get_i16()),
-            0x9f => Self::IfIcmpeq(bytes.get_i16()),
-            0xa0 => Self::IfIcmpne(bytes.get_i16()),
-            0xa1 => Self::IfIcmplt(bytes.get_i16()),
-            0xa2 => Self::IfIcmpge(bytes.get_i16()),
-            0xa3 => Self::IfIcmpgt(bytes.get_i16()),
-            0xa4 => Self::IfIcmple(bytes.get_i16()),
-            0x99 => Self::Ifeq(bytes.get_i16()),
-            0x9a => Self::Ifne(bytes.get_i16()),
-            0x9b => Self::Iflt(bytes.get_i16()),
-            0x9c => Self::Ifge(bytes.get_i16()),
-            0x9d => Self::Ifgt(bytes.get_i16()),
-            0x9e => Self::Ifle(bytes.get_i16()),
-            0xc7 => Self::Ifnonnull(bytes.get_i16()),
-            0xc6 => Self::Ifnull(bytes.get_i16()),
-            0x84 => Self::Iinc(bytes.get_u8(), bytes.get_i8()),
-            0x15 => Self::Iload(bytes.get_u8()),
+            0xa5 => Self::IfAcmpeq(reader.i16()?),
+            0xa6 => Self::IfAcmpne(reader.i16()?),
+            0x9f => Self::IfIcmpeq(reader.i16()?),
+            0xa0 => Self::IfIcmpne(reader.i16()?),
+            0xa1 => Self::IfIcmplt(reader.i16()?),
+            0xa2 => Self::IfIcmpge(reader.i16()?),
+            0xa3 => Self::IfIcmpgt(reader.i16()?),
+            0xa4 => Self::IfIcmple(reader.i16()?),
+            0x99 => Self::Ifeq(reader.i16()?),
+            0x9a => Self::Ifne(reader.i16()?),
+            0x9b => Self::Iflt(reader.i16()?),
+            0x9c => Self::Ifge(reader.i16()?),
+            0x9d => Self::Ifgt(reader.i16()?),
+            0x9e => Self::Ifle(reader.i16()?),
+            0xc7 => Self::Ifnonnull(reader.i16()?),
+            0xc6 => Self::Ifnull(reader.i16()?),
+            0x84 => Self::Iinc(reader.u8()?, reader.i8()?),
+            0x15 => Self::Iload(reader.u8()?),
             0x1a => Self::Iload0,
             0x1b => Self::Iload1,
             0x1c => Self::Iload2,
             0x1d => Self::Iload3,
             0x68 => Self::Imul,
             0x74 => Self::Ineg,
-            0xc1 => Self::Instanceof(bytes.get_u16()),
-            0xba => Self::Invokedynamic(bytes.get_u16()),
-            0xb9 => Self::Invokeinterface(bytes.get_u16(), bytes.get_u8()),
-            0xb7 => Self::Invokespecial(bytes.get_u16()),
-            0xb8 => Self::Invokestatic(bytes.get_u16()),
-            0xb6 => Self::Invokevirtual(bytes.get_u16()),
+            0xc1 => Self::Instanceof(reader.u16()?),
+            0xba => {
+                let index = reader.u16()?;
+                if reader.u16()? != 0 {
+                    return Err(reader.error_at(offset, "invokedynamic operands 3 and 4 must be zero"));
+                }
+                Self::Invokedynamic(index)
+            }
+            0xb9 => {
+                let index = reader.u16()?;
+                let count = reader.u8()?;
+                if count == 0 {
+                    return Err(reader.error_at(offset, "invokeinterface count must not be zero"));
+                }
+                if reader.u8()? != 0 {
+                    return Err(reader.error_at(offset, "invokeinterface operand 4 must be zero"));
+                }
+                Self::Invokeinterface(index, count)
+            }
+            0xb7 => Self::Invokespecial(reader.u16()?),
+            0xb8 => Self::Invokestatic(reader.u16()?),
+            0xb6 => Self::Invokevirtual(reader.u16()?),
             0x80 => Self::Ior,
             0x70 => Self::Irem,
             0xac => Self::Ireturn,
             0x78 => Self::Ishl,
             0x7a => Self::Ishr,
-            0x36 => Self::Istore(bytes.get_u8()),
+            0x36 => Self::Istore(reader.u8()?),
             0x3b => Self::Istore0,
             0x3c => Self::Istore1,
             0x3d => Self::Istore2,
@@ -404,8 +1221,8 @@ impl Instr {
             0x64 => Self::Isub,
             0x7c => Self::Iushr,
             0x82 => Self::Ixor,
-            0xa8 => Self::Jsr(bytes.get_i16()),
-            0xc9 => Self::JsrW(bytes.get_i32()),
+            0xa8 => Self::Jsr(reader.i16()?),
+            0xc9 => Self::JsrW(reader.i32()?),
             0x8a => Self::L2d,
             0x89 => Self::L2f,
             0x88 => Self::L2i,
@@ -416,11 +1233,11 @@ impl Instr {
             0x94 => Self::Lcmp,
             0x09 => Self::Lconst0,
             0x0a => Self::Lconst1,
-            0x12 => Self::Ldc(bytes.get_u8()),
-            0x13 => Self::LdcW(bytes.get_u16()),
-            0x14 => Self::Ldc2W(bytes.get_u16()),
+            0x12 => Self::Ldc(reader.u8()?),
+            0x13 => Self::LdcW(reader.u16()?),
+            0x14 => Self::Ldc2W(reader.u16()?),
             0x6d => Self::Ldiv,
-            0x16 => Self::Lload(bytes.get_u8()),
+            0x16 => Self::Lload(reader.u8()?),
             0x1e => Self::Lload0,
             0x1f => Self::Lload1,
             0x20 => Self::Lload2,
@@ -428,14 +1245,17 @@ impl Instr {
             0x69 => Self::Lmul,
             0x75 => Self::Lneg,
             0xab => {
-                let default = bytes.get_i32();
-                let npairs = bytes.get_u32();
+                skip_switch_padding(reader, pc)?;
+                let default = reader.i32()?;
+                let npairs = reader.i32()?;
+                if npairs < 0 {
+                    return Err(reader.error_at(offset, format!("lookupswitch npairs {npairs} is negative")));
+                }
                 let match_offsets = (0..npairs)
-                    .map(|_| (bytes.get_i32(), bytes.get_i32()))
-                    .collect();
+                    .map(|_| Ok((reader.i32()?, reader.i32()?)))
+                    .collect::<ParseResult<_>>()?;
                 Self::Lookupswitch {
                     default,
-                    npairs,
                     match_offsets,
                 }
             },
@@ -444,7 +1264,7 @@ impl Instr {
             0xad => Self::Lreturn,
             0x79 => Self::Lshl,
             0x7b => Self::Lshr,
-            0x37 => Self::Lstore(bytes.get_u8()),
+            0x37 => Self::Lstore(reader.u8()?),
             0x3f => Self::Lstore0,
             0x40 => Self::Lstore1,
             0x41 => Self::Lstore2,
@@ -454,27 +1274,31 @@ impl Instr {
             0x83 => Self::Lxor,
             0xc2 => Self::Monitorenter,
             0xc3 => Self::Monitorexit,
-            0xc5 => Self::Multianewarray(bytes.get_u16(), bytes.get_u8()),
-            0xbb => Self::New(bytes.get_u16()),
-            0xbc => Self::Newarray(bytes.get_u8()),
+            0xc5 => Self::Multianewarray(reader.u16()?, reader.u8()?),
+            0xbb => Self::New(reader.u16()?),
+            0xbc => Self::Newarray(reader.u8()?),
             0x00 => Self::Nop,
             0x57 => Self::Pop,
             0x58 => Self::Pop2,
-            0xb5 => Self::Putfield(bytes.get_u16()),
-            0xb3 => Self::Putstatic(bytes.get_u16()),
-            0xa9 => Self::Ret(bytes.get_u8()),
+            0xb5 => Self::Putfield(reader.u16()?),
+            0xb3 => Self::Putstatic(reader.u16()?),
+            0xa9 => Self::Ret(reader.u8()?),
             0xb1 => Self::Return,
             0x35 => Self::Saload,
             0x56 => Self::Sastore,
-            0x11 => Self::Sipush(bytes.get_i16()),
+            0x11 => Self::Sipush(reader.i16()?),
             0x5f => Self::Swap,
             0xaa => {
-                let default = bytes.get_i32();
-                let low = bytes.get_i32();
-                let high = bytes.get_i32();
-                let jump_offsets = (0..high - low + 1)
-                    .map(|_| bytes.get_i32())
-                    .collect();
+                skip_switch_padding(reader, pc)?;
+                let default = reader.i32()?;
+                let low = reader.i32()?;
+                let high = reader.i32()?;
+                if high < low {
+                    return Err(reader.error_at(offset, format!("tableswitch high {high} is below low {low}")));
+                }
+                let jump_offsets = (low..=high)
+                    .map(|_| reader.i32())
+                    .collect::<ParseResult<_>>()?;
                 Self::Tableswitch {
                     default,
                     low,
@@ -482,42 +1306,409 @@ impl Instr {
                     jump_offsets,
                 }
             },
-            0xc4 => Self::Wide {
-                instr: Box::new(Self::from_bytes(bytes)),
-                index: bytes.get_u16(),
-            },
-            _ => panic!("Invalid instruction"),
+            0xc4 => Self::Wide(WideInstr::from_bytes(reader)?),
+            opcode => return Err(reader.error_at(offset, format!("invalid opcode {opcode:#04x}"))),
+        };
+        Ok(instr)
+    }
+}
+
+/// Skips the zero to three bytes that align `tableswitch` and
+/// `lookupswitch` operands to a multiple of four from the start of the code.
+fn skip_switch_padding(reader: &mut Reader, pc: u32) -> ParseResult<()> {
+    reader.bytes(switch_padding(pc) as usize)?;
+    Ok(())
+}
+
+fn put_switch_padding(buf: &mut Vec<u8>, pc: u32) {
+    buf.put_bytes(0, switch_padding(pc) as usize);
+}
+
+/// The number of padding bytes after a switch opcode at byte offset `pc`.
+fn switch_padding(pc: u32) -> u32 {
+    (4 - (pc + 1) % 4) % 4
+}
+
+impl WideInstr {
+    pub fn opcode(&self) -> u8 {
+        match self {
+            Self::Iload(_) => 0x15,
+            Self::Lload(_) => 0x16,
+            Self::Fload(_) => 0x17,
+            Self::Dload(_) => 0x18,
+            Self::Aload(_) => 0x19,
+            Self::Istore(_) => 0x36,
+            Self::Lstore(_) => 0x37,
+            Self::Fstore(_) => 0x38,
+            Self::Dstore(_) => 0x39,
+            Self::Astore(_) => 0x3a,
+            Self::Ret(_) => 0xa9,
+            Self::Iinc(_, _) => 0x84,
+        }
+    }
+
+    /// Appends the encoding of this instruction, without the `wide` prefix.
+    pub fn to_bytes(&self, buf: &mut Vec<u8>) {
+        buf.put_u8(self.opcode());
+        match self {
+            Self::Iinc(index, value) => {
+                buf.put_u16(*index);
+                buf.put_i16(*value);
+            }
+            Self::Iload(index) | Self::Lload(index) | Self::Fload(index)
+            | Self::Dload(index) | Self::Aload(index)
+            | Self::Istore(index) | Self::Lstore(index) | Self::Fstore(index)
+            | Self::Dstore(index) | Self::Astore(index)
+            | Self::Ret(index) => buf.put_u16(*index),
+        }
+    }
+
+    /// The shortest instruction with the same effect: one of the implicit
+    /// `_0` to `_3` forms, the one-byte index form, or `wide` only when the
+    /// operands need it.
+    pub fn shortest(self) -> Instr {
+        match self {
+            Self::Iload(0) => Instr::Iload0,
+            Self::Iload(1) => Instr::Iload1,
+            Self::Iload(2) => Instr::Iload2,
+            Self::Iload(3) => Instr::Iload3,
+            Self::Lload(0) => Instr::Lload0,
+            Self::Lload(1) => Instr::Lload1,
+            Self::Lload(2) => Instr::Lload2,
+            Self::Lload(3) => Instr::Lload3,
+            Self::Fload(0) => Instr::Fload0,
+            Self::Fload(1) => Instr::Fload1,
+            Self::Fload(2) => Instr::Fload2,
+            Self::Fload(3) => Instr::Fload3,
+            Self::Dload(0) => Instr::Dload0,
+            Self::Dload(1) => Instr::Dload1,
+            Self::Dload(2) => Instr::Dload2,
+            Self::Dload(3) => Instr::Dload3,
+            Self::Aload(0) => Instr::Aload0,
+            Self::Aload(1) => Instr::Aload1,
+            Self::Aload(2) => Instr::Aload2,
+            Self::Aload(3) => Instr::Aload3,
+            Self::Istore(0) => Instr::Istore0,
+            Self::Istore(1) => Instr::Istore1,
+            Self::Istore(2) => Instr::Istore2,
+            Self::Istore(3) => Instr::Istore3,
+            Self::Lstore(0) => Instr::Lstore0,
+            Self::Lstore(1) => Instr::Lstore1,
+            Self::Lstore(2) => Instr::Lstore2,
+            Self::Lstore(3) => Instr::Lstore3,
+            Self::Fstore(0) => Instr::Fstore0,
+            Self::Fstore(1) => Instr::Fstore1,
+            Self::Fstore(2) => Instr::Fstore2,
+            Self::Fstore(3) => Instr::Fstore3,
+            Self::Dstore(0) => Instr::Dstore0,
+            Self::Dstore(1) => Instr::Dstore1,
+            Self::Dstore(2) => Instr::Dstore2,
+            Self::Dstore(3) => Instr::Dstore3,
+            Self::Astore(0) => Instr::Astore0,
+            Self::Astore(1) => Instr::Astore1,
+            Self::Astore(2) => Instr::Astore2,
+            Self::Astore(3) => Instr::Astore3,
+            _ => self.narrow().unwrap_or(Instr::Wide(self)),
         }
     }
+
+    /// The same instruction without the `wide` prefix, if its operands fit.
+    fn narrow(&self) -> Option<Instr> {
+        let instr = match *self {
+            Self::Iload(index) => Instr::Iload(index.try_into().ok()?),
+            Self::Lload(index) => Instr::Lload(index.try_into().ok()?),
+            Self::Fload(index) => Instr::Fload(index.try_into().ok()?),
+            Self::Dload(index) => Instr::Dload(index.try_into().ok()?),
+            Self::Aload(index) => Instr::Aload(index.try_into().ok()?),
+            Self::Istore(index) => Instr::Istore(index.try_into().ok()?),
+            Self::Lstore(index) => Instr::Lstore(index.try_into().ok()?),
+            Self::Fstore(index) => Instr::Fstore(index.try_into().ok()?),
+            Self::Dstore(index) => Instr::Dstore(index.try_into().ok()?),
+            Self::Astore(index) => Instr::Astore(index.try_into().ok()?),
+            Self::Ret(index) => Instr::Ret(index.try_into().ok()?),
+            Self::Iinc(index, value) => Instr::Iinc(index.try_into().ok()?, value.try_into().ok()?),
+        };
+        Some(instr)
+    }
+
+    pub fn from_bytes(reader: &mut Reader) -> ParseResult<Self> {
+        let offset = reader.offset();
+        let instr = match reader.u8()? {
+            0x15 => Self::Iload(reader.u16()?),
+            0x16 => Self::Lload(reader.u16()?),
+            0x17 => Self::Fload(reader.u16()?),
+            0x18 => Self::Dload(reader.u16()?),
+            0x19 => Self::Aload(reader.u16()?),
+            0x36 => Self::Istore(reader.u16()?),
+            0x37 => Self::Lstore(reader.u16()?),
+            0x38 => Self::Fstore(reader.u16()?),
+            0x39 => Self::Dstore(reader.u16()?),
+            0x3a => Self::Astore(reader.u16()?),
+            0xa9 => Self::Ret(reader.u16()?),
+            0x84 => Self::Iinc(reader.u16()?, reader.i16()?),
+            opcode => return Err(reader.error_at(offset, format!("opcode {opcode:#04x} cannot be widened"))),
+        };
+        Ok(instr)
+    }
 }
 
 #[derive(Clone, Debug)]
 pub struct ExceptionTableEntry {
-    start_pc: u16,
-    end_pc: u16,
-    handler_pc: u16,
-    catch_type: u16,
+    pub start_pc: u16,
+    pub end_pc: u16,
+    pub handler_pc: u16,
+    pub catch_type: u16,
 }
 
 impl ExceptionTableEntry {
-    pub fn from_bytes(bytes: &mut Bytes) -> Self {
-        let start_pc = bytes.get_u16();
-        let end_pc = bytes.get_u16();
-        let handler_pc = bytes.get_u16();
-        let catch_type = bytes.get_u16();
+    /// Whether this handler covers the instruction at byte offset `offset`.
+    pub fn covers(&self, offset: u32) -> bool {
+        (self.start_pc as u32..self.end_pc as u32).contains(&offset)
+    }
+
+    /// The index of the first instruction of the handler.
+    pub fn handler_index(&self, code: &Code) -> Option<usize> {
+        code.index_of(self.handler_pc as u32)
+    }
+
+    pub fn to_bytes(&self, buf: &mut Vec<u8>) {
+        buf.put_u16(self.start_pc);
+        buf.put_u16(self.end_pc);
+        buf.put_u16(self.handler_pc);
+        buf.put_u16(self.catch_type);
+    }
+
+    pub fn from_bytes(reader: &mut Reader) -> ParseResult<Self> {
+        let start_pc = reader.u16()?;
+        let end_pc = reader.u16()?;
+        let handler_pc = reader.u16()?;
+        let catch_type = reader.u16()?;
 
-        Self {
+        Ok(Self {
             start_pc,
             end_pc,
             handler_pc,
             catch_type,
-        }
+        })
     }
 }
 
-fn parse_exception_table(bytes: &mut Bytes) -> Vec<ExceptionTableEntry> {
-    let exception_table_length = bytes.get_u16() as usize;
+fn parse_exception_table(reader: &mut Reader) -> ParseResult<Vec<ExceptionTableEntry>> {
+    let exception_table_length = reader.u16()? as usize;
     (0..exception_table_length)
-        .map(|_| ExceptionTableEntry::from_bytes(bytes))
-        .collect::<Vec<_>>()
-}
\ No newline at end of file
+        .map(|_| ExceptionTableEntry::from_bytes(reader))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+    use crate::class::{Class, Method};
+    use crate::annotation::{LocalVariableTarget, TypeAnnotationTarget};
+    use crate::stack_map::{Frame, VerificationType};
+    use super::*;
+
+    fn parse(bytes: &'static [u8]) -> Class {
+        Class::parse(Bytes::from_static(bytes)).unwrap()
+    }
+
+    fn methods(class: &Class) -> impl Iterator<Item = &Method> {
+        class.static_methods.iter().chain(&class.methods)
+    }
+
+    /// The code array exactly as it appears in the method's `Code`
+    /// attribute.
+    fn original_code(method: &Method) -> &[u8] {
+        let info = &method.attributes.iter().find(|attr| attr.name.as_ref() == "Code").unwrap().info();
+        let length = u32::from_be_bytes(info[4..8].try_into().unwrap()) as usize;
+        &info[8..8 + length]
+    }
+
+    /// Asserts that re-encoding every method of the class reproduces its
+    /// code array byte for byte, instruction by instruction.
+    fn assert_round_trip(class: &Class) {
+        for method in methods(class).filter(|method| method.has_code()) {
+            let code = method.code().unwrap();
+            let original = original_code(method);
+            for (index, instr) in code.code.iter().enumerate() {
+                let start = code.offset(index);
+                let end = code.offsets.get(index + 1).copied().unwrap_or(code.code_length);
+                let mut buf = Vec::new();
+                instr.to_bytes(start, &mut buf);
+                assert_eq!(buf, original[start as usize..end as usize], "{}.{} at {start}: {instr:?}", class.name, method.name);
+                assert_eq!(instr.len(start), end - start, "{}.{} at {start}: {instr:?}", class.name, method.name);
+            }
+            assert_eq!(code.code_bytes(), original, "{}.{}", class.name, method.name);
+        }
+    }
+
+    fn instructions(class: &Class) -> impl Iterator<Item = (u32, &Instr)> {
+        methods(class)
+            .filter_map(Method::code)
+            .flat_map(|code| code.offsets.iter().copied().zip(&code.code))
+    }
+
+    #[test]
+    fn round_trips_hello_and_apple() {
+        assert_round_trip(&parse(include_bytes!("../Hello.class")));
+        assert_round_trip(&parse(include_bytes!("../Apple.class")));
+    }
+
+    #[test]
+    fn round_trips_switches_at_every_alignment() {
+        let class = parse(include_bytes!("../tests/fixtures/Switches.class"));
+        assert_round_trip(&class);
+
+        let mut table = [false; 4];
+        let mut lookup = [false; 4];
+        for (pc, instr) in instructions(&class) {
+            match instr {
+                Instr::Tableswitch { .. } => table[pc as usize % 4] = true,
+                Instr::Lookupswitch { .. } => lookup[pc as usize % 4] = true,
+                _ => {}
+            }
+        }
+        assert_eq!(table, [true; 4]);
+        assert_eq!(lookup, [true; 4]);
+    }
+
+    #[test]
+    fn round_trips_wide() {
+        let class = parse(include_bytes!("../tests/fixtures/Wide.class"));
+        assert_round_trip(&class);
+        assert!(instructions(&class).any(|(_, instr)| matches!(instr, Instr::Wide(WideInstr::Iinc(_, 1000)))));
+        assert!(instructions(&class).any(|(_, instr)| matches!(instr, Instr::Wide(WideInstr::Iload(_)))));
+        assert!(instructions(&class).any(|(_, instr)| matches!(instr, Instr::Wide(WideInstr::Istore(_)))));
+    }
+
+    #[test]
+    fn round_trips_invokeinterface_and_invokedynamic() {
+        let class = parse(include_bytes!("../tests/fixtures/Invokes.class"));
+        assert_round_trip(&class);
+        assert!(instructions(&class).any(|(_, instr)| matches!(instr, Instr::Invokeinterface(..))));
+        assert!(instructions(&class).any(|(_, instr)| matches!(instr, Instr::Invokedynamic(_))));
+    }
+
+    #[test]
+    fn rejects_the_wrong_number_of_branch_offsets() {
+        let mut instr = Instr::Tableswitch { default: 0, low: 0, high: 1, jump_offsets: vec![4, 8] };
+        assert!(matches!(instr.set_branch_deltas(&[0, 4]), Err(Error::WrongBranchCount { expected: 3, actual: 2 })));
+        assert!(matches!(Instr::Goto(0).set_branch_deltas(&[]), Err(Error::WrongBranchCount { expected: 1, actual: 0 })));
+        instr.set_branch_deltas(&[12, 16, 20]).unwrap();
+        assert_eq!(instr.branch_deltas(), [12, 16, 20]);
+    }
+
+    #[test]
+    fn rewritten_code_is_kept_when_written() {
+        let mut class = parse(include_bytes!("../Hello.class"));
+        let method = class.static_methods.iter_mut().find(|method| method.name.as_ref() == "main").unwrap();
+        let lines_before = method.code().unwrap().line_numbers().to_vec();
+        method.rewrite_code(|index, instr| match index {
+            0 => vec![Instr::Nop, instr.clone()],
+            _ => vec![instr.clone()],
+        }).unwrap();
+
+        let class = Class::parse(Bytes::from(class.write().unwrap())).unwrap();
+        let code = class.static_methods.iter().find(|method| method.name.as_ref() == "main").unwrap().code().unwrap();
+        assert!(matches!(code.code[0], Instr::Nop));
+        let lines = code.line_numbers();
+        assert_eq!(lines.len(), lines_before.len());
+        assert_eq!(lines[0].start_pc, 0);
+        for (line, before) in lines.iter().zip(&lines_before).skip(1) {
+            assert_eq!(line.start_pc, before.start_pc + 1);
+        }
+    }
+
+    #[test]
+    fn rewriting_moves_stack_map_frames_with_their_instructions() {
+        let mut class = parse(include_bytes!("../tests/fixtures/Switches.class"));
+        let initial = Frame::initial(&[VerificationType::Integer]);
+        let frames = |code: &Code| {
+            code.stack_map_frames(&initial).unwrap().into_iter()
+                .map(|frame| (code.index_of(frame.offset).unwrap(), frame.locals, frame.stack))
+                .collect::<Vec<_>>()
+        };
+        for method in class.static_methods.iter_mut().filter(|method| method.name.starts_with("table")) {
+            let before = frames(method.code().unwrap());
+            assert!(!before.is_empty());
+            // Enough to push the first frame past the compact offset_delta
+            // range and to move every switch to a new alignment.
+            method.rewrite_code(|index, instr| match index {
+                0 => std::iter::repeat_n(Instr::Nop, 70).chain([instr.clone()]).collect(),
+                _ => vec![instr.clone()],
+            }).unwrap();
+            let after = frames(method.code().unwrap());
+            let moved = before.into_iter()
+                .map(|(index, locals, stack)| (index + 70, locals, stack))
+                .collect::<Vec<_>>();
+            assert_eq!(after, moved);
+        }
+    }
+
+    #[test]
+    fn widens_conditional_branches_over_a_goto_w() {
+        let class = parse(include_bytes!("../Hello.class"));
+        let mut code = class.static_methods.iter().find(|method| method.name.as_ref() == "main").unwrap().code().unwrap().clone();
+        assert!(code.stack_map_table().is_none());
+        let last_offset = *code.offsets.last().unwrap() as i16;
+        code.rewrite(|index, instr| match index {
+            0 => [Instr::Iconst0, Instr::Ifeq(last_offset), instr.clone()].into_iter()
+                .chain(std::iter::repeat_n(Instr::Nop, 40_000))
+                .collect(),
+            _ => vec![instr.clone()],
+        }).unwrap();
+
+        assert!(matches!(code.code[1], Instr::Ifne(8)));
+        let Instr::GotoW(delta) = code.code[2] else {
+            panic!("expected goto_w, found {:?}", code.code[2]);
+        };
+        assert_eq!(code.index_of(code.offsets[2] + delta as u32), Some(code.code.len() - 1));
+        assert_eq!(code.index_of(code.offsets[1] + 8), Some(3));
+    }
+
+    #[test]
+    fn remaps_local_variables_that_end_with_the_code() {
+        let class = parse(include_bytes!("../tests/fixtures/Locals.class"));
+        let mut code = class.static_methods.iter().find(|method| method.name.as_ref() == "sum").unwrap().code().unwrap().clone();
+        let before = code.local_variables().to_vec();
+        assert!(before.iter().any(|local| local.start_pc as u32 + local.length as u32 == code.code_length));
+        code.rewrite(|index, instr| match index {
+            0 => vec![Instr::Nop, instr.clone()],
+            _ => vec![instr.clone()],
+        }).unwrap();
+        let locals = code.local_variables();
+        assert_eq!(locals.len(), before.len());
+        for (local, before) in locals.iter().zip(&before) {
+            assert_eq!(local.start_pc, before.start_pc + (before.start_pc > 0) as u16);
+            assert_eq!(local.start_pc as u32 + local.length as u32, before.start_pc as u32 + before.length as u32 + 1);
+        }
+    }
+
+    #[test]
+    fn remaps_the_targets_of_type_annotations() {
+        let class = parse(include_bytes!("../tests/fixtures/TypeAnnotated.class"));
+        let mut code = class.static_methods.iter().find(|method| method.name.as_ref() == "size").unwrap().code().unwrap().clone();
+        let targets = |code: &Code| code.type_annotations().map(|annotation| annotation.target.clone()).collect::<Vec<_>>();
+        let before = targets(&code);
+        assert_eq!(before.len(), 5);
+
+        // A nop after the cast at offset 1, so the cast stays where it is
+        // and the `new` and the local variable after it move.
+        let cast = code.index_of(1).unwrap();
+        assert!(matches!(code.code[cast], Instr::Checkcast(_)));
+        code.rewrite(|index, instr| match index {
+            _ if index == cast => vec![instr.clone(), Instr::Nop],
+            _ => vec![instr.clone()],
+        }).unwrap();
+
+        let moved = before.into_iter().map(|target| match target {
+            TypeAnnotationTarget::Offset(5) => TypeAnnotationTarget::Offset(6),
+            TypeAnnotationTarget::LocalVariable(locals) => TypeAnnotationTarget::LocalVariable(
+                locals.into_iter().map(|local| LocalVariableTarget { start_pc: local.start_pc + 1, ..local }).collect(),
+            ),
+            target => target,
+        }).collect::<Vec<_>>();
+        assert!(moved.contains(&TypeAnnotationTarget::TypeArgument { offset: 1, type_argument_index: 0 }));
+        assert_eq!(targets(&code), moved);
+    }
+}
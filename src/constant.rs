@@ -1,6 +1,13 @@
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
 use std::sync::Arc;
-use bytes::{Buf, Bytes};
+use bytes::{Buf, BufMut, Bytes};
 use crate::class::Class;
+use crate::descriptor::{FieldType, MethodDescriptor};
+use crate::error::{Error, ParseResult, Result};
+use crate::mutf8;
+use crate::reader::Reader;
+use crate::version::ClassVersion;
 
 #[derive(Clone, Debug, PartialEq)]
 pub enum Constant {
@@ -18,6 +25,9 @@ pub enum Constant {
         descriptor_index: u16,
     },
     Utf8(Arc<str>),
+    /// A `CONSTANT_Utf8` whose contents contain unpaired surrogates and so
+    /// cannot be represented as a `str`.
+    Utf16(Arc<[u16]>),
     MethodHandle {
         reference_kind: u8,
         reference_index: u16,
@@ -33,56 +43,566 @@ pub enum Constant {
     },
     Module { name_index: u16 },
     Package { name_index: u16 },
+    /// Index 0 and the slot after a `Long` or `Double`, which occupy two
+    /// constant pool entries. These slots exist but may not be referenced.
+    Unusable,
+}
+
+/// A resolved `CONSTANT_Fieldref`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FieldRef {
+    pub class: Arc<str>,
+    pub name: Arc<str>,
+    pub descriptor: FieldType,
+}
+
+/// A resolved `CONSTANT_Methodref` or `CONSTANT_InterfaceMethodref`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MethodRef {
+    pub class: Arc<str>,
+    pub name: Arc<str>,
+    pub descriptor: MethodDescriptor,
+}
+
+/// The contents of a `CONSTANT_String`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum StringRef {
+    Str(Arc<str>),
+    /// The string contains unpaired surrogates.
+    Utf16(Arc<[u16]>),
+}
+
+/// The `reference_kind` of a `CONSTANT_MethodHandle` (JVMS 5.4.3.5).
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ReferenceKind {
+    GetField = 1,
+    GetStatic = 2,
+    PutField = 3,
+    PutStatic = 4,
+    InvokeVirtual = 5,
+    InvokeStatic = 6,
+    InvokeSpecial = 7,
+    NewInvokeSpecial = 8,
+    InvokeInterface = 9,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum MemberRef {
+    Field(FieldRef),
+    Method(MethodRef),
+    InterfaceMethod(MethodRef),
+}
+
+/// A resolved `CONSTANT_MethodHandle`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MethodHandleRef {
+    pub kind: ReferenceKind,
+    pub member: MemberRef,
+}
+
+/// A class file's constant pool, addressed by the indices used in the class
+/// file itself (JVMS 4.4).
+#[derive(Clone, Debug)]
+pub struct ConstantPool {
+    constants: Vec<Constant>,
+    /// The first index of each distinct entry, for `add`. Built by the first
+    /// `add`, since most pools are only ever read.
+    index: Option<HashMap<Identical, u16>>,
+}
+
+/// A constant compared with `Constant::is_identical`, as a map key.
+#[derive(Clone, Debug)]
+struct Identical(Constant);
+
+impl PartialEq for Identical {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.is_identical(&other.0)
+    }
+}
+
+impl Eq for Identical {}
+
+impl Hash for Identical {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.tag().hash(state);
+        match &self.0 {
+            Constant::Utf8(string) => string.hash(state),
+            Constant::Utf16(units) => units.hash(state),
+            Constant::Integer(value) => value.hash(state),
+            Constant::Float(value) => value.to_bits().hash(state),
+            Constant::Long(value) => value.hash(state),
+            Constant::Double(value) => value.to_bits().hash(state),
+            Constant::Class { name_index: index }
+            | Constant::String { string_index: index }
+            | Constant::MethodType { descriptor_index: index }
+            | Constant::Module { name_index: index }
+            | Constant::Package { name_index: index } => index.hash(state),
+            Constant::FieldRef { class_index: a, name_and_type_index: b }
+            | Constant::MethodRef { class_index: a, name_and_type_index: b }
+            | Constant::InterfaceMethodRef { class_index: a, name_and_type_index: b }
+            | Constant::NameAndType { name_index: a, descriptor_index: b }
+            | Constant::Dynamic { bootstrap_method_attr_index: a, name_and_type_index: b }
+            | Constant::InvokeDynamic { bootstrap_method_attr_index: a, name_and_type_index: b } => (a, b).hash(state),
+            Constant::MethodHandle { reference_kind, reference_index } => (reference_kind, reference_index).hash(state),
+            Constant::Unusable => {}
+        }
+    }
+}
+
+impl Default for ConstantPool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ConstantPool {
+    /// An empty pool, for building a class from scratch.
+    pub fn new() -> Self {
+        Self { constants: vec![Constant::Unusable], index: None }
+    }
+
+    pub fn from_bytes(reader: &mut Reader, version: ClassVersion) -> ParseResult<Self> {
+        reader.within("constant_pool", |reader| {
+            let constants_count = reader.u16()? as usize;
+            let mut constants = Vec::with_capacity(constants_count);
+            constants.push(Constant::Unusable);
+
+            while constants.len() < constants_count {
+                let offset = reader.offset();
+                let constant = Constant::from_bytes(reader)?;
+                if !version.allows_constant(&constant) {
+                    return Err(reader.error_at(offset, format!(
+                        "constant {} is not allowed in class file version {version}",
+                        constants.len(),
+                    )));
+                }
+                let wide = constant.is_wide();
+                constants.push(constant);
+                if wide {
+                    constants.push(Constant::Unusable);
+                }
+            }
+
+            if constants.len() > constants_count {
+                return Err(reader.error("last constant is a Long or Double with no room for its second slot"));
+            }
+
+            Ok(Self { constants, index: None })
+        })
+    }
+
+    /// The entry at `index`, or `None` if `index` is out of range or names an
+    /// unusable slot.
+    pub fn get(&self, index: u16) -> Option<&Constant> {
+        match self.constants.get(index as usize) {
+            Some(Constant::Unusable) | None => None,
+            constant => constant,
+        }
+    }
+
+    pub fn utf8(&self, index: u16) -> Option<&Arc<str>> {
+        match self.get(index) {
+            Some(Constant::Utf8(string)) => Some(string),
+            _ => None,
+        }
+    }
+
+    /// The name referenced by a `CONSTANT_Class` entry.
+    pub fn class_name(&self, index: u16) -> Option<&Arc<str>> {
+        match self.get(index) {
+            Some(Constant::Class { name_index }) => self.utf8(*name_index),
+            _ => None,
+        }
+    }
+
+    /// The name referenced by a `CONSTANT_Module` entry.
+    pub fn module_name(&self, index: u16) -> Option<&Arc<str>> {
+        match self.get(index) {
+            Some(Constant::Module { name_index }) => self.utf8(*name_index),
+            _ => None,
+        }
+    }
+
+    /// The name referenced by a `CONSTANT_Package` entry.
+    pub fn package_name(&self, index: u16) -> Option<&Arc<str>> {
+        match self.get(index) {
+            Some(Constant::Package { name_index }) => self.utf8(*name_index),
+            _ => None,
+        }
+    }
+
+    /// The name and descriptor referenced by a `CONSTANT_NameAndType` entry.
+    pub fn name_and_type(&self, index: u16) -> Option<(&Arc<str>, &Arc<str>)> {
+        match self.get(index) {
+            Some(Constant::NameAndType { name_index, descriptor_index }) =>
+                Some((self.utf8(*name_index)?, self.utf8(*descriptor_index)?)),
+            _ => None,
+        }
+    }
+
+    /// Resolves a `CONSTANT_Class` entry to the class name.
+    pub fn class_ref(&self, index: u16) -> Result<Arc<str>> {
+        self.class_name(index).cloned().ok_or(invalid(index, "Class"))
+    }
+
+    pub fn field_ref(&self, index: u16) -> Result<FieldRef> {
+        let Some(Constant::FieldRef { class_index, name_and_type_index }) = self.get(index) else {
+            return Err(invalid(index, "FieldRef"));
+        };
+        let (class, name, descriptor) = self.member(*class_index, *name_and_type_index)?;
+        let descriptor = FieldType::parse(&descriptor).ok_or(Error::InvalidDescriptor(descriptor))?;
+        Ok(FieldRef { class, name, descriptor })
+    }
+
+    pub fn method_ref(&self, index: u16) -> Result<MethodRef> {
+        let Some(Constant::MethodRef { class_index, name_and_type_index }) = self.get(index) else {
+            return Err(invalid(index, "MethodRef"));
+        };
+        self.method(*class_index, *name_and_type_index)
+    }
+
+    pub fn interface_method_ref(&self, index: u16) -> Result<MethodRef> {
+        let Some(Constant::InterfaceMethodRef { class_index, name_and_type_index }) = self.get(index) else {
+            return Err(invalid(index, "InterfaceMethodRef"));
+        };
+        self.method(*class_index, *name_and_type_index)
+    }
+
+    /// Resolves a `CONSTANT_String` entry to its contents.
+    pub fn string(&self, index: u16) -> Result<StringRef> {
+        let Some(Constant::String { string_index }) = self.get(index) else {
+            return Err(invalid(index, "String"));
+        };
+        match self.get(*string_index) {
+            Some(Constant::Utf8(string)) => Ok(StringRef::Str(string.clone())),
+            Some(Constant::Utf16(units)) => Ok(StringRef::Utf16(units.clone())),
+            _ => Err(invalid(*string_index, "Utf8")),
+        }
+    }
+
+    /// Resolves a `CONSTANT_MethodHandle` entry, checking that it refers to
+    /// the kind of member its reference kind requires (JVMS 4.4.8).
+    pub fn method_handle(&self, index: u16) -> Result<MethodHandleRef> {
+        let Some(Constant::MethodHandle { reference_kind, reference_index }) = self.get(index) else {
+            return Err(invalid(index, "MethodHandle"));
+        };
+        let kind = ReferenceKind::from_u8(*reference_kind).ok_or(invalid(index, "MethodHandle"))?;
+        let index = *reference_index;
+        let member = match kind {
+            ReferenceKind::GetField
+            | ReferenceKind::GetStatic
+            | ReferenceKind::PutField
+            | ReferenceKind::PutStatic => MemberRef::Field(self.field_ref(index)?),
+            ReferenceKind::InvokeVirtual
+            | ReferenceKind::NewInvokeSpecial => MemberRef::Method(self.method_ref(index)?),
+            ReferenceKind::InvokeStatic
+            | ReferenceKind::InvokeSpecial => match self.get(index) {
+                Some(Constant::InterfaceMethodRef { .. }) => MemberRef::InterfaceMethod(self.interface_method_ref(index)?),
+                _ => MemberRef::Method(self.method_ref(index)?),
+            },
+            ReferenceKind::InvokeInterface => MemberRef::InterfaceMethod(self.interface_method_ref(index)?),
+        };
+        Ok(MethodHandleRef { kind, member })
+    }
+
+    /// The class, name and descriptor shared by field and method references.
+    fn member(&self, class_index: u16, name_and_type_index: u16) -> Result<(Arc<str>, Arc<str>, Arc<str>)> {
+        let class = self.class_ref(class_index)?;
+        let (name, descriptor) = self.name_and_type(name_and_type_index)
+            .ok_or(invalid(name_and_type_index, "NameAndType"))?;
+        Ok((class, name.clone(), descriptor.clone()))
+    }
+
+    fn method(&self, class_index: u16, name_and_type_index: u16) -> Result<MethodRef> {
+        let (class, name, descriptor) = self.member(class_index, name_and_type_index)?;
+        let descriptor = MethodDescriptor::parse(&descriptor).ok_or(Error::InvalidDescriptor(descriptor))?;
+        Ok(MethodRef { class, name, descriptor })
+    }
+
+    /// The `constant_pool_count` of the class file: one more than the
+    /// largest valid index.
+    pub fn count(&self) -> u16 {
+        self.constants.len() as u16
+    }
+
+    /// Every usable entry together with its index.
+    pub fn iter(&self) -> impl Iterator<Item = (u16, &Constant)> {
+        self.constants.iter()
+            .enumerate()
+            .filter(|(_, constant)| !matches!(constant, Constant::Unusable))
+            .map(|(index, constant)| (index as u16, constant))
+    }
+
+    /// The index of an entry identical to `constant`, adding it to the end
+    /// of the pool if there is none.
+    pub fn add(&mut self, constant: Constant) -> Result<u16> {
+        let constants = &self.constants;
+        let existing = self.index.get_or_insert_with(|| {
+            let mut index = HashMap::with_capacity(constants.len());
+            for (n, constant) in constants.iter().enumerate().rev() {
+                if !matches!(constant, Constant::Unusable) {
+                    index.insert(Identical(constant.clone()), n as u16);
+                }
+            }
+            index
+        });
+        let key = Identical(constant);
+        if let Some(&index) = existing.get(&key) {
+            return Ok(index);
+        }
+
+        let Identical(constant) = key;
+        let slots = if constant.is_wide() { 2 } else { 1 };
+        let index = self.constants.len();
+        if index + slots > u16::MAX as usize {
+            return Err(Error::ConstantPoolFull);
+        }
+        existing.insert(Identical(constant.clone()), index as u16);
+        self.constants.push(constant);
+        if slots == 2 {
+            self.constants.push(Constant::Unusable);
+        }
+        Ok(index as u16)
+    }
+
+    pub fn add_utf8(&mut self, string: &str) -> Result<u16> {
+        self.add(Constant::Utf8(Arc::from(string)))
+    }
+
+    pub fn add_class(&mut self, name: &str) -> Result<u16> {
+        let name_index = self.add_utf8(name)?;
+        self.add(Constant::Class { name_index })
+    }
+
+    pub fn add_string(&mut self, string: &str) -> Result<u16> {
+        let string_index = self.add_utf8(string)?;
+        self.add(Constant::String { string_index })
+    }
+
+    pub fn add_name_and_type(&mut self, name: &str, descriptor: &str) -> Result<u16> {
+        let name_index = self.add_utf8(name)?;
+        let descriptor_index = self.add_utf8(descriptor)?;
+        self.add(Constant::NameAndType { name_index, descriptor_index })
+    }
+
+    pub fn add_field_ref(&mut self, class: &str, name: &str, descriptor: &str) -> Result<u16> {
+        let class_index = self.add_class(class)?;
+        let name_and_type_index = self.add_name_and_type(name, descriptor)?;
+        self.add(Constant::FieldRef { class_index, name_and_type_index })
+    }
+
+    pub fn add_method_ref(&mut self, class: &str, name: &str, descriptor: &str) -> Result<u16> {
+        let class_index = self.add_class(class)?;
+        let name_and_type_index = self.add_name_and_type(name, descriptor)?;
+        self.add(Constant::MethodRef { class_index, name_and_type_index })
+    }
+
+    pub fn add_interface_method_ref(&mut self, class: &str, name: &str, descriptor: &str) -> Result<u16> {
+        let class_index = self.add_class(class)?;
+        let name_and_type_index = self.add_name_and_type(name, descriptor)?;
+        self.add(Constant::InterfaceMethodRef { class_index, name_and_type_index })
+    }
+
+    /// Appends `constant_pool_count` and every entry.
+    pub fn to_bytes(&self, buf: &mut Vec<u8>) -> Result<()> {
+        buf.put_u16(self.count());
+        for (_, constant) in self.iter() {
+            constant.to_bytes(buf)?;
+        }
+        Ok(())
+    }
+}
+
+impl StringRef {
+    /// The string as the UTF-16 code units of a `java.lang.String`.
+    pub fn to_utf16(&self) -> Vec<u16> {
+        match self {
+            Self::Str(string) => string.encode_utf16().collect(),
+            Self::Utf16(units) => units.to_vec(),
+        }
+    }
+}
+
+impl ReferenceKind {
+    pub fn from_u8(kind: u8) -> Option<Self> {
+        Some(match kind {
+            1 => Self::GetField,
+            2 => Self::GetStatic,
+            3 => Self::PutField,
+            4 => Self::PutStatic,
+            5 => Self::InvokeVirtual,
+            6 => Self::InvokeStatic,
+            7 => Self::InvokeSpecial,
+            8 => Self::NewInvokeSpecial,
+            9 => Self::InvokeInterface,
+            _ => return None,
+        })
+    }
 }
 
 impl Constant {
-    pub fn from_bytes(bytes: &mut Bytes) -> Self {
-        match bytes.get_u8() {
-            7 => Self::Class { name_index: bytes.get_u16() },
+    /// Whether this entry takes up two slots in the constant pool.
+    pub fn is_wide(&self) -> bool {
+        matches!(self, Self::Long(_) | Self::Double(_))
+    }
+
+    /// Like `==`, but floating point constants compare by bit pattern, so
+    /// `0.0` and `-0.0` differ and `NaN` matches itself.
+    pub fn is_identical(&self, other: &Constant) -> bool {
+        match (self, other) {
+            (Self::Float(a), Self::Float(b)) => a.to_bits() == b.to_bits(),
+            (Self::Double(a), Self::Double(b)) => a.to_bits() == b.to_bits(),
+            _ => self == other,
+        }
+    }
+
+    pub fn tag(&self) -> u8 {
+        match self {
+            Self::Utf8(_) | Self::Utf16(_) => 1,
+            Self::Integer(_) => 3,
+            Self::Float(_) => 4,
+            Self::Long(_) => 5,
+            Self::Double(_) => 6,
+            Self::Class { .. } => 7,
+            Self::String { .. } => 8,
+            Self::FieldRef { .. } => 9,
+            Self::MethodRef { .. } => 10,
+            Self::InterfaceMethodRef { .. } => 11,
+            Self::NameAndType { .. } => 12,
+            Self::MethodHandle { .. } => 15,
+            Self::MethodType { .. } => 16,
+            Self::Dynamic { .. } => 17,
+            Self::InvokeDynamic { .. } => 18,
+            Self::Module { .. } => 19,
+            Self::Package { .. } => 20,
+            Self::Unusable => 0,
+        }
+    }
+
+    /// Appends the `cp_info` encoding of this entry. `Unusable` slots have
+    /// no encoding and append nothing.
+    pub fn to_bytes(&self, buf: &mut Vec<u8>) -> Result<()> {
+        if let Self::Unusable = self {
+            return Ok(());
+        }
+        buf.put_u8(self.tag());
+        match self {
+            Self::Class { name_index: index }
+            | Self::String { string_index: index }
+            | Self::MethodType { descriptor_index: index }
+            | Self::Module { name_index: index }
+            | Self::Package { name_index: index } => buf.put_u16(*index),
+            Self::FieldRef { class_index, name_and_type_index }
+            | Self::MethodRef { class_index, name_and_type_index }
+            | Self::InterfaceMethodRef { class_index, name_and_type_index } => {
+                buf.put_u16(*class_index);
+                buf.put_u16(*name_and_type_index);
+            }
+            Self::NameAndType { name_index, descriptor_index } => {
+                buf.put_u16(*name_index);
+                buf.put_u16(*descriptor_index);
+            }
+            Self::Dynamic { bootstrap_method_attr_index, name_and_type_index }
+            | Self::InvokeDynamic { bootstrap_method_attr_index, name_and_type_index } => {
+                buf.put_u16(*bootstrap_method_attr_index);
+                buf.put_u16(*name_and_type_index);
+            }
+            Self::MethodHandle { reference_kind, reference_index } => {
+                buf.put_u8(*reference_kind);
+                buf.put_u16(*reference_index);
+            }
+            Self::Integer(value) => buf.put_i32(*value),
+            Self::Float(value) => buf.put_f32(*value),
+            Self::Long(value) => buf.put_i64(*value),
+            Self::Double(value) => buf.put_f64(*value),
+            Self::Utf8(string) => put_utf8(buf, &mutf8::encode(string))?,
+            Self::Utf16(units) => put_utf8(buf, &mutf8::encode_utf16(units.iter().copied()))?,
+            Self::Unusable => unreachable!(),
+        }
+        Ok(())
+    }
+
+    pub fn from_bytes(reader: &mut Reader) -> ParseResult<Self> {
+        let offset = reader.offset();
+        let constant = match reader.u8()? {
+            7 => Self::Class { name_index: reader.u16()? },
             9 => Self::FieldRef {
-                class_index: bytes.get_u16(),
-                name_and_type_index: bytes.get_u16(),
+                class_index: reader.u16()?,
+                name_and_type_index: reader.u16()?,
             },
             10 => Self::MethodRef {
-                class_index: bytes.get_u16(),
-                name_and_type_index: bytes.get_u16(),
+                class_index: reader.u16()?,
+                name_and_type_index: reader.u16()?,
             },
             11 => Self::InterfaceMethodRef {
-                class_index: bytes.get_u16(),
-                name_and_type_index: bytes.get_u16(),
+                class_index: reader.u16()?,
+                name_and_type_index: reader.u16()?,
             },
-            8 => Self::String { string_index: bytes.get_u16() },
-            3 => Self::Integer(bytes.get_i32()),
-            4 => Self::Float(bytes.get_f32()),
-            5 => Self::Long(bytes.get_i64()),
-            6 => Self::Double(bytes.get_f64()),
+            8 => Self::String { string_index: reader.u16()? },
+            3 => Self::Integer(reader.i32()?),
+            4 => Self::Float(reader.f32()?),
+            5 => Self::Long(reader.i64()?),
+            6 => Self::Double(reader.f64()?),
             12 => Self::NameAndType {
-                name_index: bytes.get_u16(),
-                descriptor_index: bytes.get_u16(),
+                name_index: reader.u16()?,
+                descriptor_index: reader.u16()?,
             },
             1 => {
-                let length = bytes.get_u16() as usize;
-                let mut buf = vec![0; length];
-                bytes.copy_to_slice(&mut buf);
-                let string = String::from_utf8(buf).unwrap();
-                Self::Utf8(Arc::from(string))
+                let length = reader.u16()? as usize;
+                let buf = reader.bytes(length)?;
+                let Some(decoded) = mutf8::decode(&buf) else {
+                    return Err(reader.error_at(offset, "malformed modified UTF-8"));
+                };
+                match decoded {
+                    mutf8::Decoded::Str(string) => Self::Utf8(Arc::from(string)),
+                    mutf8::Decoded::Utf16(units) => Self::Utf16(Arc::from(units)),
+                }
             }
             15 => Self::MethodHandle {
-                reference_kind: bytes.get_u8(),
-                reference_index: bytes.get_u16(),
+                reference_kind: reader.u8()?,
+                reference_index: reader.u16()?,
             },
-            16 => Self::MethodType { descriptor_index: bytes.get_u16(), },
+            16 => Self::MethodType { descriptor_index: reader.u16()?, },
             17 => Self::Dynamic {
-                bootstrap_method_attr_index: bytes.get_u16(),
-                name_and_type_index: bytes.get_u16(),
+                bootstrap_method_attr_index: reader.u16()?,
+                name_and_type_index: reader.u16()?,
             },
             18 => Self::InvokeDynamic {
-                bootstrap_method_attr_index: bytes.get_u16(),
-                name_and_type_index: bytes.get_u16(),
+                bootstrap_method_attr_index: reader.u16()?,
+                name_and_type_index: reader.u16()?,
             },
-            19 => Self::Module { name_index: bytes.get_u16() },
-            20 => Self::Package { name_index: bytes.get_u16() },
-            _ => panic!("invalid constant tag"),
-        }
+            19 => Self::Module { name_index: reader.u16()? },
+            20 => Self::Package { name_index: reader.u16()? },
+            tag => return Err(reader.error_at(offset, format!("invalid constant tag {tag}"))),
+        };
+        Ok(constant)
     }
-}
\ No newline at end of file
+}
+
+fn invalid(index: u16, expected: &'static str) -> Error {
+    Error::InvalidConstant { index, expected }
+}
+
+fn put_utf8(buf: &mut Vec<u8>, bytes: &[u8]) -> Result<()> {
+    let length = u16::try_from(bytes.len()).map_err(|_| Error::StringTooLong)?;
+    buf.put_u16(length);
+    buf.put_slice(bytes);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn adds_each_constant_once() {
+        let mut constant_pool = ConstantPool::new();
+        let hello = constant_pool.add_string("hello").unwrap();
+        let zero = constant_pool.add(Constant::Double(0.0)).unwrap();
+        let negative_zero = constant_pool.add(Constant::Double(-0.0)).unwrap();
+        assert_ne!(zero, negative_zero);
+        assert_eq!(constant_pool.add_string("hello").unwrap(), hello);
+        assert_eq!(constant_pool.add(Constant::Double(-0.0)).unwrap(), negative_zero);
+        assert_eq!(constant_pool.count(), 7);
+    }
+}
@@ -0,0 +1,211 @@
+//! Field and method descriptors (JVMS 4.3).
+
+use std::fmt::{Display, Formatter};
+use std::sync::Arc;
+
+/// The type of a field, parameter, local variable or value.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum FieldType {
+    Byte,
+    Char,
+    Double,
+    Float,
+    Int,
+    Long,
+    Short,
+    Boolean,
+    /// An instance of the named class or interface, e.g. `java/lang/String`.
+    Object(Arc<str>),
+    Array {
+        /// Between 1 and 255.
+        dimensions: u8,
+        /// Never itself an array.
+        element: Box<FieldType>,
+    },
+}
+
+/// The parameter and return types of a method.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct MethodDescriptor {
+    pub parameters: Vec<FieldType>,
+    /// `None` for `void`.
+    pub return_type: Option<FieldType>,
+}
+
+impl FieldType {
+    /// Parses a field descriptor such as `I` or `[Ljava/lang/String;`.
+    pub fn parse(descriptor: &str) -> Option<Self> {
+        match Self::parse_prefix(descriptor)? {
+            (field_type, "") => Some(field_type),
+            _ => None,
+        }
+    }
+
+    /// Parses the field descriptor at the start of `descriptor`, returning
+    /// it and the rest of the string.
+    fn parse_prefix(descriptor: &str) -> Option<(Self, &str)> {
+        let dimensions = descriptor.bytes().take_while(|&b| b == b'[').count();
+        let descriptor = &descriptor[dimensions..];
+
+        let (element, rest) = match descriptor.as_bytes().first()? {
+            b'B' => (Self::Byte, &descriptor[1..]),
+            b'C' => (Self::Char, &descriptor[1..]),
+            b'D' => (Self::Double, &descriptor[1..]),
+            b'F' => (Self::Float, &descriptor[1..]),
+            b'I' => (Self::Int, &descriptor[1..]),
+            b'J' => (Self::Long, &descriptor[1..]),
+            b'S' => (Self::Short, &descriptor[1..]),
+            b'Z' => (Self::Boolean, &descriptor[1..]),
+            b'L' => {
+                let end = descriptor.find(';')?;
+                let name = &descriptor[1..end];
+                if !is_binary_name(name) {
+                    return None;
+                }
+                (Self::Object(Arc::from(name)), &descriptor[end + 1..])
+            }
+            _ => return None,
+        };
+
+        let field_type = match dimensions {
+            0 => element,
+            1..=255 => Self::Array {
+                dimensions: dimensions as u8,
+                element: Box::new(element),
+            },
+            _ => return None,
+        };
+        Some((field_type, rest))
+    }
+
+    /// The number of local variable or operand stack slots a value of this
+    /// type takes up.
+    pub fn slots(&self) -> usize {
+        match self {
+            Self::Long | Self::Double => 2,
+            _ => 1,
+        }
+    }
+}
+
+impl MethodDescriptor {
+    /// Parses a method descriptor such as `([Ljava/lang/String;)V`.
+    pub fn parse(descriptor: &str) -> Option<Self> {
+        let mut rest = descriptor.strip_prefix('(')?;
+        let mut parameters = Vec::new();
+        while !rest.starts_with(')') {
+            let (parameter, tail) = FieldType::parse_prefix(rest)?;
+            parameters.push(parameter);
+            rest = tail;
+        }
+
+        let return_type = match &rest[1..] {
+            "V" => None,
+            rest => Some(FieldType::parse(rest)?),
+        };
+        Some(Self { parameters, return_type })
+    }
+
+    /// The number of local variable slots the parameters take up, not
+    /// counting `this`.
+    pub fn parameter_slots(&self) -> usize {
+        self.parameters.iter().map(FieldType::slots).sum()
+    }
+}
+
+/// Whether `name` is a class or interface name in internal form, e.g.
+/// `java/lang/Object` (JVMS 4.2.1).
+pub fn is_binary_name(name: &str) -> bool {
+    name.split('/').all(is_unqualified_name)
+}
+
+/// Whether `name` is a legal field, method, or local variable name
+/// (JVMS 4.2.2).
+pub fn is_unqualified_name(name: &str) -> bool {
+    !name.is_empty() && !name.contains(['.', ';', '[', '/'])
+}
+
+/// Whether `name` is a legal method name: an unqualified name without `<`
+/// or `>`, or one of `<init>` and `<clinit>`.
+pub fn is_method_name(name: &str) -> bool {
+    matches!(name, "<init>" | "<clinit>") || (is_unqualified_name(name) && !name.contains(['<', '>']))
+}
+
+impl Display for FieldType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Byte => write!(f, "B"),
+            Self::Char => write!(f, "C"),
+            Self::Double => write!(f, "D"),
+            Self::Float => write!(f, "F"),
+            Self::Int => write!(f, "I"),
+            Self::Long => write!(f, "J"),
+            Self::Short => write!(f, "S"),
+            Self::Boolean => write!(f, "Z"),
+            Self::Object(name) => write!(f, "L{name};"),
+            Self::Array { dimensions, element } => {
+                write!(f, "{}{element}", "[".repeat(*dimensions as usize))
+            }
+        }
+    }
+}
+
+impl Display for MethodDescriptor {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "(")?;
+        for parameter in &self.parameters {
+            write!(f, "{parameter}")?;
+        }
+        match &self.return_type {
+            Some(return_type) => write!(f, "){return_type}"),
+            None => write!(f, ")V"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_two_slots_for_long_and_double() {
+        let descriptor = MethodDescriptor::parse("(JIDLjava/lang/Object;[J[D)V").unwrap();
+        let slots = descriptor.parameters.iter().map(FieldType::slots).collect::<Vec<_>>();
+        assert_eq!(slots, [2, 1, 2, 1, 1, 1]);
+        assert_eq!(descriptor.parameter_slots(), 8);
+        assert_eq!(descriptor.return_type, None);
+    }
+
+    #[test]
+    fn round_trips_arrays_of_objects() {
+        let descriptor = "([[Ljava/lang/String;)[I";
+        let parsed = MethodDescriptor::parse(descriptor).unwrap();
+        assert_eq!(parsed.parameters, [FieldType::Array {
+            dimensions: 2,
+            element: Box::new(FieldType::Object(Arc::from("java/lang/String"))),
+        }]);
+        assert_eq!(parsed.to_string(), descriptor);
+    }
+
+    #[test]
+    fn rejects_void_as_a_field_type() {
+        assert_eq!(FieldType::parse("V"), None);
+        assert_eq!(FieldType::parse("[V"), None);
+        assert_eq!(MethodDescriptor::parse("(V)V"), None);
+        assert_eq!(MethodDescriptor::parse("()[V"), None);
+    }
+
+    #[test]
+    fn rejects_unterminated_class_names() {
+        assert_eq!(FieldType::parse("Ljava/lang/Object"), None);
+        assert_eq!(FieldType::parse("L;"), None);
+        assert_eq!(MethodDescriptor::parse("(Ljava/lang/Object)V"), None);
+        assert_eq!(MethodDescriptor::parse("(I"), None);
+    }
+
+    #[test]
+    fn rejects_more_than_255_dimensions() {
+        assert!(FieldType::parse(&format!("{}I", "[".repeat(255))).is_some());
+        assert_eq!(FieldType::parse(&format!("{}I", "[".repeat(256))), None);
+    }
+}
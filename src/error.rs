@@ -1,27 +1,109 @@
 use std::fmt::Display;
+use std::sync::Arc;
+use crate::check::FormatViolation;
+use crate::version::ClassVersion;
 
 pub type Result<T> = std::result::Result<T, Error>;
+pub type ParseResult<T> = std::result::Result<T, ClassFormatError>;
 
 #[derive(Debug)]
 pub enum Error {
-    ClassNotFound,
+    /// A class that could not be found, or that failed to link or
+    /// initialize earlier, corresponding to Java's `NoClassDefFoundError`.
+    NoClassDefFound { name: Arc<str>, reason: String },
+    /// A class that is its own superclass or superinterface.
+    ClassCircularity(Arc<str>),
+    /// A class whose supertypes are not the kinds of class it expects, such
+    /// as a class that implements another class.
+    IncompatibleClassChange(String),
+    /// A class that failed verification.
+    Verify(String),
+    /// A class or field that is referenced from a class it is not
+    /// accessible to.
+    IllegalAccess(String),
+    /// A field reference that no class in the hierarchy declares.
+    NoSuchField(String),
+    /// A class defined when a class of the same name is already loaded,
+    /// corresponding to Java's `LinkageError`.
+    DuplicateClass(Arc<str>),
     ClassNotRunnable,
     ClassNotMain,
     InvalidClass,
+    UnsupportedClassVersion(ClassVersion),
+    ClassFormat(ClassFormatError),
+    /// A branch target that is not the start of an instruction.
+    InvalidBranch,
+    /// A branch taken at run time, from the instruction at byte `offset` of
+    /// `method`, that does not land on the start of an instruction.
+    InvalidBranchTarget { method: String, offset: u32, delta: i32 },
+    /// A method with no `Code` attribute, such as an abstract or native
+    /// one, where code is needed.
+    MissingCode(String),
+    /// A conditional branch whose offset does not fit in 16 bits, where it
+    /// cannot be turned into a branch over a `goto_w`.
+    BranchOutOfRange,
+    /// A different number of branch offsets than the instruction has
+    /// targets, such as a `tableswitch` given too few.
+    WrongBranchCount { expected: usize, actual: usize },
+    /// Code longer than the 65535 bytes a method may have.
+    CodeTooLarge,
+    /// More than the 65535 constant pool entries a class may have.
+    ConstantPoolFull,
+    /// A string whose modified UTF-8 encoding is longer than 65535 bytes.
+    StringTooLong,
+    /// A constant pool index that does not name the kind of entry
+    /// `expected`, e.g. `"FieldRef"`.
+    InvalidConstant { index: u16, expected: &'static str },
+    InvalidDescriptor(Arc<str>),
+    InvalidSignature(Arc<str>),
+    /// A `StackMapTable` frame that chops more locals than there are, or
+    /// that does not fall on an instruction.
+    InvalidStackMapFrame { index: usize },
+
+    /// A JAR, zip or jimage file that is malformed or uses a feature we do
+    /// not support, such as encryption.
+    InvalidArchive(String),
+
+    /// A class that failed format checking, with every violation found.
+    FormatCheck(Vec<FormatViolation>),
 
     Io(std::io::Error),
 }
 
+/// A malformed class file, corresponding to Java's `ClassFormatError`.
+#[derive(Clone, Debug)]
+pub struct ClassFormatError {
+    /// Byte offset into the class file where the problem was found.
+    pub offset: usize,
+    /// The class file structure being parsed, e.g. `method_info`.
+    pub structure: &'static str,
+    pub reason: String,
+}
+
 impl Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{:#?}", self)
     }
 }
 
+impl Display for ClassFormatError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} at offset {:#x}: {}", self.structure, self.offset, self.reason)
+    }
+}
+
 impl From<std::io::Error> for Error {
     fn from(err: std::io::Error) -> Self {
         Self::Io(err)
     }
 }
 
+impl From<ClassFormatError> for Error {
+    fn from(err: ClassFormatError) -> Self {
+        Self::ClassFormat(err)
+    }
+}
+
 impl std::error::Error for Error {}
+
+impl std::error::Error for ClassFormatError {}
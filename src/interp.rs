@@ -1,81 +1,747 @@
+use std::cell::RefCell;
 use std::collections::HashMap;
 use std::path::Path;
 use std::sync::Arc;
 use std::task::Wake;
 use bytes::Bytes;
-use crate::class::Class;
+use crate::annotation;
+use crate::attribute::Attribute;
+use crate::class::{Class, ClassState, FieldAccessFlags, FieldIndex, MethodAccessFlags};
+use crate::constant::FieldRef;
+use crate::descriptor::FieldType;
+use crate::class_path::ClassPath;
 use crate::error::{Error, Result};
 use crate::thread::Thread;
 use crate::value::Value;
 
 pub struct InterpContext {
-    pub classes: HashMap<Arc<str>, Arc<Class>>,
+    /// Every class that has been loaded, whatever its state.
+    pub classes: RefCell<HashMap<Arc<str>, Arc<Class>>>,
+    /// Where classes that have not been loaded yet are looked for.
+    pub class_path: ClassPath,
+    /// Whether to run `Class::check_format` on each class as it is loaded,
+    /// rejecting classes with any violations.
+    pub check_format: bool,
+    /// The classes whose supertypes are being loaded, innermost last. A
+    /// class that is needed again before it is done is its own supertype.
+    loading: RefCell<Vec<Arc<str>>>,
+}
+
+/// What a thread has to do before it can use a class.
+pub enum Initialization {
+    /// Nothing: the class is initialized, or is being initialized by the
+    /// same thread further up its stack.
+    Done,
+    /// Wait for another thread that is initializing the class.
+    Wait,
+    /// Run the static initializers of these classes, in order. They are
+    /// all marked as being initialized by the thread.
+    Run(Vec<Arc<Class>>),
 }
 
 impl InterpContext {
-    pub fn new() -> Self {
+    pub fn new(class_path: ClassPath) -> Self {
         Self {
-            classes: HashMap::new(),
+            classes: RefCell::new(HashMap::new()),
+            class_path,
+            check_format: false,
+            loading: RefCell::new(Vec::new()),
+        }
+    }
+
+    /// The class with binary name `name`, loaded and linked.
+    pub fn class(&self, name: &str) -> Result<Arc<Class>> {
+        let class = self.load(name)?;
+        self.link(&class)?;
+        Ok(class)
+    }
+
+    /// Resolves a reference to the class `name` from `from` (JVMS 5.4.3.1),
+    /// which must be able to access it.
+    pub fn resolve_class(&self, from: &Class, name: &str) -> Result<Arc<Class>> {
+        let class = self.class(name)?;
+        if !class.is_public() && package_of(&class.name) != package_of(&from.name) {
+            return Err(Error::IllegalAccess(format!("{} cannot access {}", from.name, class.name)));
+        }
+        Ok(class)
+    }
+
+    /// Resolves a reference to a field from `from` (JVMS 5.4.3.2), giving
+    /// the class that declares the field and where it is stored. The field
+    /// is looked for in the referenced class, then its superinterfaces and
+    /// then its superclasses.
+    pub fn resolve_field(&self, from: &Class, field: &FieldRef) -> Result<(Arc<Class>, FieldIndex)> {
+        let class = self.resolve_class(from, &field.class)?;
+        let (declaring_class, index) = self.find_field(&class, &field.name, &field.descriptor)?
+            .ok_or_else(|| Error::NoSuchField(format!("{}.{}", field.class, field.name)))?;
+
+        let access = declaring_class.declared_field(index).unwrap().access;
+        let same_package = package_of(&declaring_class.name) == package_of(&from.name);
+        let accessible = if access.contains(FieldAccessFlags::Public) {
+            true
+        } else if access.contains(FieldAccessFlags::Private) {
+            declaring_class.name == from.name || self.are_nestmates(from, &declaring_class)
+        } else if access.contains(FieldAccessFlags::Protected) {
+            same_package || self.is_subclass(from, &declaring_class)?
+        } else {
+            same_package
+        };
+        if !accessible {
+            return Err(Error::IllegalAccess(format!(
+                "{} cannot access {}.{}", from.name, declaring_class.name, field.name,
+            )));
         }
+        Ok((declaring_class, index))
     }
 
-    pub fn class(&self, name: &str) -> Option<Arc<Class>> {
-        self.classes.get(name).cloned()
+    /// Whether `a` and `b` belong to the same nest (JVMS 5.4.4). A class
+    /// whose nest host cannot be loaded, is in another run-time package, or
+    /// does not list it, is treated as the only member of its own nest.
+    fn are_nestmates(&self, a: &Class, b: &Class) -> bool {
+        let host = b.nest_host_name();
+        if a.nest_host_name() != host {
+            return false;
+        }
+        let package = package_of(host);
+        if package_of(&a.name) != package || package_of(&b.name) != package {
+            return false;
+        }
+        self.load(host).is_ok_and(|host| a.is_nestmate_of(b, &host))
+    }
+
+    fn find_field(&self, class: &Arc<Class>, name: &str, field_type: &FieldType) -> Result<Option<(Arc<Class>, FieldIndex)>> {
+        if let Some(index) = class.declared_field_index(name, field_type) {
+            return Ok(Some((class.clone(), index)));
+        }
+        for interface in &class.interfaces {
+            let interface = self.class(interface)?;
+            if let Some(found) = self.find_field(&interface, name, field_type)? {
+                return Ok(Some(found));
+            }
+        }
+        match class.superclass(self)? {
+            Some(superclass) => self.find_field(&superclass, name, field_type),
+            None => Ok(None),
+        }
+    }
+
+    /// Whether `class` is `superclass` or one of its subclasses.
+    pub fn is_subclass(&self, class: &Class, superclass: &Class) -> Result<bool> {
+        if class.name == superclass.name {
+            return Ok(true);
+        }
+        match class.superclass(self)? {
+            Some(parent) => self.is_subclass(&parent, superclass),
+            None => Ok(false),
+        }
+    }
+
+    /// The class with binary name `name`, loading it and its superclass and
+    /// superinterfaces from the class path if it has not been loaded yet
+    /// (JVMS 5.3).
+    pub fn load(&self, name: &str) -> Result<Arc<Class>> {
+        if let Some(class) = self.classes.borrow().get(name) {
+            return Ok(class.clone());
+        }
+        if self.loading.borrow().iter().any(|loading| loading.as_ref() == name) {
+            return Err(Error::ClassCircularity(name.into()));
+        }
+
+        let bytes = self.class_path.find_class(name)?.ok_or_else(|| Error::NoClassDefFound {
+            name: name.into(),
+            reason: "not found on the class path".to_owned(),
+        })?;
+        let class = self.parse(bytes)?;
+        if class.name.as_ref() != name {
+            return Err(Error::NoClassDefFound {
+                name: name.into(),
+                reason: format!("the class file declares {}", class.name),
+            });
+        }
+        self.derive(class)
+    }
+
+    /// Parses a class file and adds the class it declares, loading its
+    /// superclass and superinterfaces.
+    pub fn define_class(&self, bytes: Bytes) -> Result<Arc<Class>> {
+        let class = self.parse(bytes)?;
+        if self.classes.borrow().contains_key(&class.name) {
+            return Err(Error::DuplicateClass(class.name.clone()));
+        }
+        self.derive(class)
+    }
+
+    fn parse(&self, bytes: Bytes) -> Result<Class> {
+        let class = Class::parse(bytes)?;
+        if self.check_format {
+            let violations = class.check_format();
+            if !violations.is_empty() {
+                return Err(Error::FormatCheck(violations));
+            }
+        }
+        Ok(class)
+    }
+
+    /// Loads the supertypes of a parsed class and records it as loaded
+    /// (JVMS 5.3.5).
+    fn derive(&self, class: Class) -> Result<Arc<Class>> {
+        self.loading.borrow_mut().push(class.name.clone());
+        let supertypes = self.load_supertypes(&class);
+        self.loading.borrow_mut().pop();
+        supertypes?;
+
+        let class = Arc::new(class);
+        self.classes.borrow_mut().insert(class.name.clone(), class.clone());
+        Ok(class)
+    }
+
+    fn load_supertypes(&self, class: &Class) -> Result<()> {
+        if let Some(superclass) = &class.superclass_name {
+            let superclass = self.load(superclass)?;
+            if superclass.is_interface() {
+                return Err(Error::IncompatibleClassChange(format!(
+                    "class {} has interface {} as its superclass", class.name, superclass.name,
+                )));
+            }
+            check_permitted(class, &superclass)?;
+        }
+        for interface in &class.interfaces {
+            let interface = self.load(interface)?;
+            if !interface.is_interface() {
+                return Err(Error::IncompatibleClassChange(format!(
+                    "{} cannot implement {}, which is not an interface", class.name, interface.name,
+                )));
+            }
+            check_permitted(class, &interface)?;
+        }
+        Ok(())
+    }
+
+    /// Verifies and prepares `class` after linking its supertypes (JVMS
+    /// 5.4). Symbolic references are resolved lazily, when they are used.
+    /// If any step fails, the class is left erroneous, and every later
+    /// attempt fails too.
+    pub fn link(&self, class: &Arc<Class>) -> Result<()> {
+        match class.state.get() {
+            ClassState::Loaded => {}
+            ClassState::Erroneous => return Err(Error::NoClassDefFound {
+                name: class.name.clone(),
+                reason: "linking failed earlier".to_owned(),
+            }),
+            _ => return Ok(()),
+        }
+
+        if let Err(err) = self.link_steps(class) {
+            class.state.set(ClassState::Erroneous);
+            return Err(err);
+        }
+        class.state.set(ClassState::Linked);
+        Ok(())
+    }
+
+    /// The work of `link`, for a class that has not been linked yet.
+    fn link_steps(&self, class: &Arc<Class>) -> Result<()> {
+        let superclass = class.superclass(self)?;
+        if let Some(superclass) = &superclass {
+            if superclass.is_final() {
+                return Err(Error::Verify(format!(
+                    "{} cannot inherit from final class {}", class.name, superclass.name,
+                )));
+            }
+        }
+        for interface in &class.interfaces {
+            self.class(interface)?;
+        }
+
+        verify(class)?;
+        prepare(class, superclass.as_deref());
+        Ok(())
+    }
+
+    /// Starts initializing `class` for the thread with id `thread` (JVMS
+    /// 5.5), after linking it if needed. A class is initialized after its
+    /// superclass and the superinterfaces that declare default methods, so
+    /// several static initializers may have to run.
+    pub fn initialize(&self, class: &Arc<Class>, thread: usize) -> Result<Initialization> {
+        let mut order = Vec::new();
+        if !self.plan_initialization(class, thread, &mut order)? {
+            return Ok(Initialization::Wait);
+        }
+        if order.is_empty() {
+            return Ok(Initialization::Done);
+        }
+        for class in &order {
+            class.state.set(ClassState::Initializing(thread));
+            assign_constant_values(class);
+        }
+        Ok(Initialization::Run(order))
+    }
+
+    /// Marks `class` as initialized, once its static initializer returns.
+    pub fn finish_initialization(&self, class: &Class) {
+        class.state.set(ClassState::Initialized);
+    }
+
+    /// Appends `class` and the supertypes it needs initialized first to
+    /// `order`, unless they are done. Returns `false` if another thread is
+    /// initializing any of them.
+    fn plan_initialization(&self, class: &Arc<Class>, thread: usize, order: &mut Vec<Arc<Class>>) -> Result<bool> {
+        match class.state.get() {
+            ClassState::Initialized => return Ok(true),
+            ClassState::Initializing(initializer) => return Ok(initializer == thread),
+            ClassState::Erroneous => return Err(Error::NoClassDefFound {
+                name: class.name.clone(),
+                reason: "it is in an erroneous state from an earlier failure".to_owned(),
+            }),
+            ClassState::Loaded | ClassState::Linked => {}
+        }
+        if order.iter().any(|planned| Arc::ptr_eq(planned, class)) {
+            return Ok(true);
+        }
+        self.link(class)?;
+
+        if !class.is_interface() {
+            if let Some(superclass) = class.superclass(self)? {
+                if !self.plan_initialization(&superclass, thread, order)? {
+                    return Ok(false);
+                }
+            }
+            for interface in self.superinterfaces(class)? {
+                let has_default_methods = interface.methods.iter()
+                    .any(|method| !method.access.contains(MethodAccessFlags::Abstract));
+                if has_default_methods && !self.plan_initialization(&interface, thread, order)? {
+                    return Ok(false);
+                }
+            }
+        }
+        order.push(class.clone());
+        Ok(true)
+    }
+
+    /// The direct and indirect superinterfaces of `class`, not counting
+    /// those of its superclasses, each before its own superinterfaces.
+    fn superinterfaces(&self, class: &Class) -> Result<Vec<Arc<Class>>> {
+        let mut interfaces: Vec<Arc<Class>> = Vec::new();
+        let mut pending: Vec<Arc<str>> = class.interfaces.iter().rev().cloned().collect();
+        while let Some(name) = pending.pop() {
+            if interfaces.iter().any(|interface| interface.name == name) {
+                continue;
+            }
+            let interface = self.class(&name)?;
+            pending.extend(interface.interfaces.iter().rev().cloned());
+            interfaces.push(interface);
+        }
+        Ok(interfaces)
     }
 }
 
+/// Checks that `supertype`, if it is sealed, permits `class` to extend or
+/// implement it (JVMS 5.3.5). Modules are not modelled, so the rule that
+/// both are in the same run-time module always holds.
+fn check_permitted(class: &Class, supertype: &Class) -> Result<()> {
+    let Some(permitted) = supertype.permitted_subclasses() else {
+        return Ok(());
+    };
+    if !class.is_public() && package_of(&class.name) != package_of(&supertype.name) {
+        return Err(Error::IncompatibleClassChange(format!(
+            "{} is not public and not in the package of sealed {}", class.name, supertype.name,
+        )));
+    }
+    if !permitted.contains(&class.name) {
+        return Err(Error::IncompatibleClassChange(format!(
+            "{} is not a permitted subclass of sealed {}", class.name, supertype.name,
+        )));
+    }
+    Ok(())
+}
+
+/// Checks the parts of `class` we are able to verify: that each method's
+/// `StackMapTable` is well formed and describes its instructions.
+fn verify(class: &Class) -> Result<()> {
+    decode_attributes(&class.attributes)?;
+    for field in class.static_fields.iter().chain(&class.fields) {
+        decode_attributes(&field.attributes)?;
+    }
+    for method in class.static_methods.iter().chain(&class.methods) {
+        decode_attributes(&method.attributes)?;
+        if let Some(code) = method.code() {
+            decode_attributes(&code.attributes)?;
+        }
+        if !class.version.allows_jsr() && method.uses_jsr() {
+            return Err(Error::Verify(format!(
+                "{}.{}{} uses jsr or ret, which class file version {} does not allow",
+                class.name, method.name, method.descriptor, class.version,
+            )));
+        }
+    }
+
+    if class.version.major < 50 {
+        return Ok(());
+    }
+    for method in class.static_methods.iter().chain(&class.methods) {
+        if method.stack_map_frames(&class.name).is_err() {
+            return Err(Error::Verify(format!(
+                "{}.{}{} has an invalid StackMapTable", class.name, method.name, method.descriptor,
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Decodes each of `attributes`, which parsing the class left undecoded,
+/// failing on the first that is malformed. Annotations are left for whoever
+/// asks for them.
+fn decode_attributes(attributes: &[Attribute]) -> Result<()> {
+    for attribute in attributes.iter().filter(|attribute| !annotation::is_annotation_attribute(&attribute.name)) {
+        attribute.try_kind()?;
+    }
+    Ok(())
+}
+
+/// Gives every static field its default value and lays out the instance
+/// fields (JVMS 5.4.2).
+fn prepare(class: &Class, superclass: Option<&Class>) {
+    class.lay_out_fields(superclass);
+    for (field, value) in class.static_fields.iter().zip(&class.static_values) {
+        value.replace(Value::default_for(&field.field_type));
+    }
+}
+
+/// Assigns static fields with a `ConstantValue` attribute their constant,
+/// as the first step of initialization. String constants are left null
+/// until there are string objects.
+fn assign_constant_values(class: &Class) {
+    for (field, value) in class.static_fields.iter().zip(&class.static_values) {
+        let constant = field.constant_value().and_then(|index| class.constant_pool.get(index));
+        if let Some(constant) = constant.and_then(|constant| Value::from_constant(constant, &field.field_type)) {
+            value.replace(constant);
+        }
+    }
+}
+
+/// The package part of a binary class name, e.g. `java/lang` for
+/// `java/lang/Object`.
+fn package_of(name: &str) -> &str {
+    name.rsplit_once('/').map_or("", |(package, _)| package)
+}
+
 pub struct Interp {
     pub context: InterpContext,
     pub threads: Vec<Thread>,
 }
 
 impl Interp {
-    pub fn new() -> Self {
+    pub fn new(class_path: ClassPath) -> Self {
         Self {
-            context: InterpContext::new(),
+            context: InterpContext::new(class_path),
             threads: Vec::new(),
         }
     }
 
+    /// Loads the class in the class file `file`, whether or not it is on
+    /// the class path.
     pub fn load_class<P: AsRef<Path>>(&mut self, file: P) -> Result<()> {
         let bytes = std::fs::read(file)?;
-        let mut bytes = Bytes::from(bytes);
-        let class = match Class::parse(&mut bytes) {
-            Ok(class) => class,
-            Err(_) => return Err(Error::ClassNotFound),
-        };
-
-        let class = Arc::new(class);
-        let name = class.name();
-
-        self.context.classes.insert(name.into(), class);
-
+        self.context.define_class(Bytes::from(bytes))?;
         Ok(())
     }
 
     pub fn new_thread_runnable<C: AsRef<str>>(&mut self, class_name: C) -> Result<()> {
-        let class = self.context.class(class_name.as_ref()).ok_or(Error::ClassNotFound)?;
+        let class = self.context.class(class_name.as_ref())?;
         let method_index = class.method_index("run", "()V").ok_or(Error::ClassNotRunnable)?;
-        let thread = Thread::new(class, method_index);
+        let thread = Thread::new(self.threads.len(), class, method_index);
         self.threads.push(thread);
         Ok(())
     }
 
     pub fn new_thread_main<C: AsRef<str>>(&mut self, class_name: C) -> Result<()> {
-        let class = self.context.class(class_name.as_ref()).ok_or(Error::ClassNotFound)?;
+        let class = self.context.class(class_name.as_ref())?;
         let method_index = class
             .static_method_index("main", "([Ljava/lang/String;)V")
             .ok_or(Error::ClassNotMain)?;
-        let thread = Thread::new(class, method_index);
+        // The main class is initialized before `main` is invoked.
+        let mut thread = Thread::new(self.threads.len(), class.clone(), method_index);
+        thread.initialize(&self.context, &class)?;
         self.threads.push(thread);
         Ok(())
     }
 
+    /// Runs every thread, an instruction at a time, until they have all
+    /// finished.
     pub fn run(&mut self) -> Result<()> {
-        loop {
-            for thread in self.threads.iter_mut() {
+        while !self.threads.iter().all(Thread::is_finished) {
+            for thread in self.threads.iter_mut().filter(|thread| !thread.is_finished()) {
                 thread.exec_one(&mut self.context)?;
             }
         }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use bytes::BufMut;
+    use crate::class::{ClassAccessFlags, Field};
+    use crate::class_path::ClassPathEntry;
+    use crate::class_path::tests::scratch_directory;
+    use crate::constant::{Constant, ConstantPool};
+    use crate::version::ClassVersion;
+    use crate::writer::ClassWriter;
+    use super::*;
+
+    const VERSION: ClassVersion = ClassVersion::new(61, 0);
+
+    /// A directory of class files for a test to load from, with a
+    /// `java/lang/Object` of its own.
+    struct Classes {
+        directory: PathBuf,
+    }
+
+    impl Classes {
+        fn new(test: &str) -> Self {
+            let classes = Self { directory: scratch_directory(test) };
+            classes.define("java/lang/Object", ClassAccessFlags::Public, None, &[], |_| {});
+            classes
+        }
+
+        /// Writes the class file of `name`, with `build` adding its members
+        /// and attributes.
+        fn define(
+            &self,
+            name: &str,
+            access: ClassAccessFlags,
+            superclass: Option<&str>,
+            interfaces: &[&str],
+            build: impl FnOnce(&mut ClassWriter),
+        ) {
+            let mut writer = ClassWriter::new(VERSION, ConstantPool::new());
+            writer.set_access_flags(access);
+            writer.set_name(name).unwrap();
+            writer.set_superclass_name(superclass).unwrap();
+            for interface in interfaces {
+                writer.add_interface(interface).unwrap();
+            }
+            build(&mut writer);
+            let path = self.directory.join(format!("{name}.class"));
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, writer.finish().unwrap()).unwrap();
+        }
+
+        /// Writes a public class with no members.
+        fn class(&self, name: &str, superclass: &str) {
+            self.define(name, ClassAccessFlags::Public | ClassAccessFlags::Super, Some(superclass), &[], |_| {});
+        }
+
+        fn context(&self) -> InterpContext {
+            let mut class_path = ClassPath::new();
+            class_path.push(ClassPathEntry::Directory(self.directory.clone()));
+            InterpContext::new(class_path)
+        }
+    }
+
+    impl Drop for Classes {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.directory);
+        }
+    }
+
+    fn attribute(writer: &mut ClassWriter, name: &str, info: Vec<u8>) -> Attribute {
+        let name_index = writer.constant_pool.add_utf8(name).unwrap();
+        Attribute::new(name_index, Bytes::from(info), Arc::new(writer.constant_pool.clone()), VERSION).unwrap()
+    }
+
+    /// Adds a class attribute whose body is a list of classes, such as
+    /// `PermittedSubclasses`.
+    fn add_class_list(writer: &mut ClassWriter, name: &str, classes: &[&str]) {
+        let mut info = Vec::new();
+        info.put_u16(classes.len() as u16);
+        for class in classes {
+            info.put_u16(writer.constant_pool.add_class(class).unwrap());
+        }
+        let attribute = attribute(writer, name, info);
+        writer.add_attribute(&attribute).unwrap();
+    }
+
+    fn add_field(writer: &mut ClassWriter, access: FieldAccessFlags, name: &str, descriptor: &str, attributes: Vec<Attribute>) {
+        writer.add_field(&Field {
+            name: Arc::from(name),
+            typ: Arc::from(descriptor),
+            field_type: FieldType::parse(descriptor).unwrap(),
+            access,
+            attributes,
+            position: 0,
+        }).unwrap();
+    }
+
+    fn field_ref(class: &str, name: &str, descriptor: &str) -> FieldRef {
+        FieldRef {
+            class: Arc::from(class),
+            name: Arc::from(name),
+            descriptor: FieldType::parse(descriptor).unwrap(),
+        }
+    }
+
+    #[test]
+    fn reports_a_class_that_is_its_own_superclass() {
+        let classes = Classes::new("circularity");
+        classes.class("A", "B");
+        classes.class("B", "A");
+        let context = classes.context();
+
+        for _ in 0..2 {
+            assert!(matches!(context.load("A"), Err(Error::ClassCircularity(name)) if name.as_ref() == "A"));
+        }
+        assert!(matches!(context.load("B"), Err(Error::ClassCircularity(name)) if name.as_ref() == "B"));
+        assert!(context.classes.borrow().keys().all(|name| name.as_ref() == "java/lang/Object"));
+    }
+
+    #[test]
+    fn reports_a_missing_superclass_as_no_class_def_found() {
+        let classes = Classes::new("missing-superclass");
+        classes.class("A", "Missing");
+        let context = classes.context();
+
+        let err = context.load("A").unwrap_err();
+        assert!(matches!(&err, Error::NoClassDefFound { name, .. } if name.as_ref() == "Missing"), "{err:?}");
+        assert!(!context.classes.borrow().contains_key("A"));
+    }
+
+    #[test]
+    fn leaves_a_class_that_fails_to_link_erroneous() {
+        let classes = Classes::new("erroneous");
+        classes.define("Final", ClassAccessFlags::Public | ClassAccessFlags::Final, Some("java/lang/Object"), &[], |_| {});
+        classes.class("Sub", "Final");
+        let context = classes.context();
+
+        let sub = context.load("Sub").unwrap();
+        assert!(matches!(context.link(&sub), Err(Error::Verify(_))));
+        assert_eq!(sub.state.get(), ClassState::Erroneous);
+        assert!(matches!(context.link(&sub), Err(Error::NoClassDefFound { name, .. }) if name.as_ref() == "Sub"));
+        assert!(matches!(context.initialize(&sub, 0), Err(Error::NoClassDefFound { name, .. }) if name.as_ref() == "Sub"));
+        assert!(matches!(context.class("Sub"), Err(Error::NoClassDefFound { .. })));
+    }
+
+    #[test]
+    fn only_derives_the_permitted_subclasses_of_sealed_types() {
+        let classes = Classes::new("sealed");
+        classes.define("Shape", ClassAccessFlags::Public | ClassAccessFlags::Abstract, Some("java/lang/Object"), &[], |writer| {
+            add_class_list(writer, "PermittedSubclasses", &["Circle", "p/Hidden"]);
+        });
+        let interface = ClassAccessFlags::Public | ClassAccessFlags::Interface | ClassAccessFlags::Abstract;
+        classes.define("Named", interface, Some("java/lang/Object"), &[], |writer| {
+            add_class_list(writer, "PermittedSubclasses", &["Circle"]);
+        });
+        classes.define("Circle", ClassAccessFlags::Public | ClassAccessFlags::Final, Some("Shape"), &["Named"], |_| {});
+        classes.class("Square", "Shape");
+        classes.define("Label", ClassAccessFlags::Public, Some("java/lang/Object"), &["Named"], |_| {});
+        // Permitted, but neither public nor in the package of `Shape`.
+        classes.define("p/Hidden", ClassAccessFlags::Final, Some("Shape"), &[], |_| {});
+        let context = classes.context();
+
+        context.class("Circle").unwrap();
+        for name in ["Square", "Label", "p/Hidden"] {
+            let err = context.load(name).unwrap_err();
+            assert!(matches!(err, Error::IncompatibleClassChange(_)), "{name}: {err:?}");
+            assert!(!context.classes.borrow().contains_key(name));
+        }
+    }
+
+    /// `A` declares `a`, `b` and a static `VALUE`; `B` extends `A`,
+    /// implements `I`, which has the constant `VALUE`, and declares `c`; `C`
+    /// extends `B` and declares another `a` and `d`.
+    fn hierarchy(test: &str) -> Classes {
+        let classes = Classes::new(test);
+        let public = FieldAccessFlags::Public;
+        let class = ClassAccessFlags::Public | ClassAccessFlags::Super;
+        let interface = ClassAccessFlags::Public | ClassAccessFlags::Interface | ClassAccessFlags::Abstract;
+        classes.define("I", interface, Some("java/lang/Object"), &[], |writer| {
+            let constant = writer.constant_pool.add(Constant::Integer(1)).unwrap();
+            let constant_value = attribute(writer, "ConstantValue", constant.to_be_bytes().to_vec());
+            let flags = public | FieldAccessFlags::Static | FieldAccessFlags::Final;
+            add_field(writer, flags, "VALUE", "I", vec![constant_value]);
+        });
+        classes.define("A", class, Some("java/lang/Object"), &[], |writer| {
+            add_field(writer, public, "a", "I", Vec::new());
+            add_field(writer, public | FieldAccessFlags::Static, "VALUE", "I", Vec::new());
+            add_field(writer, public, "b", "J", Vec::new());
+        });
+        classes.define("B", class, Some("A"), &["I"], |writer| {
+            add_field(writer, public, "c", "I", Vec::new());
+        });
+        classes.define("C", class, Some("B"), &[], |writer| {
+            add_field(writer, public, "a", "I", Vec::new());
+            add_field(writer, public, "d", "Ljava/lang/Object;", Vec::new());
+        });
+        classes
+    }
+
+    #[test]
+    fn lays_out_superclass_fields_first() {
+        let classes = hierarchy("field-slots");
+        let context = classes.context();
+        let slots = |name: &str| context.class(name).unwrap().field_slots().iter()
+            .map(|slot| format!("{}.{}", slot.class, slot.name))
+            .collect::<Vec<_>>();
+
+        assert_eq!(slots("A"), ["A.a", "A.b"]);
+        assert_eq!(slots("B"), ["A.a", "A.b", "B.c"]);
+        assert_eq!(slots("C"), ["A.a", "A.b", "B.c", "C.a", "C.d"]);
+        assert!(slots("I").is_empty());
+
+        // `C.a` hides `A.a`, which keeps its slot.
+        let c = context.class("C").unwrap();
+        assert!(matches!(c.field_index("a", "I"), Some(FieldIndex::Dynamic(3))));
+        assert!(matches!(c.field_index("b", "J"), Some(FieldIndex::Dynamic(1))));
+    }
+
+    #[test]
+    fn resolves_fields_in_superinterfaces_before_superclasses() {
+        let classes = hierarchy("field-resolution");
+        let context = classes.context();
+        let c = context.class("C").unwrap();
+        let resolve = |class: &str, name: &str, descriptor: &str| {
+            let (declaring_class, index) = context.resolve_field(&c, &field_ref(class, name, descriptor)).unwrap();
+            (declaring_class.name.to_string(), index)
+        };
+
+        assert!(matches!(resolve("C", "VALUE", "I"), (class, FieldIndex::Static(0)) if class == "I"));
+        assert!(matches!(resolve("A", "VALUE", "I"), (class, FieldIndex::Static(0)) if class == "A"));
+        assert!(matches!(resolve("C", "a", "I"), (class, FieldIndex::Dynamic(3)) if class == "C"));
+        assert!(matches!(resolve("B", "a", "I"), (class, FieldIndex::Dynamic(0)) if class == "A"));
+        assert!(matches!(resolve("C", "b", "J"), (class, FieldIndex::Dynamic(1)) if class == "A"));
+        assert!(matches!(
+            context.resolve_field(&c, &field_ref("C", "b", "I")),
+            Err(Error::NoSuchField(_)),
+        ));
+    }
+
+    #[test]
+    fn only_nestmates_in_the_package_of_the_host_share_private_fields() {
+        let classes = Classes::new("nestmates");
+        let class = ClassAccessFlags::Public | ClassAccessFlags::Super;
+        let secret = |writer: &mut ClassWriter, host: &str| {
+            let host = writer.constant_pool.add_class(host).unwrap();
+            let nest_host = attribute(writer, "NestHost", host.to_be_bytes().to_vec());
+            writer.add_attribute(&nest_host).unwrap();
+            add_field(writer, FieldAccessFlags::Private, "secret", "I", Vec::new());
+        };
+        classes.define("p/Host", class, Some("java/lang/Object"), &[], |writer| {
+            add_class_list(writer, "NestMembers", &["p/Host$Inner", "q/Stray"]);
+        });
+        classes.define("p/Host$Inner", class, Some("java/lang/Object"), &[], |writer| secret(writer, "p/Host"));
+        classes.define("q/Stray", class, Some("java/lang/Object"), &[], |writer| secret(writer, "p/Host"));
+        let context = classes.context();
+
+        let host = context.class("p/Host").unwrap();
+        let inner = context.class("p/Host$Inner").unwrap();
+        let stray = context.class("q/Stray").unwrap();
+        assert!(context.resolve_field(&host, &field_ref("p/Host$Inner", "secret", "I")).is_ok());
+        // The host lists `q/Stray`, but it is in another package, so it is
+        // in a nest of its own.
+        for (from, class) in [(&host, "q/Stray"), (&inner, "q/Stray"), (&stray, "p/Host$Inner")] {
+            let result = context.resolve_field(from, &field_ref(class, "secret", "I"));
+            assert!(matches!(result, Err(Error::IllegalAccess(_))), "{} to {class}", from.name);
+        }
     }
 }
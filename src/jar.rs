@@ -0,0 +1,572 @@
+//! Reading classes and resources from JAR files, which are zip archives
+//! with an optional manifest at `META-INF/MANIFEST.MF`.
+
+use std::collections::HashMap;
+use std::fmt::{Debug, Formatter};
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use bytes::{Buf, Bytes};
+use crate::error::{Error, Result};
+use crate::version::ClassVersion;
+
+const LOCAL_HEADER: u32 = 0x04034b50;
+const CENTRAL_HEADER: u32 = 0x02014b50;
+const END_OF_CENTRAL_DIRECTORY: u32 = 0x06054b50;
+const ZIP64_END_OF_CENTRAL_DIRECTORY: u32 = 0x06064b50;
+const ZIP64_LOCATOR: u32 = 0x07064b50;
+const ZIP64_EXTRA_FIELD: u16 = 0x0001;
+
+const STORED: u16 = 0;
+const DEFLATED: u16 = 8;
+/// General purpose flag bit marking an encrypted entry.
+const ENCRYPTED: u16 = 0x0001;
+
+/// The Java SE release whose entries are used from a multi-release JAR,
+/// which is the newest release whose class files this VM can load.
+const RELEASE: u16 = ClassVersion::MAX_MAJOR - 44;
+/// The first release that looks in `META-INF/versions`.
+const FIRST_VERSIONED_RELEASE: u16 = 9;
+
+pub struct Jar {
+    pub path: PathBuf,
+    source: Source,
+    entries: HashMap<String, Entry>,
+    manifest: Option<Manifest>,
+}
+
+/// Where the bytes of an archive are read from.
+enum Source {
+    Bytes(Bytes),
+    /// The archive file and its length, read from only where an entry or
+    /// the central directory is.
+    File(Mutex<File>, u64),
+}
+
+/// Where to find an entry's data, from its central directory record.
+#[derive(Copy, Clone, Debug)]
+struct Entry {
+    method: u16,
+    flags: u16,
+    compressed_size: u64,
+    uncompressed_size: u64,
+    local_header_offset: u64,
+}
+
+/// The main section of a JAR manifest.
+#[derive(Clone, Debug, Default)]
+pub struct Manifest {
+    pub attributes: Vec<(String, String)>,
+}
+
+impl Jar {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let bytes = std::fs::read(path)?;
+        Self::from_bytes(path.to_owned(), Bytes::from(bytes))
+    }
+
+    /// Like `open`, but only the central directory is read up front and
+    /// each entry is read from the file when it is asked for. This suits
+    /// large archives of which few entries are used, such as the JMOD
+    /// files of a JDK.
+    pub fn open_on_demand<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let file = File::open(path)?;
+        let length = file.metadata()?.len();
+        Self::from_source(path.to_owned(), Source::File(Mutex::new(file), length))
+    }
+
+    /// Reads the central directory of the archive in `bytes`, which was
+    /// read from `path`.
+    pub fn from_bytes(path: PathBuf, bytes: Bytes) -> Result<Self> {
+        Self::from_source(path, Source::Bytes(bytes))
+    }
+
+    fn from_source(path: PathBuf, source: Source) -> Result<Self> {
+        let entries = read_central_directory(&source, |reason| {
+            Error::InvalidArchive(format!("{}: {reason}", path.display()))
+        })?;
+        let mut jar = Self {
+            path,
+            source,
+            entries,
+            manifest: None,
+        };
+        if let Some(manifest) = jar.entry("META-INF/MANIFEST.MF")? {
+            jar.manifest = Some(Manifest::parse(&String::from_utf8_lossy(&manifest)));
+        }
+        Ok(jar)
+    }
+
+    pub fn manifest(&self) -> Option<&Manifest> {
+        self.manifest.as_ref()
+    }
+
+    pub fn is_multi_release(&self) -> bool {
+        self.manifest.as_ref().is_some_and(Manifest::is_multi_release)
+    }
+
+    /// The contents of the resource at `path`. In a multi-release JAR, the
+    /// newest version under `META-INF/versions` that this VM supports is
+    /// preferred to the unversioned one.
+    pub fn find_resource(&self, path: &str) -> Result<Option<Bytes>> {
+        if self.is_multi_release() && !path.starts_with("META-INF/") {
+            for release in (FIRST_VERSIONED_RELEASE..=RELEASE).rev() {
+                if let Some(bytes) = self.entry(&format!("META-INF/versions/{release}/{path}"))? {
+                    return Ok(Some(bytes));
+                }
+            }
+        }
+        self.entry(path)
+    }
+
+    /// The uncompressed contents of the entry named exactly `name`.
+    pub fn entry(&self, name: &str) -> Result<Option<Bytes>> {
+        match self.entries.get(name) {
+            Some(entry) => self.read(name, entry).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// The names of every file in the archive.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.entries.keys().map(String::as_str)
+    }
+
+    fn read(&self, name: &str, entry: &Entry) -> Result<Bytes> {
+        let invalid = |reason: &str| Error::InvalidArchive(format!("{}: {name}: {reason}", self.path.display()));
+
+        if entry.flags & ENCRYPTED != 0 {
+            return Err(invalid("encrypted entries are not supported"));
+        }
+        let header = self.source.read(entry.local_header_offset, 30)?
+            .ok_or_else(|| invalid("local header is out of bounds"))?;
+        let mut header = &header[..];
+        if header.get_u32_le() != LOCAL_HEADER {
+            return Err(invalid("bad local header signature"));
+        }
+        header.advance(22);
+        let name_length = header.get_u16_le() as u64;
+        let extra_length = header.get_u16_le() as u64;
+
+        let start = entry.local_header_offset + 30 + name_length + extra_length;
+        let data = self.source.read(start, entry.compressed_size)?
+            .ok_or_else(|| invalid("data is out of bounds"))?;
+
+        let contents = match entry.method {
+            STORED => data,
+            DEFLATED => {
+                let limit = usize::try_from(entry.uncompressed_size).map_err(|_| invalid("entry is too large"))?;
+                miniz_oxide::inflate::decompress_to_vec_with_limit(&data, limit)
+                    .map(Bytes::from)
+                    .map_err(|err| invalid(&format!("inflate failed: {err}")))?
+            }
+            method => return Err(invalid(&format!("unsupported compression method {method}"))),
+        };
+        if contents.len() as u64 != entry.uncompressed_size {
+            return Err(invalid("size does not match the central directory"));
+        }
+        Ok(contents)
+    }
+}
+
+impl Source {
+    fn len(&self) -> u64 {
+        match self {
+            Self::Bytes(bytes) => bytes.len() as u64,
+            Self::File(_, length) => *length,
+        }
+    }
+
+    /// The `length` bytes at `offset`, or `None` if the archive ends
+    /// before them.
+    fn read(&self, offset: u64, length: u64) -> Result<Option<Bytes>> {
+        let Some(end) = offset.checked_add(length).filter(|&end| end <= self.len()) else {
+            return Ok(None);
+        };
+        match self {
+            Self::Bytes(bytes) => Ok(Some(bytes.slice(offset as usize..end as usize))),
+            Self::File(file, _) => {
+                let mut contents = vec![0; length as usize];
+                let mut file = file.lock().unwrap();
+                file.seek(SeekFrom::Start(offset))?;
+                file.read_exact(&mut contents)?;
+                Ok(Some(Bytes::from(contents)))
+            }
+        }
+    }
+}
+
+impl Debug for Jar {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Jar")
+            .field("path", &self.path)
+            .field("entries", &self.entries.len())
+            .field("manifest", &self.manifest)
+            .finish()
+    }
+}
+
+impl Manifest {
+    /// Parses the main section of a manifest, which runs up to the first
+    /// blank line. Lines starting with a space continue the previous value.
+    pub fn parse(text: &str) -> Self {
+        let mut attributes: Vec<(String, String)> = Vec::new();
+        for line in text.lines() {
+            if line.is_empty() {
+                break;
+            }
+            if let Some(continuation) = line.strip_prefix(' ') {
+                if let Some((_, value)) = attributes.last_mut() {
+                    value.push_str(continuation);
+                }
+            } else if let Some((name, value)) = line.split_once(':') {
+                attributes.push((name.trim().to_owned(), value.trim_start().to_owned()));
+            }
+        }
+        Self { attributes }
+    }
+
+    /// The value of the main attribute `name`, which is matched ignoring
+    /// ASCII case.
+    pub fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes.iter()
+            .find(|(attribute, _)| attribute.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// The binary name of the class to run with `-jar`, e.g. `com/acme/Main`.
+    pub fn main_class(&self) -> Option<String> {
+        self.attribute("Main-Class").map(|class| class.trim().replace('.', "/"))
+    }
+
+    /// The paths of further JARs and directories to put on the class path,
+    /// relative to the directory of the JAR unless they are absolute. The
+    /// attribute lists them as URLs, so entries with a scheme other than
+    /// `file:` are left out.
+    pub fn class_path(&self) -> impl Iterator<Item = PathBuf> + '_ {
+        self.attribute("Class-Path").unwrap_or_default()
+            .split_ascii_whitespace()
+            .filter_map(url_path)
+    }
+
+    pub fn is_multi_release(&self) -> bool {
+        self.attribute("Multi-Release").is_some_and(|value| value.trim().eq_ignore_ascii_case("true"))
+    }
+}
+
+/// The file path a relative or `file:` URL names, with percent-encoded
+/// bytes decoded, e.g. `lib/my%20lib.jar` or `file:/opt/lib/a.jar`.
+fn url_path(url: &str) -> Option<PathBuf> {
+    let path = match url.split_once(':') {
+        Some((scheme, path)) if scheme.eq_ignore_ascii_case("file") => match path.strip_prefix("//") {
+            // The authority, usually empty, names the host.
+            Some(authority_and_path) => &authority_and_path[authority_and_path.find('/')?..],
+            None => path,
+        },
+        Some((scheme, _)) if is_url_scheme(scheme) => return None,
+        _ => url,
+    };
+
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut at = 0;
+    while let Some(&byte) = bytes.get(at) {
+        let escaped = match (byte, bytes.get(at + 1..at + 3)) {
+            (b'%', Some(&[high, low])) => hex_value(high).zip(hex_value(low)).map(|(high, low)| high << 4 | low),
+            _ => None,
+        };
+        match escaped {
+            Some(escaped) => {
+                decoded.push(escaped);
+                at += 3;
+            }
+            None => {
+                decoded.push(byte);
+                at += 1;
+            }
+        }
+    }
+    String::from_utf8(decoded).ok().map(PathBuf::from)
+}
+
+fn hex_value(digit: u8) -> Option<u8> {
+    (digit as char).to_digit(16).map(|value| value as u8)
+}
+
+/// Whether `scheme` has the form of a URL scheme (RFC 3986 section 3.1).
+fn is_url_scheme(scheme: &str) -> bool {
+    scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+        && scheme.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'))
+}
+
+fn read_central_directory(source: &Source, invalid: impl Fn(&str) -> Error) -> Result<HashMap<String, Entry>> {
+    // The end of central directory record is followed by a comment of up
+    // to 65535 bytes, so it has to be searched for from the end. The zip64
+    // locator, if there is one, comes right before it.
+    let length = source.len();
+    let tail_start = length.saturating_sub(20 + 22 + u16::MAX as u64);
+    let tail = source.read(tail_start, length - tail_start)?.unwrap_or_default();
+    let search_start = tail.len().saturating_sub(22 + u16::MAX as usize);
+    let end = (search_start..tail.len().saturating_sub(21)).rev()
+        .find(|&at| tail[at..].starts_with(&END_OF_CENTRAL_DIRECTORY.to_le_bytes()))
+        .ok_or_else(|| invalid("no end of central directory record"))?;
+
+    let mut record = &tail[end + 10..];
+    let mut count = record.get_u16_le() as u64;
+    let mut size = record.get_u32_le() as u64;
+    let mut offset = record.get_u32_le() as u64;
+
+    let mut directory_end = tail_start + end as u64;
+    if count == u16::MAX as u64 || size == u32::MAX as u64 || offset == u32::MAX as u64 {
+        let zip64_end = end.checked_sub(20)
+            .map(|at| &tail[at..])
+            .filter(|locator| locator.starts_with(&ZIP64_LOCATOR.to_le_bytes()))
+            .map(|mut locator| {
+                locator.advance(8);
+                locator.get_u64_le()
+            });
+        let record = match zip64_end {
+            Some(at) => source.read(at, 56)?.map(|record| (at, record)),
+            None => None,
+        };
+        let Some((at, record)) = record.filter(|(_, record)| record.starts_with(&ZIP64_END_OF_CENTRAL_DIRECTORY.to_le_bytes())) else {
+            return Err(invalid("missing zip64 end of central directory record"));
+        };
+        let mut record = &record[32..];
+        count = record.get_u64_le();
+        size = record.get_u64_le();
+        offset = record.get_u64_le();
+        directory_end = at;
+    }
+
+    // The central directory ends where the end record starts. Any data in
+    // front of the archive, such as the header of a JMOD file, shifts the
+    // offsets recorded in it.
+    let directory_start = directory_end.checked_sub(size)
+        .ok_or_else(|| invalid("central directory is out of bounds"))?;
+    let prefix = directory_start.checked_sub(offset)
+        .ok_or_else(|| invalid("central directory is out of bounds"))?;
+    let directory = source.read(directory_start, size)?
+        .ok_or_else(|| invalid("central directory is out of bounds"))?;
+    let mut directory = &directory[..];
+
+    let mut entries = HashMap::new();
+    for _ in 0..count {
+        if directory.remaining() < 46 || directory.get_u32_le() != CENTRAL_HEADER {
+            return Err(invalid("bad central directory entry"));
+        }
+        directory.advance(4);
+        let flags = directory.get_u16_le();
+        let method = directory.get_u16_le();
+        directory.advance(8);
+        let mut compressed_size = directory.get_u32_le() as u64;
+        let mut uncompressed_size = directory.get_u32_le() as u64;
+        let name_length = directory.get_u16_le() as usize;
+        let extra_length = directory.get_u16_le() as usize;
+        let comment_length = directory.get_u16_le() as usize;
+        directory.advance(8);
+        let mut local_header_offset = directory.get_u32_le() as u64;
+
+        if directory.remaining() < name_length + extra_length + comment_length {
+            return Err(invalid("central directory entry is truncated"));
+        }
+        let name = String::from_utf8_lossy(&directory[..name_length]).into_owned();
+        let mut extra = &directory[name_length..name_length + extra_length];
+        directory.advance(name_length + extra_length + comment_length);
+
+        // Sizes and offsets that do not fit in 32 bits are stored in the
+        // zip64 extra field instead, in this order, when saturated.
+        while extra.remaining() >= 4 {
+            let id = extra.get_u16_le();
+            let length = (extra.get_u16_le() as usize).min(extra.remaining());
+            let mut field = &extra[..length];
+            extra.advance(length);
+            if id != ZIP64_EXTRA_FIELD {
+                continue;
+            }
+            for value in [&mut uncompressed_size, &mut compressed_size, &mut local_header_offset] {
+                if *value == u32::MAX as u64 && field.remaining() >= 8 {
+                    *value = field.get_u64_le();
+                }
+            }
+        }
+
+        if !name.ends_with('/') {
+            entries.insert(name, Entry {
+                method,
+                flags,
+                compressed_size,
+                uncompressed_size,
+                local_header_offset: local_header_offset.saturating_add(prefix),
+            });
+        }
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use bytes::BufMut;
+    use super::*;
+
+    /// A zip archive of `entries`, each a name, its contents and whether to
+    /// deflate it. With `zip64`, sizes and offsets are only given in the
+    /// zip64 extra fields and end of central directory record.
+    pub(crate) fn zip(entries: &[(&str, &[u8], bool)], zip64: bool) -> Vec<u8> {
+        let saturated = |value: usize| if zip64 { u32::MAX } else { value as u32 };
+        let mut archive = Vec::new();
+        let mut directory = Vec::new();
+        for &(name, contents, deflate) in entries {
+            let data = if deflate { miniz_oxide::deflate::compress_to_vec(contents, 6) } else { contents.to_vec() };
+            let method = if deflate { DEFLATED } else { STORED };
+            let offset = archive.len();
+
+            archive.put_u32_le(LOCAL_HEADER);
+            archive.put_u16_le(45);
+            archive.put_u16_le(0);
+            archive.put_u16_le(method);
+            archive.put_u32_le(0);
+            archive.put_u32_le(0);
+            archive.put_u32_le(saturated(data.len()));
+            archive.put_u32_le(saturated(contents.len()));
+            archive.put_u16_le(name.len() as u16);
+            archive.put_u16_le(if zip64 { 20 } else { 0 });
+            archive.put_slice(name.as_bytes());
+            if zip64 {
+                archive.put_u16_le(ZIP64_EXTRA_FIELD);
+                archive.put_u16_le(16);
+                archive.put_u64_le(contents.len() as u64);
+                archive.put_u64_le(data.len() as u64);
+            }
+            archive.put_slice(&data);
+
+            directory.put_u32_le(CENTRAL_HEADER);
+            directory.put_u16_le(45);
+            directory.put_u16_le(45);
+            directory.put_u16_le(0);
+            directory.put_u16_le(method);
+            directory.put_u32_le(0);
+            directory.put_u32_le(0);
+            directory.put_u32_le(saturated(data.len()));
+            directory.put_u32_le(saturated(contents.len()));
+            directory.put_u16_le(name.len() as u16);
+            directory.put_u16_le(if zip64 { 28 } else { 0 });
+            directory.put_u16_le(0);
+            directory.put_slice(&[0; 8]);
+            directory.put_u32_le(saturated(offset));
+            directory.put_slice(name.as_bytes());
+            if zip64 {
+                directory.put_u16_le(ZIP64_EXTRA_FIELD);
+                directory.put_u16_le(24);
+                directory.put_u64_le(contents.len() as u64);
+                directory.put_u64_le(data.len() as u64);
+                directory.put_u64_le(offset as u64);
+            }
+        }
+
+        let directory_offset = archive.len();
+        archive.put_slice(&directory);
+        if zip64 {
+            let record_offset = archive.len();
+            archive.put_u32_le(ZIP64_END_OF_CENTRAL_DIRECTORY);
+            archive.put_u64_le(44);
+            archive.put_u16_le(45);
+            archive.put_u16_le(45);
+            archive.put_u32_le(0);
+            archive.put_u32_le(0);
+            archive.put_u64_le(entries.len() as u64);
+            archive.put_u64_le(entries.len() as u64);
+            archive.put_u64_le(directory.len() as u64);
+            archive.put_u64_le(directory_offset as u64);
+            archive.put_u32_le(ZIP64_LOCATOR);
+            archive.put_u32_le(0);
+            archive.put_u64_le(record_offset as u64);
+            archive.put_u32_le(1);
+        }
+        let count = if zip64 { u16::MAX } else { entries.len() as u16 };
+        archive.put_u32_le(END_OF_CENTRAL_DIRECTORY);
+        archive.put_u32_le(0);
+        archive.put_u16_le(count);
+        archive.put_u16_le(count);
+        archive.put_u32_le(saturated(directory.len()));
+        archive.put_u32_le(saturated(directory_offset));
+        archive.put_u16_le(0);
+        archive
+    }
+
+    fn jar(bytes: Vec<u8>) -> Jar {
+        Jar::from_bytes(PathBuf::from("test.jar"), Bytes::from(bytes)).unwrap()
+    }
+
+    #[test]
+    fn reads_stored_and_deflated_entries() {
+        let text = "the same line over and over\n".repeat(100);
+        for zip64 in [false, true] {
+            let jar = jar(zip(&[("stored.txt", b"stored", false), ("deflated.txt", text.as_bytes(), true)], zip64));
+            assert_eq!(jar.entry("stored.txt").unwrap().unwrap(), &b"stored"[..]);
+            assert_eq!(jar.entry("deflated.txt").unwrap().unwrap(), text.as_bytes());
+            assert!(jar.entry("missing.txt").unwrap().is_none());
+        }
+    }
+
+    #[test]
+    fn decodes_class_path_urls() {
+        let manifest = Manifest::parse(
+            "Class-Path: lib/my%20lib.jar file:/opt/lib/a%2Bb.jar file:///opt/c.jar\n  classes/ 100%.jar http://example.com/d.jar\n",
+        );
+        assert_eq!(manifest.class_path().collect::<Vec<_>>(), [
+            PathBuf::from("lib/my lib.jar"),
+            PathBuf::from("/opt/lib/a+b.jar"),
+            PathBuf::from("/opt/c.jar"),
+            PathBuf::from("classes/"),
+            PathBuf::from("100%.jar"),
+        ]);
+    }
+
+    #[test]
+    fn reads_archives_after_a_prefix() {
+        // A JMOD file is a zip archive behind a four byte header.
+        let mut jmod = b"JM\x01\x00".to_vec();
+        jmod.extend(zip(&[("classes/A.class", b"A", true)], false));
+        assert_eq!(jar(jmod).entry("classes/A.class").unwrap().unwrap(), &b"A"[..]);
+    }
+
+    #[test]
+    fn reads_entries_from_the_file_on_demand() {
+        let path = std::env::temp_dir().join(format!("jvm-{}-on-demand.jmod", std::process::id()));
+        let text = "the same line over and over\n".repeat(100);
+        let entries: &[(&str, &[u8], bool)] = &[("classes/A.class", b"A", false), ("classes/B.class", text.as_bytes(), true)];
+        let mut jmod = b"JM\x01\x00".to_vec();
+        jmod.extend(zip(entries, false));
+        for archive in [jmod, zip(entries, true)] {
+            std::fs::write(&path, archive).unwrap();
+            let jar = Jar::open_on_demand(&path).unwrap();
+            assert_eq!(jar.entry("classes/A.class").unwrap().unwrap(), &b"A"[..]);
+            assert_eq!(jar.entry("classes/B.class").unwrap().unwrap(), text.as_bytes());
+            assert!(jar.entry("classes/C.class").unwrap().is_none());
+        }
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn prefers_versioned_entries_in_multi_release_jars() {
+        let future = format!("META-INF/versions/{}/A.class", RELEASE + 1);
+        let entries = |multi_release: &'static [u8]| [
+            ("META-INF/MANIFEST.MF", multi_release, false),
+            ("A.class", b"base", false),
+            ("META-INF/versions/9/A.class", b"9", false),
+            (future.as_str(), b"future", false),
+            ("B.class", b"base", false),
+        ];
+        let multi_release = jar(zip(&entries(b"Multi-Release: true\n"), false));
+        assert!(multi_release.is_multi_release());
+        assert_eq!(multi_release.find_resource("A.class").unwrap().unwrap(), &b"9"[..]);
+        assert_eq!(multi_release.find_resource("B.class").unwrap().unwrap(), &b"base"[..]);
+
+        let plain = jar(zip(&entries(b"Manifest-Version: 1.0\n"), false));
+        assert_eq!(plain.find_resource("A.class").unwrap().unwrap(), &b"base"[..]);
+    }
+}
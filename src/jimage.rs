@@ -0,0 +1,486 @@
+//! Reading resources from a jimage, the `lib/modules` file in which a JDK
+//! since Java 9 ships the classes of its modules.
+//!
+//! A jimage starts with an index: a header, a perfect hash table mapping
+//! resource names such as `/java.base/java/lang/Object.class` to location
+//! records, and a table of strings the records refer to. Resource contents
+//! follow the index. Multi-byte values use the byte order of the platform
+//! that wrote the image, which the magic number tells apart.
+
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use bytes::Bytes;
+use crate::error::{Error, Result};
+
+const MAGIC: u32 = 0xCAFEDADA;
+const MAJOR_VERSION: u32 = 1;
+const HEADER_SIZE: usize = 28;
+const HASH_MULTIPLIER: u32 = 0x01000193;
+
+/// The magic number of the header in front of a compressed resource.
+const COMPRESSED_MAGIC: u32 = 0xCAFEFAFA;
+const COMPRESSED_HEADER_SIZE: usize = 29;
+
+// Kinds of location attribute.
+const ATTRIBUTE_END: usize = 0;
+const ATTRIBUTE_MODULE: usize = 1;
+const ATTRIBUTE_PARENT: usize = 2;
+const ATTRIBUTE_BASE: usize = 3;
+const ATTRIBUTE_EXTENSION: usize = 4;
+const ATTRIBUTE_OFFSET: usize = 5;
+const ATTRIBUTE_COMPRESSED: usize = 6;
+const ATTRIBUTE_UNCOMPRESSED: usize = 7;
+const ATTRIBUTE_COUNT: usize = 8;
+
+pub struct JImage {
+    pub path: PathBuf,
+    /// Resource contents are only read when asked for, since the image of
+    /// a full JDK is well over 100 MB.
+    file: Mutex<File>,
+    index: Bytes,
+    big_endian: bool,
+    table_length: usize,
+    locations_start: usize,
+    strings_start: usize,
+}
+
+impl JImage {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref().to_owned();
+        let mut file = File::open(&path)?;
+        let invalid = |reason: &str| Error::InvalidArchive(format!("{}: {reason}", path.display()));
+
+        let mut header = [0; HEADER_SIZE];
+        file.read_exact(&mut header)?;
+        let big_endian = match header[..4].try_into().unwrap() {
+            magic if u32::from_le_bytes(magic) == MAGIC => false,
+            magic if u32::from_be_bytes(magic) == MAGIC => true,
+            _ => return Err(invalid("bad magic number")),
+        };
+        let field = |n: usize| {
+            let bytes = header[n * 4..n * 4 + 4].try_into().unwrap();
+            if big_endian { u32::from_be_bytes(bytes) } else { u32::from_le_bytes(bytes) }
+        };
+        if field(1) >> 16 != MAJOR_VERSION {
+            return Err(invalid(&format!("unsupported version {}.{}", field(1) >> 16, field(1) & 0xFFFF)));
+        }
+        let table_length = field(4) as usize;
+        let locations_size = field(5) as usize;
+        let strings_size = field(6) as usize;
+
+        let locations_start = HEADER_SIZE + table_length * 8;
+        let strings_start = locations_start + locations_size;
+        let mut index = vec![0; strings_start + strings_size];
+        index[..HEADER_SIZE].copy_from_slice(&header);
+        file.read_exact(&mut index[HEADER_SIZE..])?;
+
+        Ok(Self {
+            path,
+            file: Mutex::new(file),
+            index: Bytes::from(index),
+            big_endian,
+            table_length,
+            locations_start,
+            strings_start,
+        })
+    }
+
+    /// The contents of the resource at `path` relative to the root of its
+    /// module, such as `java/lang/Object.class`.
+    pub fn find_resource(&self, path: &str) -> Result<Option<Bytes>> {
+        let Some((package, _)) = path.rsplit_once('/') else {
+            return Ok(None);
+        };
+        match self.package_module(package) {
+            Some(module) => self.resource(&format!("/{module}/{path}")),
+            None => Ok(None),
+        }
+    }
+
+    /// The module that contains `package`, e.g. `java.base` for
+    /// `java/lang`.
+    pub fn package_module(&self, package: &str) -> Option<&str> {
+        // The image describes each package with a resource listing every
+        // module it appears in and whether the package is empty there.
+        let location = self.find_location(&format!("/packages/{}", package.replace('/', ".")))?;
+        let contents = self.read(&location).ok()?;
+        contents.chunks_exact(8)
+            .find(|entry| self.u32(&entry[..4]) == 0)
+            .and_then(|entry| self.string(self.u32(&entry[4..]) as usize))
+    }
+
+    /// The contents of the resource with the full name `name`, such as
+    /// `/java.base/java/lang/Object.class`.
+    pub fn resource(&self, name: &str) -> Result<Option<Bytes>> {
+        match self.find_location(name) {
+            Some(location) => self.read(&location).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Looks `name` up in the perfect hash table, whose slots hold either a
+    /// seed to rehash with or the index of the entry directly.
+    fn find_location(&self, name: &str) -> Option<[u64; ATTRIBUTE_COUNT]> {
+        if self.table_length == 0 {
+            return None;
+        }
+        let slot = hash(name, HASH_MULTIPLIER) as usize % self.table_length;
+        let index = match self.u32(&self.index[HEADER_SIZE + slot * 4..]) as i32 {
+            0 => return None,
+            redirect if redirect < 0 => (-1 - redirect) as usize,
+            seed => hash(name, seed as u32) as usize % self.table_length,
+        };
+        let offsets_start = HEADER_SIZE + self.table_length * 4;
+        let offset = self.u32(&self.index[offsets_start + index * 4..]) as usize;
+        let location = self.location(offset)?;
+        (self.location_name(&location)? == name).then_some(location)
+    }
+
+    fn location(&self, offset: usize) -> Option<[u64; ATTRIBUTE_COUNT]> {
+        let mut attributes = [0; ATTRIBUTE_COUNT];
+        let mut bytes = self.index.get(self.locations_start + offset..self.strings_start)?;
+        loop {
+            let (&first, rest) = bytes.split_first()?;
+            let kind = (first >> 3) as usize;
+            if kind == ATTRIBUTE_END {
+                return Some(attributes);
+            }
+            let length = (first & 7) as usize + 1;
+            let value = rest.get(..length)?;
+            *attributes.get_mut(kind)? = value.iter().fold(0, |value, &byte| value << 8 | byte as u64);
+            bytes = &rest[length..];
+        }
+    }
+
+    fn location_name(&self, location: &[u64; ATTRIBUTE_COUNT]) -> Option<String> {
+        let module = self.string(location[ATTRIBUTE_MODULE] as usize)?;
+        let parent = self.string(location[ATTRIBUTE_PARENT] as usize)?;
+        let base = self.string(location[ATTRIBUTE_BASE] as usize)?;
+        let extension = self.string(location[ATTRIBUTE_EXTENSION] as usize)?;
+
+        let mut name = String::new();
+        if !module.is_empty() {
+            name += &format!("/{module}/");
+        }
+        if !parent.is_empty() {
+            name += &format!("{parent}/");
+        }
+        name += base;
+        if !extension.is_empty() {
+            name += &format!(".{extension}");
+        }
+        Some(name)
+    }
+
+    fn read(&self, location: &[u64; ATTRIBUTE_COUNT]) -> Result<Bytes> {
+        let compressed_size = location[ATTRIBUTE_COMPRESSED];
+        let size = if compressed_size != 0 { compressed_size } else { location[ATTRIBUTE_UNCOMPRESSED] };
+        let mut contents = vec![0; size as usize];
+        {
+            let mut file = self.file.lock().unwrap();
+            file.seek(SeekFrom::Start(self.index.len() as u64 + location[ATTRIBUTE_OFFSET]))?;
+            file.read_exact(&mut contents)?;
+        }
+        if compressed_size == 0 {
+            return Ok(Bytes::from(contents));
+        }
+        self.decompress(contents)
+    }
+
+    /// Undoes the compression `jlink --compress` applies, which may be
+    /// stacked several times, each with its own header.
+    fn decompress(&self, mut contents: Vec<u8>) -> Result<Bytes> {
+        let invalid = |reason: &str| Error::InvalidArchive(format!("{}: {reason}", self.path.display()));
+
+        while contents.len() >= COMPRESSED_HEADER_SIZE && self.u32(&contents) == COMPRESSED_MAGIC {
+            let compressed_size = self.u64(&contents[4..]) as usize;
+            let uncompressed_size = self.u64(&contents[12..]) as usize;
+            let decompressor = self.string(self.u32(&contents[20..]) as usize)
+                .ok_or_else(|| invalid("bad decompressor name"))?;
+            let data = contents.get(COMPRESSED_HEADER_SIZE..COMPRESSED_HEADER_SIZE + compressed_size)
+                .ok_or_else(|| invalid("compressed resource is truncated"))?;
+            contents = match decompressor {
+                "zip" => miniz_oxide::inflate::decompress_to_vec_zlib_with_limit(data, uncompressed_size)
+                    .map_err(|err| invalid(&format!("inflate failed: {err}")))?,
+                _ => return Err(invalid(&format!("unsupported decompressor {decompressor:?}"))),
+            };
+        }
+        Ok(Bytes::from(contents))
+    }
+
+    /// The NUL-terminated string at `offset` in the strings table.
+    fn string(&self, offset: usize) -> Option<&str> {
+        let strings = self.index.get(self.strings_start + offset..)?;
+        let end = strings.iter().position(|&byte| byte == 0)?;
+        std::str::from_utf8(&strings[..end]).ok()
+    }
+
+    fn u32(&self, bytes: &[u8]) -> u32 {
+        let bytes = bytes[..4].try_into().unwrap();
+        if self.big_endian { u32::from_be_bytes(bytes) } else { u32::from_le_bytes(bytes) }
+    }
+
+    fn u64(&self, bytes: &[u8]) -> u64 {
+        let bytes = bytes[..8].try_into().unwrap();
+        if self.big_endian { u64::from_be_bytes(bytes) } else { u64::from_le_bytes(bytes) }
+    }
+}
+
+impl std::fmt::Debug for JImage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("JImage")
+            .field("path", &self.path)
+            .field("resources", &self.table_length)
+            .finish()
+    }
+}
+
+/// The hash of the UTF-8 bytes of `name` that the image's table is built
+/// with.
+fn hash(name: &str, seed: u32) -> u32 {
+    name.bytes().fold(seed, |hash, byte| hash.wrapping_mul(HASH_MULTIPLIER) ^ byte as u32) & 0x7FFF_FFFF
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use super::*;
+
+    /// Builds a jimage in memory, laid out as `jlink` lays it out.
+    struct ImageBuilder {
+        big_endian: bool,
+        strings: Vec<u8>,
+        string_offsets: HashMap<String, u32>,
+        /// Each resource's name, its contents as stored, and its size once
+        /// decompressed if it is compressed.
+        resources: Vec<(String, Vec<u8>, Option<usize>)>,
+    }
+
+    impl ImageBuilder {
+        fn new(big_endian: bool) -> Self {
+            let mut builder = Self { big_endian, strings: Vec::new(), string_offsets: HashMap::new(), resources: Vec::new() };
+            builder.string("");
+            builder
+        }
+
+        fn u32(&self, value: u32) -> [u8; 4] {
+            if self.big_endian { value.to_be_bytes() } else { value.to_le_bytes() }
+        }
+
+        fn u64(&self, value: u64) -> [u8; 8] {
+            if self.big_endian { value.to_be_bytes() } else { value.to_le_bytes() }
+        }
+
+        fn string(&mut self, string: &str) -> u32 {
+            if let Some(&offset) = self.string_offsets.get(string) {
+                return offset;
+            }
+            let offset = self.strings.len() as u32;
+            self.strings.extend_from_slice(string.as_bytes());
+            self.strings.push(0);
+            self.string_offsets.insert(string.to_owned(), offset);
+            offset
+        }
+
+        fn resource(&mut self, name: &str, contents: &[u8]) {
+            self.resources.push((name.to_owned(), contents.to_vec(), None));
+        }
+
+        /// Adds a resource compressed `layers` times over with the `zip`
+        /// decompressor.
+        fn compressed(&mut self, name: &str, contents: &[u8], layers: usize) {
+            let decompressor = self.string("zip");
+            let mut stored = contents.to_vec();
+            for _ in 0..layers {
+                let data = miniz_oxide::deflate::compress_to_vec_zlib(&stored, 6);
+                let mut layer = Vec::new();
+                layer.extend(self.u32(COMPRESSED_MAGIC));
+                layer.extend(self.u64(data.len() as u64));
+                layer.extend(self.u64(stored.len() as u64));
+                layer.extend(self.u32(decompressor));
+                layer.extend(self.u32(0));
+                layer.push(1);
+                layer.extend(data);
+                stored = layer;
+            }
+            self.resources.push((name.to_owned(), stored, Some(contents.len())));
+        }
+
+        /// Adds the `/packages/` resource listing the modules `package`
+        /// is in, each with whether the package is empty there.
+        fn package(&mut self, package: &str, modules: &[(&str, bool)]) {
+            let mut contents = Vec::new();
+            for &(module, empty) in modules {
+                let module = self.string(module);
+                contents.extend(self.u32(empty as u32));
+                contents.extend(self.u32(module));
+            }
+            self.resource(&format!("/packages/{package}"), &contents);
+        }
+
+        /// The image, and the slots of its hash table.
+        fn build(mut self) -> (Vec<u8>, Vec<i32>) {
+            let mut locations = Vec::new();
+            let mut location_offsets = Vec::new();
+            let mut contents = Vec::new();
+            for (name, stored, uncompressed) in std::mem::take(&mut self.resources) {
+                let (module, path) = name[1..].split_once('/').unwrap();
+                let (parent, file) = path.rsplit_once('/').unwrap_or(("", path));
+                let (base, extension) = file.rsplit_once('.').unwrap_or((file, ""));
+                let mut attributes = [0; ATTRIBUTE_COUNT];
+                attributes[ATTRIBUTE_MODULE] = self.string(module) as u64;
+                attributes[ATTRIBUTE_PARENT] = self.string(parent) as u64;
+                attributes[ATTRIBUTE_BASE] = self.string(base) as u64;
+                attributes[ATTRIBUTE_EXTENSION] = self.string(extension) as u64;
+                attributes[ATTRIBUTE_OFFSET] = contents.len() as u64;
+                match uncompressed {
+                    Some(size) => {
+                        attributes[ATTRIBUTE_COMPRESSED] = stored.len() as u64;
+                        attributes[ATTRIBUTE_UNCOMPRESSED] = size as u64;
+                    }
+                    None => attributes[ATTRIBUTE_UNCOMPRESSED] = stored.len() as u64,
+                }
+                contents.extend(stored);
+
+                location_offsets.push((name, locations.len() as u32));
+                for (kind, &value) in attributes.iter().enumerate().skip(1) {
+                    if value != 0 {
+                        locations.push((kind << 3) as u8 | 7);
+                        locations.extend(value.to_be_bytes());
+                    }
+                }
+                locations.push(0);
+            }
+
+            // Names whose hashes collide share a bucket that holds a seed
+            // to rehash them with, and the rest of the buckets point
+            // straight at their entry.
+            let length = location_offsets.len();
+            let mut buckets = vec![Vec::new(); length];
+            for (name, offset) in &location_offsets {
+                buckets[hash(name, HASH_MULTIPLIER) as usize % length].push((name.as_str(), *offset));
+            }
+            let mut redirects = vec![0; length];
+            let mut offsets = vec![None; length];
+            let mut by_size = (0..length).collect::<Vec<_>>();
+            by_size.sort_by_key(|&bucket| std::cmp::Reverse(buckets[bucket].len()));
+            for bucket in by_size {
+                match buckets[bucket][..] {
+                    [] => {}
+                    [(_, offset)] => {
+                        let slot = offsets.iter().position(Option::is_none).unwrap();
+                        offsets[slot] = Some(offset);
+                        redirects[bucket] = -1 - slot as i32;
+                    }
+                    ref entries => {
+                        let seed = (1..).find(|&seed| {
+                            let mut slots = entries.iter()
+                                .map(|(name, _)| hash(name, seed) as usize % length)
+                                .collect::<Vec<_>>();
+                            slots.sort();
+                            slots.dedup();
+                            slots.len() == entries.len() && slots.iter().all(|&slot| offsets[slot].is_none())
+                        }).unwrap();
+                        for &(name, offset) in entries {
+                            offsets[hash(name, seed) as usize % length] = Some(offset);
+                        }
+                        redirects[bucket] = seed as i32;
+                    }
+                }
+            }
+
+            let mut image = Vec::new();
+            image.extend(self.u32(MAGIC));
+            image.extend(self.u32(MAJOR_VERSION << 16));
+            image.extend(self.u32(0));
+            image.extend(self.u32(length as u32));
+            image.extend(self.u32(length as u32));
+            image.extend(self.u32(locations.len() as u32));
+            image.extend(self.u32(self.strings.len() as u32));
+            for &redirect in &redirects {
+                image.extend(self.u32(redirect as u32));
+            }
+            for offset in &offsets {
+                image.extend(self.u32(offset.unwrap()));
+            }
+            image.extend(locations);
+            image.extend(&self.strings);
+            image.extend(contents);
+            (image, redirects)
+        }
+    }
+
+    fn open(name: &str, image: &[u8]) -> JImage {
+        let path = std::env::temp_dir().join(format!("jvm-{}-{name}", std::process::id()));
+        std::fs::write(&path, image).unwrap();
+        let image = JImage::open(&path).unwrap();
+        // The open file handle is all the image needs from here on.
+        std::fs::remove_file(path).unwrap();
+        image
+    }
+
+    const CLASSES: [&str; 12] = [
+        "java/lang/Object", "java/lang/String", "java/lang/System", "java/lang/Thread",
+        "java/util/List", "java/util/Map", "java/util/HashMap", "java/util/ArrayList",
+        "java/io/File", "java/io/Reader", "java/io/Writer", "java/io/InputStream",
+    ];
+
+    #[test]
+    fn finds_resources_in_either_byte_order() {
+        for big_endian in [false, true] {
+            let mut builder = ImageBuilder::new(big_endian);
+            for class in CLASSES {
+                builder.resource(&format!("/java.base/{class}.class"), class.as_bytes());
+            }
+            for package in ["java.lang", "java.util", "java.io"] {
+                builder.package(package, &[("java.base", false)]);
+            }
+            let (bytes, redirects) = builder.build();
+            // Both ways of finding an entry from its bucket are covered.
+            assert!(redirects.iter().any(|&redirect| redirect < 0));
+            assert!(redirects.iter().any(|&redirect| redirect > 0));
+
+            let image = open(&format!("order-{big_endian}"), &bytes);
+            assert_eq!(image.big_endian, big_endian);
+            for class in CLASSES {
+                assert_eq!(image.find_resource(&format!("{class}.class")).unwrap().unwrap(), class.as_bytes(), "{class}");
+            }
+            assert_eq!(image.resource("/java.base/java/lang/Object.class").unwrap().unwrap(), &b"java/lang/Object"[..]);
+            assert!(image.find_resource("java/lang/Missing.class").unwrap().is_none());
+            assert!(image.find_resource("javax/swing/JFrame.class").unwrap().is_none());
+            assert!(image.resource("/java.base/java/lang/Object").unwrap().is_none());
+        }
+    }
+
+    #[test]
+    fn looks_packages_up_in_the_module_that_is_not_empty() {
+        let mut builder = ImageBuilder::new(false);
+        builder.resource("/java.sql/java/sql/Driver.class", b"Driver");
+        builder.package("java.sql", &[("java.base", true), ("java.sql", false)]);
+        builder.package("java.empty", &[("java.base", true)]);
+        let image = open("packages", &builder.build().0);
+
+        assert_eq!(image.package_module("java/sql"), Some("java.sql"));
+        assert_eq!(image.package_module("java/empty"), None);
+        assert_eq!(image.package_module("java/missing"), None);
+        assert_eq!(image.find_resource("java/sql/Driver.class").unwrap().unwrap(), &b"Driver"[..]);
+        assert!(image.find_resource("Driver.class").unwrap().is_none());
+    }
+
+    #[test]
+    fn undoes_stacked_zip_compression() {
+        let text = "the same line over and over\n".repeat(100);
+        for big_endian in [false, true] {
+            let mut builder = ImageBuilder::new(big_endian);
+            builder.compressed("/java.base/java/lang/Once.class", text.as_bytes(), 1);
+            builder.compressed("/java.base/java/lang/Twice.class", text.as_bytes(), 2);
+            builder.package("java.lang", &[("java.base", false)]);
+            let image = open(&format!("compressed-{big_endian}"), &builder.build().0);
+
+            assert_eq!(image.find_resource("java/lang/Once.class").unwrap().unwrap(), text.as_bytes());
+            assert_eq!(image.find_resource("java/lang/Twice.class").unwrap().unwrap(), text.as_bytes());
+        }
+    }
+}
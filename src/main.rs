@@ -1,10 +1,14 @@
 #![allow(unused)]
+#![allow(clippy::arc_with_non_send_sync)]
 
-use std::fs::{read};
-use bytes::{Buf, Bytes};
-use crate::class::Class;
+use std::path::PathBuf;
+use std::sync::Arc;
+use anyhow::bail;
+use crate::class_path::ClassPath;
 use crate::interp::Interp;
+use crate::jar::{Jar, Manifest};
 
+mod attribute;
 mod value;
 mod constant;
 mod class;
@@ -12,17 +16,72 @@ mod interp;
 mod code;
 mod thread;
 mod error;
+mod version;
+mod mutf8;
+mod reader;
+mod writer;
+mod descriptor;
+mod stack_map;
+mod signature;
+mod annotation;
+mod module;
+mod check;
+mod class_path;
+mod jar;
+mod jimage;
+
+const USAGE: &str = "usage: jvm [options] [-cp <class path>] <main class> [args...]
+       jvm [options] -jar <jar file> [args...]
+options:
+       --java-home <path>  the JDK to load platform classes from, instead of $JAVA_HOME";
 
 fn main() -> anyhow::Result<()> {
-    let file = read("Hello.class")?;
-    let mut br = Bytes::from(file);
+    let mut args = std::env::args().skip(1);
+    let mut class_path = None;
+    let mut java_home = std::env::var_os("JAVA_HOME").map(PathBuf::from);
+    let main_class = loop {
+        match args.next().as_deref() {
+            Some("-cp" | "-classpath" | "--class-path") => {
+                let Some(paths) = args.next() else { bail!(USAGE) };
+                class_path = Some(ClassPath::parse(&paths)?);
+            }
+            Some("--java-home") => {
+                let Some(path) = args.next() else { bail!(USAGE) };
+                java_home = Some(PathBuf::from(path));
+            }
+            Some("-jar") => {
+                // As with `java`, the JAR replaces any other class path.
+                let Some(path) = args.next() else { bail!(USAGE) };
+                let jar = Arc::new(Jar::open(&path)?);
+                let Some(main_class) = jar.manifest().and_then(Manifest::main_class) else {
+                    bail!("no main manifest attribute, in {path}");
+                };
+                let mut jar_class_path = ClassPath::new();
+                jar_class_path.push_jar(jar)?;
+                class_path = Some(jar_class_path);
+                break main_class;
+            }
+            Some(option) if option.starts_with('-') => bail!("unrecognized option {option}\n{USAGE}"),
+            Some(class) => break class.replace('.', "/"),
+            None => bail!(USAGE),
+        }
+    };
+    let class_path = match class_path {
+        Some(class_path) => class_path,
+        None => ClassPath::parse(&std::env::var("CLASSPATH").unwrap_or_else(|_| ".".to_owned()))?,
+    };
 
-    let class = Class::parse(&mut br);
-    println!("{:#?}", class);
+    // Platform classes are found before application classes, so that an
+    // application cannot replace them.
+    let mut full_class_path = ClassPath::new();
+    match java_home {
+        Some(java_home) => full_class_path.push_java_home(java_home)?,
+        None => eprintln!("warning: no --java-home or JAVA_HOME, so platform classes cannot be loaded"),
+    }
+    full_class_path.entries.extend(class_path.entries);
 
-    let mut interp = Interp::new();
-    interp.load_class("Hello.class")?;
-    interp.new_thread_main("Hello")?;
+    let mut interp = Interp::new(full_class_path);
+    interp.new_thread_main(&main_class)?;
     interp.run()?;
 
     Ok(())
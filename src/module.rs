@@ -0,0 +1,173 @@
+//! The `Module` attribute of `module-info.class` (JVMS 4.7.25).
+
+use std::sync::Arc;
+use bitflags::bitflags;
+use crate::constant::ConstantPool;
+use crate::error::ParseResult;
+use crate::reader::Reader;
+
+bitflags! {
+    #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+    pub struct ModuleFlags: u16 {
+        const Open = 0x0020;
+        const Synthetic = 0x1000;
+        const Mandated = 0x8000;
+    }
+
+    #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+    pub struct RequiresFlags: u16 {
+        const Transitive = 0x0020;
+        const StaticPhase = 0x0040;
+        const Synthetic = 0x1000;
+        const Mandated = 0x8000;
+    }
+
+    /// Flags of both `exports` and `opens` directives.
+    #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+    pub struct ExportsFlags: u16 {
+        const Synthetic = 0x1000;
+        const Mandated = 0x8000;
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ModuleDescriptor {
+    pub name: Arc<str>,
+    pub flags: ModuleFlags,
+    pub version: Option<Arc<str>>,
+    pub requires: Vec<Requires>,
+    pub exports: Vec<Exports>,
+    pub opens: Vec<Opens>,
+    /// Service interfaces the module uses, as class names.
+    pub uses: Vec<Arc<str>>,
+    pub provides: Vec<Provides>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Requires {
+    pub module: Arc<str>,
+    pub flags: RequiresFlags,
+    /// The version of the module at compile time, if it was recorded.
+    pub version: Option<Arc<str>>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Exports {
+    /// A package name in internal form, e.g. `java/lang`.
+    pub package: Arc<str>,
+    pub flags: ExportsFlags,
+    /// The modules the package is exported to, or empty if it is exported
+    /// to every module.
+    pub to: Vec<Arc<str>>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Opens {
+    pub package: Arc<str>,
+    pub flags: ExportsFlags,
+    /// The modules the package is open to, or empty if it is open to every
+    /// module.
+    pub to: Vec<Arc<str>>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Provides {
+    /// The service interface, as a class name.
+    pub service: Arc<str>,
+    /// The implementations, as class names.
+    pub with: Vec<Arc<str>>,
+}
+
+impl ModuleDescriptor {
+    pub fn from_bytes(reader: &mut Reader, constant_pool: &ConstantPool) -> ParseResult<Self> {
+        Ok(Self {
+            name: reader.module_ref(constant_pool)?,
+            flags: ModuleFlags::from_bits_retain(reader.u16()?),
+            version: reader.optional_utf8_ref(constant_pool)?,
+            requires: reader.list(|r| Ok(Requires {
+                module: r.module_ref(constant_pool)?,
+                flags: RequiresFlags::from_bits_retain(r.u16()?),
+                version: r.optional_utf8_ref(constant_pool)?,
+            }))?,
+            exports: reader.list(|r| Ok(Exports {
+                package: r.package_ref(constant_pool)?,
+                flags: ExportsFlags::from_bits_retain(r.u16()?),
+                to: r.list(|r| r.module_ref(constant_pool))?,
+            }))?,
+            opens: reader.list(|r| Ok(Opens {
+                package: r.package_ref(constant_pool)?,
+                flags: ExportsFlags::from_bits_retain(r.u16()?),
+                to: r.list(|r| r.module_ref(constant_pool))?,
+            }))?,
+            uses: reader.list(|r| r.class_ref(constant_pool))?,
+            provides: reader.list(|r| Ok(Provides {
+                service: r.class_ref(constant_pool)?,
+                with: r.list(|r| r.class_ref(constant_pool))?,
+            }))?,
+        })
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.flags.contains(ModuleFlags::Open)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+    use crate::class::Class;
+    use super::*;
+
+    fn names(names: &[Arc<str>]) -> Vec<&str> {
+        names.iter().map(AsRef::as_ref).collect()
+    }
+
+    #[test]
+    fn reads_module_info() {
+        let class = Class::parse(Bytes::from_static(include_bytes!("../tests/fixtures/module-info.class"))).unwrap();
+        assert!(class.is_module());
+        let module = class.module().unwrap();
+        assert_eq!(module.name.as_ref(), "com.example.app");
+        assert!(!module.is_open());
+        assert_eq!(module.version.as_deref(), Some("1.2.3"));
+
+        let requires = module.requires.iter()
+            .map(|requires| (requires.module.as_ref(), requires.flags, requires.version.as_deref()))
+            .collect::<Vec<_>>();
+        assert_eq!(requires, [
+            ("java.base", RequiresFlags::Mandated, Some("17.0.15")),
+            ("java.logging", RequiresFlags::Transitive, Some("17.0.15")),
+            ("java.sql", RequiresFlags::StaticPhase, Some("17.0.15")),
+        ]);
+
+        let exports = module.exports.iter()
+            .map(|exports| (exports.package.as_ref(), exports.flags, names(&exports.to)))
+            .collect::<Vec<_>>();
+        assert_eq!(exports, [
+            ("com/example/app/api", ExportsFlags::empty(), vec![]),
+            ("com/example/app/spi", ExportsFlags::empty(), vec!["java.logging", "java.sql"]),
+        ]);
+
+        let opens = module.opens.iter()
+            .map(|opens| (opens.package.as_ref(), opens.flags, names(&opens.to)))
+            .collect::<Vec<_>>();
+        assert_eq!(opens, [
+            ("com/example/app/internal", ExportsFlags::empty(), vec![]),
+            ("com/example/app/impl", ExportsFlags::empty(), vec!["java.logging"]),
+        ]);
+
+        assert_eq!(names(&module.uses), ["com/example/app/spi/Service"]);
+        let provides = module.provides.iter()
+            .map(|provides| (provides.service.as_ref(), names(&provides.with)))
+            .collect::<Vec<_>>();
+        assert_eq!(provides, [("com/example/app/spi/Service", vec!["com/example/app/impl/ServiceImpl"])]);
+
+        assert_eq!(names(class.module_packages().unwrap()), [
+            "com/example/app/api",
+            "com/example/app/impl",
+            "com/example/app/internal",
+            "com/example/app/spi",
+        ]);
+        assert_eq!(class.module_main_class().map(AsRef::as_ref), Some("com/example/app/api/Main"));
+    }
+}
@@ -0,0 +1,154 @@
+//! The "modified UTF-8" encoding used by `CONSTANT_Utf8` entries (JVMS 4.4.7).
+//!
+//! It differs from standard UTF-8 in two ways: NUL is encoded as the two
+//! bytes `C0 80`, and characters outside the basic multilingual plane are
+//! encoded as a surrogate pair of three-byte sequences instead of a single
+//! four-byte sequence. Because Java strings are sequences of UTF-16 code
+//! units, a decoded string may also contain unpaired surrogates, which have
+//! no representation as a Rust `str`.
+
+use std::borrow::Cow;
+
+/// A decoded modified UTF-8 string.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Decoded<'a> {
+    /// Borrows from the input when it is plain ASCII, which needs no
+    /// decoding.
+    Str(Cow<'a, str>),
+    /// The string contains unpaired surrogates, so it is kept as its raw
+    /// UTF-16 code units.
+    Utf16(Vec<u16>),
+}
+
+/// Decodes modified UTF-8, returning `None` if the bytes are malformed.
+pub fn decode(bytes: &[u8]) -> Option<Decoded<'_>> {
+    if bytes.iter().all(|&b| (0x01..0x80).contains(&b)) {
+        let string = std::str::from_utf8(bytes).ok()?;
+        return Some(Decoded::Str(Cow::Borrowed(string)));
+    }
+
+    let units = decode_utf16(bytes)?;
+    match String::from_utf16(&units) {
+        Ok(string) => Some(Decoded::Str(Cow::Owned(string))),
+        Err(_) => Some(Decoded::Utf16(units)),
+    }
+}
+
+/// Decodes modified UTF-8 into the UTF-16 code units it encodes.
+pub fn decode_utf16(bytes: &[u8]) -> Option<Vec<u16>> {
+    let mut units = Vec::with_capacity(bytes.len());
+    let mut rest = bytes;
+
+    while let Some((&first, tail)) = rest.split_first() {
+        let (unit, len) = match first {
+            0x01..=0x7F => (first as u16, 1),
+            0xC0..=0xDF => {
+                let second = continuation(tail, 0)?;
+                (((first as u16 & 0x1F) << 6) | second, 2)
+            }
+            0xE0..=0xEF => {
+                let second = continuation(tail, 0)?;
+                let third = continuation(tail, 1)?;
+                (((first as u16 & 0x0F) << 12) | (second << 6) | third, 3)
+            }
+            _ => return None,
+        };
+        units.push(unit);
+        rest = &rest[len..];
+    }
+
+    Some(units)
+}
+
+fn continuation(bytes: &[u8], index: usize) -> Option<u16> {
+    match bytes.get(index) {
+        Some(&b) if b & 0xC0 == 0x80 => Some(b as u16 & 0x3F),
+        _ => None,
+    }
+}
+
+/// Encodes a string as modified UTF-8.
+pub fn encode(string: &str) -> Vec<u8> {
+    encode_utf16(string.encode_utf16())
+}
+
+/// Encodes UTF-16 code units as modified UTF-8. Unpaired surrogates are
+/// encoded like any other code unit, so this is the inverse of
+/// `decode_utf16`.
+pub fn encode_utf16<I: IntoIterator<Item = u16>>(units: I) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    for unit in units {
+        match unit {
+            0x0001..=0x007F => bytes.push(unit as u8),
+            0x0000 | 0x0080..=0x07FF => {
+                bytes.push(0xC0 | (unit >> 6) as u8);
+                bytes.push(0x80 | (unit & 0x3F) as u8);
+            }
+            _ => {
+                bytes.push(0xE0 | (unit >> 12) as u8);
+                bytes.push(0x80 | ((unit >> 6) & 0x3F) as u8);
+                bytes.push(0x80 | (unit & 0x3F) as u8);
+            }
+        }
+    }
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(string: &str, encoded: &[u8]) {
+        assert_eq!(encode(string), encoded, "encoding {string:?}");
+        assert_eq!(decode(encoded), Some(Decoded::Str(Cow::Borrowed(string))), "decoding {encoded:x?}");
+    }
+
+    #[test]
+    fn encodes_nul_as_two_bytes() {
+        round_trip("\0", &[0xC0, 0x80]);
+        round_trip("a\0b", &[b'a', 0xC0, 0x80, b'b']);
+    }
+
+    #[test]
+    fn encodes_supplementary_characters_as_surrogate_pairs() {
+        round_trip("\u{1F600}", &[0xED, 0xA0, 0xBD, 0xED, 0xB8, 0x80]);
+        round_trip("\u{10000}", &[0xED, 0xA0, 0x80, 0xED, 0xB0, 0x80]);
+        round_trip("\u{10FFFF}", &[0xED, 0xAF, 0xBF, 0xED, 0xBF, 0xBF]);
+    }
+
+    #[test]
+    fn switches_sequence_length_at_the_boundaries() {
+        round_trip("\u{7F}", &[0x7F]);
+        round_trip("\u{80}", &[0xC2, 0x80]);
+        round_trip("\u{7FF}", &[0xDF, 0xBF]);
+        round_trip("\u{800}", &[0xE0, 0xA0, 0x80]);
+        round_trip("\u{FFFF}", &[0xEF, 0xBF, 0xBF]);
+    }
+
+    #[test]
+    fn keeps_unpaired_surrogates_as_utf16() {
+        let encoded = [b'x', 0xED, 0xA0, 0x80];
+        assert_eq!(decode(&encoded), Some(Decoded::Utf16(vec![0x78, 0xD800])));
+        assert_eq!(encode_utf16([0x78, 0xD800]), encoded);
+    }
+
+    #[test]
+    fn rejects_malformed_input() {
+        let malformed: [&[u8]; 7] = [
+            // A continuation byte on its own.
+            &[0x80],
+            &[b'a', 0xBF],
+            // Sequences cut short.
+            &[0xC3],
+            &[0xE2, 0x82],
+            // A lead byte followed by something other than a continuation.
+            &[0xE2, b'a', 0x80],
+            // Standard UTF-8's four-byte form, and a raw NUL.
+            &[0xF0, 0x9F, 0x98, 0x80],
+            &[b'a', 0x00],
+        ];
+        for bytes in malformed {
+            assert_eq!(decode(bytes), None, "{bytes:x?}");
+        }
+    }
+}
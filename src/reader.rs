@@ -0,0 +1,192 @@
+use std::sync::Arc;
+use bytes::{Buf, Bytes};
+use crate::constant::ConstantPool;
+use crate::error::{ClassFormatError, ParseResult};
+
+/// A cursor over class file bytes that remembers its absolute position, so
+/// that parse failures can report where in the file they happened.
+pub struct Reader {
+    bytes: Bytes,
+    offset: usize,
+    structure: &'static str,
+}
+
+macro_rules! get {
+    ($name:ident, $get:ident, $ty:ty) => {
+        pub fn $name(&mut self) -> ParseResult<$ty> {
+            self.need(std::mem::size_of::<$ty>())?;
+            self.offset += std::mem::size_of::<$ty>();
+            Ok(self.bytes.$get())
+        }
+    };
+}
+
+impl Reader {
+    pub fn new(bytes: Bytes) -> Self {
+        Self::at(bytes, 0)
+    }
+
+    /// A reader over `bytes`, which start `offset` bytes into the class file.
+    pub fn at(bytes: Bytes, offset: usize) -> Self {
+        Self {
+            bytes,
+            offset,
+            structure: "ClassFile",
+        }
+    }
+
+    /// The absolute offset of the next byte to be read.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.bytes.remaining()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    pub fn error<R: Into<String>>(&self, reason: R) -> ClassFormatError {
+        self.error_at(self.offset, reason)
+    }
+
+    pub fn error_at<R: Into<String>>(&self, offset: usize, reason: R) -> ClassFormatError {
+        ClassFormatError {
+            offset,
+            structure: self.structure,
+            reason: reason.into(),
+        }
+    }
+
+    /// Runs `f` with errors attributed to `structure`.
+    pub fn within<T, F>(&mut self, structure: &'static str, f: F) -> ParseResult<T>
+    where
+        F: FnOnce(&mut Self) -> ParseResult<T>,
+    {
+        let outer = std::mem::replace(&mut self.structure, structure);
+        let result = f(self);
+        self.structure = outer;
+        result
+    }
+
+    fn need(&self, len: usize) -> ParseResult<()> {
+        if self.bytes.remaining() < len {
+            return Err(self.error(format!(
+                "unexpected end of data: needed {len} bytes, {} remain",
+                self.bytes.remaining(),
+            )));
+        }
+        Ok(())
+    }
+
+    get!(u8, get_u8, u8);
+    get!(u16, get_u16, u16);
+    get!(u32, get_u32, u32);
+    get!(i8, get_i8, i8);
+    get!(i16, get_i16, i16);
+    get!(i32, get_i32, i32);
+    get!(i64, get_i64, i64);
+    get!(f32, get_f32, f32);
+    get!(f64, get_f64, f64);
+
+    pub fn bytes(&mut self, len: usize) -> ParseResult<Bytes> {
+        self.need(len)?;
+        self.offset += len;
+        Ok(self.bytes.split_to(len))
+    }
+
+    /// Splits off the next `len` bytes into a reader of their own, for
+    /// structures such as attributes that declare their length up front.
+    pub fn sub(&mut self, len: usize) -> ParseResult<Reader> {
+        let offset = self.offset;
+        let bytes = self.bytes(len)?;
+        Ok(Self {
+            bytes,
+            offset,
+            structure: self.structure,
+        })
+    }
+
+    /// Fails if any bytes remain, for structures whose declared length must
+    /// be consumed exactly.
+    pub fn expect_end(&self) -> ParseResult<()> {
+        if !self.is_empty() {
+            return Err(self.error(format!("{} unexpected trailing bytes", self.remaining())));
+        }
+        Ok(())
+    }
+
+    /// Reads a constant pool index that must name a `CONSTANT_Utf8` entry.
+    pub fn utf8_ref(&mut self, constant_pool: &ConstantPool) -> ParseResult<Arc<str>> {
+        let offset = self.offset;
+        let index = self.u16()?;
+        constant_pool.utf8(index)
+            .cloned()
+            .ok_or_else(|| self.error_at(offset, format!("constant {index} is not a Utf8 entry")))
+    }
+
+    /// Reads a constant pool index that must name a `CONSTANT_Class` entry,
+    /// returning the class name.
+    pub fn class_ref(&mut self, constant_pool: &ConstantPool) -> ParseResult<Arc<str>> {
+        let offset = self.offset;
+        self.optional_class_ref(constant_pool)?
+            .ok_or_else(|| self.error_at(offset, "constant 0 is not a Class entry"))
+    }
+
+    /// Like `class_ref`, but index 0 is allowed and means "no class".
+    pub fn optional_class_ref(&mut self, constant_pool: &ConstantPool) -> ParseResult<Option<Arc<str>>> {
+        let offset = self.offset;
+        let index = self.u16()?;
+        if index == 0 {
+            return Ok(None);
+        }
+        constant_pool.class_name(index)
+            .cloned()
+            .map(Some)
+            .ok_or_else(|| self.error_at(offset, format!("constant {index} is not a valid Class entry")))
+    }
+
+    /// Like `utf8_ref`, but index 0 is allowed and means "absent".
+    pub fn optional_utf8_ref(&mut self, constant_pool: &ConstantPool) -> ParseResult<Option<Arc<str>>> {
+        let offset = self.offset;
+        let index = self.u16()?;
+        if index == 0 {
+            return Ok(None);
+        }
+        constant_pool.utf8(index)
+            .cloned()
+            .map(Some)
+            .ok_or_else(|| self.error_at(offset, format!("constant {index} is not a Utf8 entry")))
+    }
+
+    /// Reads a constant pool index that must name a `CONSTANT_Module` entry,
+    /// returning the module name.
+    pub fn module_ref(&mut self, constant_pool: &ConstantPool) -> ParseResult<Arc<str>> {
+        let offset = self.offset;
+        let index = self.u16()?;
+        constant_pool.module_name(index)
+            .cloned()
+            .ok_or_else(|| self.error_at(offset, format!("constant {index} is not a valid Module entry")))
+    }
+
+    /// Reads a constant pool index that must name a `CONSTANT_Package`
+    /// entry, returning the package name.
+    pub fn package_ref(&mut self, constant_pool: &ConstantPool) -> ParseResult<Arc<str>> {
+        let offset = self.offset;
+        let index = self.u16()?;
+        constant_pool.package_name(index)
+            .cloned()
+            .ok_or_else(|| self.error_at(offset, format!("constant {index} is not a valid Package entry")))
+    }
+
+    /// Reads a `u16` count followed by that many items.
+    pub fn list<T, F>(&mut self, mut item: F) -> ParseResult<Vec<T>>
+    where
+        F: FnMut(&mut Self) -> ParseResult<T>,
+    {
+        let count = self.u16()?;
+        (0..count).map(|_| item(self)).collect()
+    }
+}
@@ -0,0 +1,510 @@
+//! Generic signatures from the `Signature` attribute (JVMS 4.7.9.1).
+
+use std::fmt::{Display, Formatter};
+use std::sync::Arc;
+use crate::attribute::{Attribute, AttributeKind};
+use crate::error::{Error, Result};
+
+/// The generic declaration of a class: its type parameters and generic
+/// supertypes.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ClassSignature {
+    pub type_parameters: Vec<TypeParameter>,
+    pub superclass: ClassTypeSignature,
+    pub interfaces: Vec<ClassTypeSignature>,
+}
+
+/// The generic declaration of a method.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MethodSignature {
+    pub type_parameters: Vec<TypeParameter>,
+    pub parameters: Vec<JavaTypeSignature>,
+    /// `None` for `void`.
+    pub result: Option<JavaTypeSignature>,
+    /// Only ever class types and type variables.
+    pub throws: Vec<ReferenceTypeSignature>,
+}
+
+/// The generic type of a field, component or local variable.
+pub type FieldSignature = ReferenceTypeSignature;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TypeParameter {
+    pub name: Arc<str>,
+    /// `None` when the first bound is an interface, as in
+    /// `<T::Ljava/lang/Comparable<TT;>;>`.
+    pub class_bound: Option<ReferenceTypeSignature>,
+    pub interface_bounds: Vec<ReferenceTypeSignature>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum JavaTypeSignature {
+    Byte,
+    Char,
+    Double,
+    Float,
+    Int,
+    Long,
+    Short,
+    Boolean,
+    Reference(ReferenceTypeSignature),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ReferenceTypeSignature {
+    Class(ClassTypeSignature),
+    TypeVariable(Arc<str>),
+    Array(Box<JavaTypeSignature>),
+}
+
+/// A possibly parameterized class type such as
+/// `java/util/Map<TK;TV;>.Entry<TK;TV;>`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ClassTypeSignature {
+    /// The outermost class, including its package, e.g. `java/util/Map`.
+    pub name: Arc<str>,
+    pub type_arguments: Vec<TypeArgument>,
+    /// Member classes, from outermost to innermost, e.g. `Entry`.
+    pub inner: Vec<SimpleClassTypeSignature>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SimpleClassTypeSignature {
+    pub name: Arc<str>,
+    pub type_arguments: Vec<TypeArgument>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TypeArgument {
+    /// `?`
+    Wildcard,
+    Exact(ReferenceTypeSignature),
+    /// `? extends`
+    Extends(ReferenceTypeSignature),
+    /// `? super`
+    Super(ReferenceTypeSignature),
+}
+
+impl ClassSignature {
+    pub fn parse(signature: &str) -> Option<Self> {
+        let mut parser = Parser { rest: signature };
+        let type_parameters = parser.type_parameters()?;
+        let superclass = parser.class_type()?;
+        let mut interfaces = Vec::new();
+        while !parser.rest.is_empty() {
+            interfaces.push(parser.class_type()?);
+        }
+        Some(Self { type_parameters, superclass, interfaces })
+    }
+}
+
+impl MethodSignature {
+    pub fn parse(signature: &str) -> Option<Self> {
+        let mut parser = Parser { rest: signature };
+        let type_parameters = parser.type_parameters()?;
+        parser.expect('(')?;
+        let mut parameters = Vec::new();
+        while !parser.eat(')') {
+            parameters.push(parser.java_type()?);
+        }
+        let result = match parser.eat('V') {
+            true => None,
+            false => Some(parser.java_type()?),
+        };
+        let mut throws = Vec::new();
+        while parser.eat('^') {
+            throws.push(match parser.peek()? {
+                'T' => parser.reference_type()?,
+                _ => ReferenceTypeSignature::Class(parser.class_type()?),
+            });
+        }
+        parser.end()?;
+        Some(Self { type_parameters, parameters, result, throws })
+    }
+}
+
+impl ReferenceTypeSignature {
+    /// Parses a field signature.
+    pub fn parse(signature: &str) -> Option<Self> {
+        let mut parser = Parser { rest: signature };
+        let signature = parser.reference_type()?;
+        parser.end()?;
+        Some(signature)
+    }
+}
+
+impl ClassTypeSignature {
+    /// The binary name of the innermost class, e.g. `java/util/Map$Entry`.
+    pub fn binary_name(&self) -> String {
+        let mut name = self.name.to_string();
+        for inner in &self.inner {
+            name.push('$');
+            name.push_str(&inner.name);
+        }
+        name
+    }
+}
+
+/// Parses the `Signature` attribute among `attributes` with `parse`, if
+/// there is one.
+pub fn from_attributes<T>(attributes: &[Attribute], parse: fn(&str) -> Option<T>) -> Result<Option<T>> {
+    let Some(signature) = attributes.iter().find_map(|attr| match &attr.kind() {
+        AttributeKind::Signature(signature) => Some(signature),
+        _ => None,
+    }) else {
+        return Ok(None);
+    };
+    parse(signature)
+        .map(Some)
+        .ok_or_else(|| Error::InvalidSignature(signature.clone()))
+}
+
+struct Parser<'a> {
+    rest: &'a str,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<char> {
+        self.rest.chars().next()
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        match self.rest.strip_prefix(c) {
+            Some(rest) => {
+                self.rest = rest;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn expect(&mut self, c: char) -> Option<()> {
+        self.eat(c).then_some(())
+    }
+
+    fn end(&self) -> Option<()> {
+        self.rest.is_empty().then_some(())
+    }
+
+    fn identifier(&mut self) -> Option<Arc<str>> {
+        let len = self.rest
+            .find(['.', ';', '[', '/', '<', '>', ':'])
+            .unwrap_or(self.rest.len());
+        if len == 0 {
+            return None;
+        }
+        let (identifier, rest) = self.rest.split_at(len);
+        self.rest = rest;
+        Some(Arc::from(identifier))
+    }
+
+    fn type_parameters(&mut self) -> Option<Vec<TypeParameter>> {
+        let mut type_parameters = Vec::new();
+        if !self.eat('<') {
+            return Some(type_parameters);
+        }
+        loop {
+            let name = self.identifier()?;
+            self.expect(':')?;
+            let class_bound = match self.peek()? {
+                ':' => None,
+                _ => Some(self.reference_type()?),
+            };
+            let mut interface_bounds = Vec::new();
+            while self.eat(':') {
+                interface_bounds.push(self.reference_type()?);
+            }
+            type_parameters.push(TypeParameter { name, class_bound, interface_bounds });
+            if self.eat('>') {
+                return Some(type_parameters);
+            }
+        }
+    }
+
+    fn java_type(&mut self) -> Option<JavaTypeSignature> {
+        let base = match self.peek()? {
+            'B' => JavaTypeSignature::Byte,
+            'C' => JavaTypeSignature::Char,
+            'D' => JavaTypeSignature::Double,
+            'F' => JavaTypeSignature::Float,
+            'I' => JavaTypeSignature::Int,
+            'J' => JavaTypeSignature::Long,
+            'S' => JavaTypeSignature::Short,
+            'Z' => JavaTypeSignature::Boolean,
+            _ => return Some(JavaTypeSignature::Reference(self.reference_type()?)),
+        };
+        self.rest = &self.rest[1..];
+        Some(base)
+    }
+
+    fn reference_type(&mut self) -> Option<ReferenceTypeSignature> {
+        match self.peek()? {
+            'L' => Some(ReferenceTypeSignature::Class(self.class_type()?)),
+            'T' => {
+                self.rest = &self.rest[1..];
+                let name = self.identifier()?;
+                self.expect(';')?;
+                Some(ReferenceTypeSignature::TypeVariable(name))
+            }
+            '[' => {
+                self.rest = &self.rest[1..];
+                Some(ReferenceTypeSignature::Array(Box::new(self.java_type()?)))
+            }
+            _ => None,
+        }
+    }
+
+    fn class_type(&mut self) -> Option<ClassTypeSignature> {
+        self.expect('L')?;
+        let mut name = self.identifier()?.to_string();
+        while self.eat('/') {
+            name.push('/');
+            name.push_str(&self.identifier()?);
+        }
+        let type_arguments = self.type_arguments()?;
+        let mut inner = Vec::new();
+        while self.eat('.') {
+            let name = self.identifier()?;
+            let type_arguments = self.type_arguments()?;
+            inner.push(SimpleClassTypeSignature { name, type_arguments });
+        }
+        self.expect(';')?;
+        Some(ClassTypeSignature {
+            name: Arc::from(name),
+            type_arguments,
+            inner,
+        })
+    }
+
+    fn type_arguments(&mut self) -> Option<Vec<TypeArgument>> {
+        let mut type_arguments = Vec::new();
+        if !self.eat('<') {
+            return Some(type_arguments);
+        }
+        loop {
+            type_arguments.push(match self.peek()? {
+                '*' => {
+                    self.rest = &self.rest[1..];
+                    TypeArgument::Wildcard
+                }
+                '+' => {
+                    self.rest = &self.rest[1..];
+                    TypeArgument::Extends(self.reference_type()?)
+                }
+                '-' => {
+                    self.rest = &self.rest[1..];
+                    TypeArgument::Super(self.reference_type()?)
+                }
+                _ => TypeArgument::Exact(self.reference_type()?),
+            });
+            if self.eat('>') {
+                return Some(type_arguments);
+            }
+        }
+    }
+}
+
+impl Display for ClassSignature {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        fmt_type_parameters(f, &self.type_parameters)?;
+        write!(f, "{}", self.superclass)?;
+        for interface in &self.interfaces {
+            write!(f, "{interface}")?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for MethodSignature {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        fmt_type_parameters(f, &self.type_parameters)?;
+        write!(f, "(")?;
+        for parameter in &self.parameters {
+            write!(f, "{parameter}")?;
+        }
+        match &self.result {
+            Some(result) => write!(f, "){result}")?,
+            None => write!(f, ")V")?,
+        }
+        for throws in &self.throws {
+            write!(f, "^{throws}")?;
+        }
+        Ok(())
+    }
+}
+
+fn fmt_type_parameters(f: &mut Formatter<'_>, type_parameters: &[TypeParameter]) -> std::fmt::Result {
+    if type_parameters.is_empty() {
+        return Ok(());
+    }
+    write!(f, "<")?;
+    for parameter in type_parameters {
+        write!(f, "{}:", parameter.name)?;
+        if let Some(bound) = &parameter.class_bound {
+            write!(f, "{bound}")?;
+        }
+        for bound in &parameter.interface_bounds {
+            write!(f, ":{bound}")?;
+        }
+    }
+    write!(f, ">")
+}
+
+impl Display for JavaTypeSignature {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Byte => write!(f, "B"),
+            Self::Char => write!(f, "C"),
+            Self::Double => write!(f, "D"),
+            Self::Float => write!(f, "F"),
+            Self::Int => write!(f, "I"),
+            Self::Long => write!(f, "J"),
+            Self::Short => write!(f, "S"),
+            Self::Boolean => write!(f, "Z"),
+            Self::Reference(reference) => write!(f, "{reference}"),
+        }
+    }
+}
+
+impl Display for ReferenceTypeSignature {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Class(class) => write!(f, "{class}"),
+            Self::TypeVariable(name) => write!(f, "T{name};"),
+            Self::Array(component) => write!(f, "[{component}"),
+        }
+    }
+}
+
+impl Display for ClassTypeSignature {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "L{}", self.name)?;
+        fmt_type_arguments(f, &self.type_arguments)?;
+        for inner in &self.inner {
+            write!(f, ".{}", inner.name)?;
+            fmt_type_arguments(f, &inner.type_arguments)?;
+        }
+        write!(f, ";")
+    }
+}
+
+fn fmt_type_arguments(f: &mut Formatter<'_>, type_arguments: &[TypeArgument]) -> std::fmt::Result {
+    if type_arguments.is_empty() {
+        return Ok(());
+    }
+    write!(f, "<")?;
+    for argument in type_arguments {
+        match argument {
+            TypeArgument::Wildcard => write!(f, "*")?,
+            TypeArgument::Exact(reference) => write!(f, "{reference}")?,
+            TypeArgument::Extends(reference) => write!(f, "+{reference}")?,
+            TypeArgument::Super(reference) => write!(f, "-{reference}")?,
+        }
+    }
+    write!(f, ">")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn class(name: &str, type_arguments: Vec<TypeArgument>) -> ClassTypeSignature {
+        ClassTypeSignature { name: Arc::from(name), type_arguments, inner: Vec::new() }
+    }
+
+    fn variable(name: &str) -> ReferenceTypeSignature {
+        ReferenceTypeSignature::TypeVariable(Arc::from(name))
+    }
+
+    #[test]
+    fn round_trips_a_type_parameter_bounded_only_by_an_interface() {
+        let signature = "<T::Ljava/lang/Comparable<TT;>;>Ljava/lang/Object;";
+        let parsed = ClassSignature::parse(signature).unwrap();
+        assert_eq!(parsed.type_parameters, [TypeParameter {
+            name: Arc::from("T"),
+            class_bound: None,
+            interface_bounds: vec![ReferenceTypeSignature::Class(class(
+                "java/lang/Comparable",
+                vec![TypeArgument::Exact(variable("T"))],
+            ))],
+        }]);
+        assert_eq!(parsed.superclass, class("java/lang/Object", Vec::new()));
+        assert_eq!(parsed.to_string(), signature);
+    }
+
+    #[test]
+    fn round_trips_wildcards() {
+        let signature = "Ljava/util/Map<*+Ljava/lang/Number;>.Entry<-TT;[I>;";
+        let ReferenceTypeSignature::Class(parsed) = ReferenceTypeSignature::parse(signature).unwrap() else {
+            panic!("expected a class type");
+        };
+        assert_eq!(parsed.type_arguments, [
+            TypeArgument::Wildcard,
+            TypeArgument::Extends(ReferenceTypeSignature::Class(class("java/lang/Number", Vec::new()))),
+        ]);
+        assert_eq!(parsed.inner[0].type_arguments, [
+            TypeArgument::Super(variable("T")),
+            TypeArgument::Exact(ReferenceTypeSignature::Array(Box::new(JavaTypeSignature::Int))),
+        ]);
+        assert_eq!(parsed.to_string(), signature);
+    }
+
+    #[test]
+    fn round_trips_parameterized_member_classes() {
+        let signature = "Lp/Outer<TT;>.Inner<TU;>;";
+        let ReferenceTypeSignature::Class(parsed) = ReferenceTypeSignature::parse(signature).unwrap() else {
+            panic!("expected a class type");
+        };
+        assert_eq!(parsed.name.as_ref(), "p/Outer");
+        assert_eq!(parsed.type_arguments, [TypeArgument::Exact(variable("T"))]);
+        assert_eq!(parsed.inner, [SimpleClassTypeSignature {
+            name: Arc::from("Inner"),
+            type_arguments: vec![TypeArgument::Exact(variable("U"))],
+        }]);
+        assert_eq!(parsed.binary_name(), "p/Outer$Inner");
+        assert_eq!(parsed.to_string(), signature);
+    }
+
+    #[test]
+    fn round_trips_throws_clauses() {
+        let signature = "<E:Ljava/lang/Exception;>(JLjava/util/List<TE;>;)V^TE;^Ljava/io/IOException;";
+        let parsed = MethodSignature::parse(signature).unwrap();
+        assert_eq!(parsed.parameters.len(), 2);
+        assert_eq!(parsed.result, None);
+        assert_eq!(parsed.throws, [
+            variable("E"),
+            ReferenceTypeSignature::Class(class("java/io/IOException", Vec::new())),
+        ]);
+        assert_eq!(parsed.to_string(), signature);
+
+        let signature = "()[TT;";
+        let parsed = MethodSignature::parse(signature).unwrap();
+        assert_eq!(parsed.result, Some(JavaTypeSignature::Reference(ReferenceTypeSignature::Array(
+            Box::new(JavaTypeSignature::Reference(variable("T"))),
+        ))));
+        assert_eq!(parsed.to_string(), signature);
+    }
+
+    #[test]
+    fn rejects_malformed_signatures() {
+        for signature in [
+            "",
+            "Ljava/lang/Object",
+            "Ljava/util/List<>;",
+            "Ljava/util/List<TT;",
+            "Ljava//List;",
+            "TT",
+            "I",
+            "[V",
+            "Ljava/lang/Object;;",
+        ] {
+            assert_eq!(ReferenceTypeSignature::parse(signature), None, "{signature:?}");
+        }
+        for signature in ["<>Ljava/lang/Object;", "<T>Ljava/lang/Object;", "<T:Ljava/lang/Object;"] {
+            assert_eq!(ClassSignature::parse(signature), None, "{signature:?}");
+        }
+        for signature in ["()", "(V)V", "(I", "()VV", "()V^I", "()V^"] {
+            assert_eq!(MethodSignature::parse(signature), None, "{signature:?}");
+        }
+    }
+}
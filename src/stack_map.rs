@@ -0,0 +1,313 @@
+//! The `StackMapTable` attribute (JVMS 4.7.4), which declares the types of
+//! the local variables and operand stack at branch targets and exception
+//! handlers for the type checking verifier.
+
+use std::sync::Arc;
+use bytes::{BufMut, Bytes};
+use crate::constant::ConstantPool;
+use crate::descriptor::FieldType;
+use crate::error::{Error, ParseResult, Result};
+use crate::reader::Reader;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum VerificationType {
+    Top,
+    Integer,
+    Float,
+    Long,
+    Double,
+    Null,
+    /// `this` in a constructor before the superclass constructor is called.
+    UninitializedThis,
+    /// An instance of the named class, or an array whose descriptor is the
+    /// name, as in a `CONSTANT_Class` entry.
+    Object(Arc<str>),
+    /// The result of the `new` instruction at this byte offset, before its
+    /// constructor is called.
+    Uninitialized(u16),
+}
+
+/// A frame as it appears in the attribute, relative to the frame before it.
+///
+/// The `_extended` forms are folded into the compact ones, as they only
+/// differ in how `offset_delta` is encoded.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum StackMapFrame {
+    /// Same locals as the previous frame and an empty stack.
+    Same { offset_delta: u16 },
+    /// Same locals as the previous frame and a single stack item.
+    SameLocals1StackItem { offset_delta: u16, stack: VerificationType },
+    /// The previous frame's locals without the last `k`, and an empty stack.
+    Chop { offset_delta: u16, k: u8 },
+    /// The previous frame's locals plus `locals`, and an empty stack.
+    Append { offset_delta: u16, locals: Vec<VerificationType> },
+    Full {
+        offset_delta: u16,
+        locals: Vec<VerificationType>,
+        stack: Vec<VerificationType>,
+    },
+}
+
+/// The declared types at one byte offset, with every frame before it
+/// applied.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Frame {
+    pub offset: u32,
+    /// Indexed by local variable number: a `Long` or `Double` is followed by
+    /// a `Top` for its second slot.
+    pub locals: Vec<VerificationType>,
+    /// Bottom first, one entry per value.
+    pub stack: Vec<VerificationType>,
+}
+
+impl VerificationType {
+    /// The type a value of `field_type` has on the stack or in a local.
+    pub fn from_field_type(field_type: &FieldType) -> Self {
+        match field_type {
+            FieldType::Boolean | FieldType::Byte | FieldType::Char | FieldType::Short | FieldType::Int => Self::Integer,
+            FieldType::Float => Self::Float,
+            FieldType::Long => Self::Long,
+            FieldType::Double => Self::Double,
+            FieldType::Object(name) => Self::Object(name.clone()),
+            FieldType::Array { .. } => Self::Object(Arc::from(field_type.to_string())),
+        }
+    }
+
+    pub fn is_wide(&self) -> bool {
+        matches!(self, Self::Long | Self::Double)
+    }
+
+    pub fn from_bytes(reader: &mut Reader, constant_pool: &ConstantPool) -> ParseResult<Self> {
+        let offset = reader.offset();
+        Ok(match reader.u8()? {
+            0 => Self::Top,
+            1 => Self::Integer,
+            2 => Self::Float,
+            3 => Self::Double,
+            4 => Self::Long,
+            5 => Self::Null,
+            6 => Self::UninitializedThis,
+            7 => Self::Object(reader.class_ref(constant_pool)?),
+            8 => Self::Uninitialized(reader.u16()?),
+            tag => return Err(reader.error_at(offset, format!("invalid verification type tag {tag}"))),
+        })
+    }
+}
+
+impl StackMapFrame {
+    pub fn offset_delta(&self) -> u16 {
+        match self {
+            Self::Same { offset_delta }
+            | Self::SameLocals1StackItem { offset_delta, .. }
+            | Self::Chop { offset_delta, .. }
+            | Self::Append { offset_delta, .. }
+            | Self::Full { offset_delta, .. } => *offset_delta,
+        }
+    }
+
+    pub fn from_bytes(reader: &mut Reader, constant_pool: &ConstantPool) -> ParseResult<Self> {
+        let offset = reader.offset();
+        let types = |reader: &mut Reader, count: u16| {
+            (0..count)
+                .map(|_| VerificationType::from_bytes(reader, constant_pool))
+                .collect::<ParseResult<Vec<_>>>()
+        };
+
+        Ok(match reader.u8()? {
+            frame_type @ 0..=63 => Self::Same { offset_delta: frame_type as u16 },
+            frame_type @ 64..=127 => Self::SameLocals1StackItem {
+                offset_delta: frame_type as u16 - 64,
+                stack: VerificationType::from_bytes(reader, constant_pool)?,
+            },
+            247 => Self::SameLocals1StackItem {
+                offset_delta: reader.u16()?,
+                stack: VerificationType::from_bytes(reader, constant_pool)?,
+            },
+            frame_type @ 248..=250 => Self::Chop {
+                offset_delta: reader.u16()?,
+                k: 251 - frame_type,
+            },
+            251 => Self::Same { offset_delta: reader.u16()? },
+            frame_type @ 252..=254 => {
+                let offset_delta = reader.u16()?;
+                let locals = types(reader, frame_type as u16 - 251)?;
+                Self::Append { offset_delta, locals }
+            }
+            255 => {
+                let offset_delta = reader.u16()?;
+                let locals_count = reader.u16()?;
+                let locals = types(reader, locals_count)?;
+                let stack_count = reader.u16()?;
+                let stack = types(reader, stack_count)?;
+                Self::Full { offset_delta, locals, stack }
+            }
+            frame_type => return Err(reader.error_at(offset, format!("reserved frame type {frame_type}"))),
+        })
+    }
+}
+
+/// Re-encodes the body of a `StackMapTable` attribute for code whose
+/// instructions have moved, with each frame and each `Uninitialized` type
+/// at the offset `remap` gives for its old one. Frames are otherwise copied
+/// as they are, but may change between compact and `_extended` forms as
+/// their offset deltas change.
+pub fn remap(info: &Bytes, remap: impl Fn(u32) -> Result<u16>) -> Result<Bytes> {
+    let mut reader = Reader::new(info.clone());
+    let mut buf = Vec::with_capacity(info.len());
+    let count = reader.u16()?;
+    buf.put_u16(count);
+
+    let mut old_offset: Option<u32> = None;
+    let mut new_offset: Option<u16> = None;
+    for index in 0..count {
+        let frame_type = reader.u8()?;
+        let delta = match frame_type {
+            0..=63 => frame_type as u32,
+            64..=127 => frame_type as u32 - 64,
+            247..=255 => reader.u16()? as u32,
+            _ => return Err(Error::InvalidStackMapFrame { index: index as usize }),
+        };
+        let offset = old_offset.map_or(delta, |previous| previous + delta + 1);
+        old_offset = Some(offset);
+        let remapped = remap(offset)?;
+        let new_delta = match new_offset {
+            None => remapped,
+            Some(previous) => remapped.checked_sub(previous + 1)
+                .ok_or(Error::InvalidStackMapFrame { index: index as usize })?,
+        };
+        new_offset = Some(remapped);
+
+        match frame_type {
+            0..=63 | 251 => if new_delta <= 63 {
+                buf.put_u8(new_delta as u8);
+            } else {
+                buf.put_u8(251);
+                buf.put_u16(new_delta);
+            },
+            64..=127 | 247 => {
+                if new_delta <= 63 {
+                    buf.put_u8(64 + new_delta as u8);
+                } else {
+                    buf.put_u8(247);
+                    buf.put_u16(new_delta);
+                }
+                remap_types(&mut reader, &mut buf, 1, &remap)?;
+            }
+            248..=250 => {
+                buf.put_u8(frame_type);
+                buf.put_u16(new_delta);
+            }
+            252..=254 => {
+                buf.put_u8(frame_type);
+                buf.put_u16(new_delta);
+                remap_types(&mut reader, &mut buf, frame_type as u16 - 251, &remap)?;
+            }
+            _ => {
+                buf.put_u8(frame_type);
+                buf.put_u16(new_delta);
+                for _ in 0..2 {
+                    let count = reader.u16()?;
+                    buf.put_u16(count);
+                    remap_types(&mut reader, &mut buf, count, &remap)?;
+                }
+            }
+        }
+    }
+    reader.expect_end()?;
+    Ok(Bytes::from(buf))
+}
+
+/// Copies `count` encoded verification types from `reader` to `buf`,
+/// remapping the offsets of `Uninitialized` types.
+fn remap_types(reader: &mut Reader, buf: &mut Vec<u8>, count: u16, remap: impl Fn(u32) -> Result<u16>) -> Result<()> {
+    for _ in 0..count {
+        let tag = reader.u8()?;
+        buf.put_u8(tag);
+        match tag {
+            7 => buf.put_u16(reader.u16()?),
+            8 => buf.put_u16(remap(reader.u16()? as u32)?),
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+impl Frame {
+    /// A frame at offset 0 whose locals are `locals`, one entry per value.
+    pub fn initial(locals: &[VerificationType]) -> Self {
+        Self {
+            offset: 0,
+            locals: to_slots(locals),
+            stack: Vec::new(),
+        }
+    }
+}
+
+/// Applies each of `frames` in turn, starting from `initial`, which is
+/// itself not included in the result.
+pub fn expand(initial: &Frame, frames: &[StackMapFrame]) -> Result<Vec<Frame>> {
+    let mut locals = from_slots(&initial.locals);
+    let mut offset = None;
+
+    frames.iter()
+        .enumerate()
+        .map(|(index, frame)| {
+            let invalid = || Error::InvalidStackMapFrame { index };
+            let delta = frame.offset_delta() as u32;
+            let frame_offset = match offset {
+                None => delta,
+                Some(previous) => previous + delta + 1,
+            };
+            offset = Some(frame_offset);
+
+            let stack = match frame {
+                StackMapFrame::Same { .. } => Vec::new(),
+                StackMapFrame::SameLocals1StackItem { stack, .. } => vec![stack.clone()],
+                StackMapFrame::Chop { k, .. } => {
+                    let len = locals.len().checked_sub(*k as usize).ok_or_else(invalid)?;
+                    locals.truncate(len);
+                    Vec::new()
+                }
+                StackMapFrame::Append { locals: appended, .. } => {
+                    locals.extend(appended.iter().cloned());
+                    Vec::new()
+                }
+                StackMapFrame::Full { locals: full, stack, .. } => {
+                    locals = full.clone();
+                    stack.clone()
+                }
+            };
+
+            Ok(Frame {
+                offset: frame_offset,
+                locals: to_slots(&locals),
+                stack,
+            })
+        })
+        .collect()
+}
+
+/// Expands `Long` and `Double` entries to two slots.
+fn to_slots(types: &[VerificationType]) -> Vec<VerificationType> {
+    let mut slots = Vec::with_capacity(types.len());
+    for verification_type in types {
+        slots.push(verification_type.clone());
+        if verification_type.is_wide() {
+            slots.push(VerificationType::Top);
+        }
+    }
+    slots
+}
+
+/// The inverse of `to_slots`.
+fn from_slots(slots: &[VerificationType]) -> Vec<VerificationType> {
+    let mut types = Vec::with_capacity(slots.len());
+    let mut slots = slots.iter();
+    while let Some(verification_type) = slots.next() {
+        if verification_type.is_wide() {
+            slots.next();
+        }
+        types.push(verification_type.clone());
+    }
+    types
+}
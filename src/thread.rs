@@ -1,41 +1,116 @@
 use std::collections::HashMap;
 use std::sync::Arc;
 use crate::class::{Class, FieldIndex, Method, MethodIndex};
-use crate::code::Instr;
+use crate::code::{Code, DebugLocal, Instr};
 use crate::constant::Constant;
+use crate::descriptor::FieldType;
 use crate::error::{Error, Result};
-use crate::interp::InterpContext;
+use crate::interp::{Initialization, InterpContext};
 use crate::value::Value;
 
 pub struct Thread {
+    /// Identifies the thread that is initializing a class.
+    pub id: usize,
     pc: Pc,
     stack: Vec<Frame>,
 }
 
 impl Thread {
-    pub fn new(class: Arc<Class>, method_index: MethodIndex) -> Self {
+    pub fn new(id: usize, class: Arc<Class>, method_index: MethodIndex) -> Self {
         let pc = Pc::new(class.clone(), method_index);
         let frame = Frame::for_method(class.clone(), method_index);
         let stack = vec![frame];
 
         Self {
+            id,
             pc,
             stack,
         }
     }
 
+    /// Whether the thread has returned from its first method.
+    pub fn is_finished(&self) -> bool {
+        self.stack.is_empty()
+    }
+
+    /// Starts initializing `class` if it is not initialized yet. Returns
+    /// whether the thread can go on using the class, which it cannot while
+    /// static initializers are to run first or another thread is running
+    /// them.
+    pub fn initialize(&mut self, context: &InterpContext, class: &Arc<Class>) -> Result<bool> {
+        match context.initialize(class, self.id)? {
+            Initialization::Done => Ok(true),
+            Initialization::Wait => Ok(false),
+            Initialization::Run(classes) => Ok(!self.push_initializers(context, classes)),
+        }
+    }
+
+    /// Pushes a frame for each `<clinit>` of `classes`, so that they run in
+    /// order before the current instruction is executed again. A class
+    /// without one is done once the initializers before it have returned.
+    /// Returns whether any frames were pushed.
+    fn push_initializers(&mut self, context: &InterpContext, classes: Vec<Arc<Class>>) -> bool {
+        let depth = self.stack.len();
+        let mut done = Vec::new();
+        for class in classes.into_iter().rev() {
+            match class.initializer_index() {
+                Some(method_index) => {
+                    let return_pc = std::mem::replace(&mut self.pc, Pc::new(class.clone(), method_index));
+                    let mut frame = Frame::new(return_pc, class.clone(), method_index);
+                    frame.initializing.push(class);
+                    frame.initializing.extend(done.drain(..).rev());
+                    self.stack.push(frame);
+                }
+                None => done.push(class),
+            }
+        }
+        for class in done.into_iter().rev() {
+            context.finish_initialization(&class);
+        }
+        self.stack.len() > depth
+    }
+
+    /// The class that `instr` causes to be initialized (JVMS 5.5), if any.
+    /// For a static field this is the class that declares it, which may be
+    /// a superclass or superinterface of the one the reference names.
+    fn initialization_trigger(&self, context: &InterpContext, instr: &Instr) -> Result<Option<Arc<Class>>> {
+        let class = &self.pc.class;
+        let name = match *instr {
+            Instr::Getstatic(index) | Instr::Putstatic(index) => {
+                let field = class.constant_pool.field_ref(index)?;
+                let (declaring_class, _) = context.resolve_field(class, &field)?;
+                return Ok(Some(declaring_class));
+            }
+            // Methods are not resolved yet, so this is the class the
+            // reference names rather than the one that declares the method.
+            Instr::Invokestatic(index) => match class.constant_pool.method_ref(index) {
+                Ok(method) => method.class,
+                Err(_) => class.constant_pool.interface_method_ref(index)?.class,
+            },
+            Instr::New(index) => class.constant_pool.class_ref(index)?,
+            _ => return Ok(None),
+        };
+        context.resolve_class(class, &name).map(Some)
+    }
+
     pub fn current_frame(&mut self) -> &mut Frame {
         self.stack.last_mut().unwrap()
     }
 
     pub fn method(&self) -> &Method {
-        &self.pc.class.method(self.pc.method_index)
+        self.pc.class.method(self.pc.method_index)
     }
 
     pub fn exec_one(&mut self, context: &mut InterpContext) -> Result<()> {
         let instr = self.method().code().unwrap().code[self.pc.instr].clone();
+        if let Some(class) = self.initialization_trigger(context, &instr)? {
+            if !self.initialize(context, &class)? {
+                return Ok(());
+            }
+        }
         let class = self.pc.class.clone();
         let frame = self.current_frame();
+        let mut branch = None;
         eprintln!("exec: {:?}", instr);
         match instr {
             Instr::Aaload => todo!(),
@@ -122,11 +197,11 @@ impl Thread {
             Instr::Fsub => todo!(),
             Instr::Getfield(_) => todo!(),
             Instr::Getstatic(n) => {
-                let value = class.get_static_from_constant(context, n.into())?;
+                let value = class.get_static_from_constant(context, n)?;
                 frame.stack.push(value);
             }
-            Instr::Goto(_) => todo!(),
-            Instr::GotoW(_) => todo!(),
+            Instr::Goto(delta) => branch = Some(delta as i32),
+            Instr::GotoW(delta) => branch = Some(delta),
             Instr::I2b => todo!(),
             Instr::I2c => todo!(),
             Instr::I2d => todo!(),
@@ -236,15 +311,30 @@ impl Thread {
             Instr::Putfield(_) => todo!(),
             Instr::Putstatic(_) => todo!(),
             Instr::Ret(_) => todo!(),
-            Instr::Return => todo!(),
+            Instr::Return => {
+                let frame = self.stack.pop().unwrap();
+                for class in &frame.initializing {
+                    context.finish_initialization(class);
+                }
+                // The caller carries on from the pc it saved, which is the
+                // instruction that triggered initialization for a `<clinit>`.
+                if let Some(return_pc) = frame.return_pc {
+                    self.pc = return_pc;
+                }
+                return Ok(());
+            }
             Instr::Saload => todo!(),
             Instr::Sastore => todo!(),
             Instr::Sipush(_) => todo!(),
             Instr::Swap => todo!(),
             Instr::Tableswitch { .. } => todo!(),
-            Instr::Wide { .. } => todo!(),
+            Instr::Wide(_) => todo!(),
+        }
+
+        match branch {
+            Some(delta) => self.pc.branch(delta)?,
+            None => self.pc.instr += 1,
         }
-        self.pc.instr += 1;
 
         Ok(())
     }
@@ -273,10 +363,49 @@ impl Pc {
     pub fn pc(&self) -> usize {
         self.instr
     }
+
+    /// The byte offset of the current instruction within its method's code.
+    pub fn offset(&self) -> u32 {
+        self.code().offset(self.instr)
+    }
+
+    /// Moves to the instruction `delta` bytes from the current one, as a
+    /// branch instruction does.
+    pub fn branch(&mut self, delta: i32) -> Result<()> {
+        self.instr = self.code()
+            .branch_target(self.instr, delta)
+            .ok_or_else(|| {
+                let method = self.class.method(self.method_index);
+                Error::InvalidBranchTarget {
+                    method: format!("{}.{}{}", self.class.name, method.name, method.descriptor),
+                    offset: self.offset(),
+                    delta,
+                }
+            })?;
+        Ok(())
+    }
+
+    /// The source line of the current instruction, if known.
+    pub fn line_number(&self) -> Option<u16> {
+        self.code().line_number(self.offset())
+    }
+
+    /// The local variables the debug attributes say are live at the current
+    /// instruction.
+    pub fn live_locals(&self) -> impl Iterator<Item = &DebugLocal> {
+        self.code().live_locals(self.offset())
+    }
+
+    fn code(&self) -> &Code {
+        self.class.method(self.method_index).code().unwrap()
+    }
 }
 
 pub struct Frame {
     return_pc: Option<Pc>,
+    /// The classes that are initialized once this frame returns, when it
+    /// runs a static initializer.
+    initializing: Vec<Arc<Class>>,
 
     locals: Vec<Value>,
     stack: Vec<Value>,
@@ -293,6 +422,7 @@ impl Frame {
 
         Self {
             return_pc: None,
+            initializing: Vec::new(),
             locals: vec![Value::Int(0); max_locals],
             stack: Vec::with_capacity(max_stack),
         }
@@ -314,6 +444,34 @@ impl Frame {
         self.stack.pop().unwrap()
     }
 
+    /// Pops the arguments of `method`, and `this` if it is an instance
+    /// method, off `caller`'s stack into this frame's locals. `long` and
+    /// `double` arguments take up two local variable slots.
+    ///
+    /// Unverified code can call a method with too few values on the stack,
+    /// or a method can declare too few locals for its arguments; both are
+    /// reported as `Verify` errors.
+    pub fn take_arguments(&mut self, caller: &mut Frame, method: &Method) -> Result<()> {
+        let receiver = if method.is_static() { 0 } else { 1 };
+        let count = method.method_type.parameters.len() + receiver;
+        let start = caller.stack.len().checked_sub(count).ok_or_else(|| Error::Verify(format!(
+            "{}{} takes {count} arguments but the stack holds {}",
+            method.name, method.descriptor, caller.stack.len(),
+        )))?;
+        let arguments = caller.stack.split_off(start);
+
+        let slots = std::iter::repeat_n(1, receiver)
+            .chain(method.method_type.parameters.iter().map(FieldType::slots));
+        let mut slot = 0;
+        for (argument, size) in arguments.into_iter().zip(slots) {
+            *self.locals.get_mut(slot).ok_or_else(|| Error::Verify(format!(
+                "{}{} has too few locals for its arguments", method.name, method.descriptor,
+            )))? = argument;
+            slot += size;
+        }
+        Ok(())
+    }
+
     pub fn new(return_pc: Pc, call_class: Arc<Class>, call_method: MethodIndex) -> Self {
         let method = call_class.method(call_method);
         if !method.has_code() {
@@ -321,8 +479,53 @@ impl Frame {
         }
         Self {
             return_pc: Some(return_pc),
-            locals: Vec::with_capacity(method.max_locals().unwrap()),
+            initializing: Vec::new(),
+            locals: vec![Value::Int(0); method.max_locals().unwrap()],
             stack: Vec::with_capacity(method.max_stack().unwrap()),
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+    use super::*;
+
+    fn frame(locals: usize, stack: Vec<Value>) -> Frame {
+        Frame { return_pc: None, initializing: Vec::new(), locals: vec![Value::Null; locals], stack }
+    }
+
+    #[test]
+    fn takes_two_slots_for_long_and_double_arguments() {
+        let class = Class::parse(Bytes::from_static(include_bytes!("../tests/fixtures/Arguments.class"))).unwrap();
+        let method = class.static_methods.iter().find(|method| method.name.as_ref() == "take").unwrap();
+        assert_eq!(method.descriptor.as_ref(), "(JIDLjava/lang/Object;)V");
+
+        let mut caller = frame(0, vec![Value::Int(7), Value::Long(1), Value::Int(2), Value::Double(3.0), Value::Null]);
+        let mut callee = frame(method.max_locals().unwrap(), Vec::new());
+        callee.take_arguments(&mut caller, method).unwrap();
+        assert!(matches!(caller.stack[..], [Value::Int(7)]));
+        assert!(matches!(callee.locals[..], [Value::Long(1), _, Value::Int(2), Value::Double(3.0), _, Value::Null]));
+
+        let mut callee = frame(method.max_locals().unwrap(), Vec::new());
+        assert!(matches!(callee.take_arguments(&mut frame(0, vec![Value::Int(2)]), method), Err(Error::Verify(_))));
+        let mut callee = frame(4, Vec::new());
+        let mut caller = frame(0, vec![Value::Long(1), Value::Int(2), Value::Double(3.0), Value::Null]);
+        assert!(matches!(callee.take_arguments(&mut caller, method), Err(Error::Verify(_))));
+    }
+
+    #[test]
+    fn takes_the_receiver_into_slot_zero() {
+        let class = Class::parse(Bytes::from_static(include_bytes!("../tests/fixtures/Arguments.class"))).unwrap();
+        let method = class.methods.iter().find(|method| method.name.as_ref() == "take").unwrap();
+
+        let mut caller = frame(0, vec![Value::Null, Value::Long(1), Value::Int(2), Value::Double(3.0), Value::Null, Value::Int(4)]);
+        let mut callee = frame(method.max_locals().unwrap(), Vec::new());
+        callee.take_arguments(&mut caller, method).unwrap();
+        assert!(caller.stack.is_empty());
+        assert!(matches!(
+            callee.locals[..],
+            [Value::Null, Value::Long(1), _, Value::Int(2), Value::Double(3.0), _, Value::Null, Value::Int(4)],
+        ));
+    }
+}
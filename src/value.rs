@@ -4,19 +4,8 @@ use std::hint::black_box;
 use std::sync::{Arc, Mutex};
 use bitflags::bitflags;
 use crate::class::{Class, FieldIndex};
-
-#[derive(Clone, Debug)]
-pub enum Type {
-    Boolean,
-    Char,
-    Byte,
-    Short,
-    Int,
-    Long,
-    Float,
-    Double,
-    Reference(Arc<Class>),
-}
+use crate::constant::Constant;
+use crate::descriptor::FieldType;
 
 #[derive(Clone, Debug)]
 pub struct Object {
@@ -35,9 +24,54 @@ pub enum Value {
     Float(f32),
     Double(f64),
     Reference(Arc<Object>),
+    Null,
+}
+
+impl Value {
+    /// The value a variable of type `field_type` has before it is assigned,
+    /// such as a static field after preparation (JVMS 2.3, 2.4).
+    pub fn default_for(field_type: &FieldType) -> Self {
+        match field_type {
+            FieldType::Boolean => Self::Boolean(false),
+            FieldType::Char => Self::Char(0),
+            FieldType::Byte => Self::Byte(0),
+            FieldType::Short => Self::Short(0),
+            FieldType::Int => Self::Int(0),
+            FieldType::Long => Self::Long(0),
+            FieldType::Float => Self::Float(0.0),
+            FieldType::Double => Self::Double(0.0),
+            FieldType::Object(_) | FieldType::Array { .. } => Self::Null,
+        }
+    }
+
+    /// The value of a `ConstantValue` attribute for a field of type
+    /// `field_type`, or `None` if the constant is not a primitive of a
+    /// matching kind.
+    pub fn from_constant(constant: &Constant, field_type: &FieldType) -> Option<Self> {
+        Some(match (constant, field_type) {
+            (Constant::Integer(value), FieldType::Boolean) => Self::Boolean(*value != 0),
+            (Constant::Integer(value), FieldType::Char) => Self::Char(*value as i16),
+            (Constant::Integer(value), FieldType::Byte) => Self::Byte(*value as i8),
+            (Constant::Integer(value), FieldType::Short) => Self::Short(*value as i16),
+            (Constant::Integer(value), FieldType::Int) => Self::Int(*value),
+            (Constant::Long(value), FieldType::Long) => Self::Long(*value),
+            (Constant::Float(value), FieldType::Float) => Self::Float(*value),
+            (Constant::Double(value), FieldType::Double) => Self::Double(*value),
+            _ => return None,
+        })
+    }
 }
 
 impl Object {
+    /// A new instance of `class`, which must be linked, with every field,
+    /// inherited or not, set to its default value.
+    pub fn new(class: Arc<Class>) -> Self {
+        let fields = class.field_slots().iter()
+            .map(|slot| RefCell::new(Value::default_for(&slot.field_type)))
+            .collect();
+        Self { class, fields }
+    }
+
     pub fn get_field(&self, index: FieldIndex) -> Value {
         match index {
             FieldIndex::Dynamic(index) => self.fields[index].borrow().clone(),
@@ -0,0 +1,181 @@
+use std::fmt::Display;
+use crate::constant::Constant;
+
+/// The `major_version.minor_version` pair from a class file header.
+///
+/// Ordering compares the major version first, so `ClassVersion::new(45, 3)`
+/// sorts before `ClassVersion::new(46, 0)`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ClassVersion {
+    pub major: u16,
+    pub minor: u16,
+}
+
+/// The first class file version in which each predefined attribute is
+/// recognized (JVMS table 4.7-B). Older class files may contain attributes
+/// with these names, but they must be treated as non-standard and ignored.
+const ATTRIBUTE_VERSIONS: &[(&str, ClassVersion)] = &[
+    ("ConstantValue", ClassVersion::new(45, 3)),
+    ("Code", ClassVersion::new(45, 3)),
+    ("Exceptions", ClassVersion::new(45, 3)),
+    ("SourceFile", ClassVersion::new(45, 3)),
+    ("LineNumberTable", ClassVersion::new(45, 3)),
+    ("LocalVariableTable", ClassVersion::new(45, 3)),
+    ("InnerClasses", ClassVersion::new(45, 3)),
+    ("Synthetic", ClassVersion::new(45, 3)),
+    ("Deprecated", ClassVersion::new(45, 3)),
+    ("EnclosingMethod", ClassVersion::new(49, 0)),
+    ("Signature", ClassVersion::new(49, 0)),
+    ("SourceDebugExtension", ClassVersion::new(49, 0)),
+    ("LocalVariableTypeTable", ClassVersion::new(49, 0)),
+    ("RuntimeVisibleAnnotations", ClassVersion::new(49, 0)),
+    ("RuntimeInvisibleAnnotations", ClassVersion::new(49, 0)),
+    ("RuntimeVisibleParameterAnnotations", ClassVersion::new(49, 0)),
+    ("RuntimeInvisibleParameterAnnotations", ClassVersion::new(49, 0)),
+    ("AnnotationDefault", ClassVersion::new(49, 0)),
+    ("StackMapTable", ClassVersion::new(50, 0)),
+    ("BootstrapMethods", ClassVersion::new(51, 0)),
+    ("RuntimeVisibleTypeAnnotations", ClassVersion::new(52, 0)),
+    ("RuntimeInvisibleTypeAnnotations", ClassVersion::new(52, 0)),
+    ("MethodParameters", ClassVersion::new(52, 0)),
+    ("Module", ClassVersion::new(53, 0)),
+    ("ModulePackages", ClassVersion::new(53, 0)),
+    ("ModuleMainClass", ClassVersion::new(53, 0)),
+    ("NestHost", ClassVersion::new(55, 0)),
+    ("NestMembers", ClassVersion::new(55, 0)),
+    ("Record", ClassVersion::new(60, 0)),
+    ("PermittedSubclasses", ClassVersion::new(61, 0)),
+];
+
+impl ClassVersion {
+    /// JDK 1.0.2 and 1.1.
+    pub const MIN_MAJOR: u16 = 45;
+    /// Java SE 27.
+    pub const MAX_MAJOR: u16 = 71;
+    /// The minor version marking a class file that depends on preview features.
+    pub const PREVIEW_MINOR: u16 = 0xFFFF;
+    /// Java SE 12, the first release with a fixed minor version and preview features.
+    pub const FIRST_PREVIEW_MAJOR: u16 = 56;
+
+    pub const fn new(major: u16, minor: u16) -> Self {
+        Self { major, minor }
+    }
+
+    /// Whether this VM can load class files of this version.
+    ///
+    /// Before Java SE 12 any minor version is allowed. From then on the minor
+    /// version must be 0, or `PREVIEW_MINOR` for the most recent release only.
+    pub fn is_supported(self) -> bool {
+        if !(Self::MIN_MAJOR..=Self::MAX_MAJOR).contains(&self.major) {
+            return false;
+        }
+        if self.major < Self::FIRST_PREVIEW_MAJOR {
+            return true;
+        }
+        match self.minor {
+            0 => true,
+            Self::PREVIEW_MINOR => self.major == Self::MAX_MAJOR,
+            _ => false,
+        }
+    }
+
+    pub fn is_preview(self) -> bool {
+        self.major >= Self::FIRST_PREVIEW_MAJOR && self.minor == Self::PREVIEW_MINOR
+    }
+
+    /// `jsr`, `jsr_w` and `ret` may only appear in class files older than 51.0.
+    pub fn allows_jsr(self) -> bool {
+        self.major < 51
+    }
+
+    /// The first version in which the predefined attribute `name` is
+    /// recognized, or `None` if `name` is not a predefined attribute.
+    pub fn attribute_since(name: &str) -> Option<ClassVersion> {
+        ATTRIBUTE_VERSIONS.iter()
+            .find(|(attribute, _)| *attribute == name)
+            .map(|(_, version)| *version)
+    }
+
+    /// Whether `name` is a predefined attribute that class files of this
+    /// version are allowed to use.
+    pub fn recognizes_attribute(self, name: &str) -> bool {
+        Self::attribute_since(name).is_some_and(|since| self >= since)
+    }
+
+    /// Whether a constant pool entry of this kind is legal in class files of
+    /// this version (JVMS table 4.4-C).
+    pub fn allows_constant(self, constant: &Constant) -> bool {
+        let since = match constant {
+            Constant::MethodHandle { .. }
+            | Constant::MethodType { .. }
+            | Constant::InvokeDynamic { .. } => 51,
+            Constant::Module { .. } | Constant::Package { .. } => 53,
+            Constant::Dynamic { .. } => 55,
+            _ => return true,
+        };
+        self.major >= since
+    }
+}
+
+impl Display for ClassVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn supports_majors_from_45_through_the_newest() {
+        assert!(!ClassVersion::new(44, 0).is_supported());
+        assert!(ClassVersion::new(45, 0).is_supported());
+        assert!(ClassVersion::new(ClassVersion::MAX_MAJOR, 0).is_supported());
+        assert!(!ClassVersion::new(ClassVersion::MAX_MAJOR + 1, 0).is_supported());
+        assert_eq!(ClassVersion::MAX_MAJOR, 71);
+    }
+
+    #[test]
+    fn restricts_minor_versions_from_java_12() {
+        // Before Java SE 12 any minor version is fine.
+        assert!(ClassVersion::new(45, 3).is_supported());
+        assert!(ClassVersion::new(55, 0xFFFF).is_supported());
+        assert!(!ClassVersion::new(55, 0xFFFF).is_preview());
+
+        assert!(!ClassVersion::new(56, 1).is_supported());
+        assert!(!ClassVersion::new(56, 0xFFFF).is_supported());
+        assert!(!ClassVersion::new(ClassVersion::MAX_MAJOR - 1, 0xFFFF).is_supported());
+        let preview = ClassVersion::new(ClassVersion::MAX_MAJOR, ClassVersion::PREVIEW_MINOR);
+        assert!(preview.is_supported());
+        assert!(preview.is_preview());
+    }
+
+    #[test]
+    fn allows_constants_from_the_version_that_added_them() {
+        let method_type = Constant::MethodType { descriptor_index: 1 };
+        assert!(!ClassVersion::new(50, 0).allows_constant(&method_type));
+        assert!(ClassVersion::new(51, 0).allows_constant(&method_type));
+
+        let module = Constant::Module { name_index: 1 };
+        assert!(!ClassVersion::new(52, 0).allows_constant(&module));
+        assert!(ClassVersion::new(53, 0).allows_constant(&module));
+
+        let dynamic = Constant::Dynamic { bootstrap_method_attr_index: 0, name_and_type_index: 1 };
+        assert!(!ClassVersion::new(54, 0).allows_constant(&dynamic));
+        assert!(ClassVersion::new(55, 0).allows_constant(&dynamic));
+
+        assert!(ClassVersion::new(45, 0).allows_constant(&Constant::Integer(0)));
+    }
+
+    #[test]
+    fn recognizes_attributes_from_the_version_that_added_them() {
+        assert!(!ClassVersion::new(45, 2).recognizes_attribute("Code"));
+        assert!(ClassVersion::new(45, 3).recognizes_attribute("Code"));
+        assert!(!ClassVersion::new(49, 65535).recognizes_attribute("StackMapTable"));
+        assert!(ClassVersion::new(50, 0).recognizes_attribute("StackMapTable"));
+        assert!(!ClassVersion::new(60, 0).recognizes_attribute("PermittedSubclasses"));
+        assert!(ClassVersion::new(61, 0).recognizes_attribute("PermittedSubclasses"));
+        assert!(!ClassVersion::new(ClassVersion::MAX_MAJOR, 0).recognizes_attribute("Unknown"));
+    }
+}
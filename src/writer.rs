@@ -0,0 +1,119 @@
+use bytes::BufMut;
+use crate::attribute::Attribute;
+use crate::class::{ClassAccessFlags, Field, Method};
+use crate::constant::ConstantPool;
+use crate::error::Result;
+use crate::version::ClassVersion;
+
+/// Assembles a class file (JVMS 4.1).
+///
+/// Everything after the constant pool is written as it is added, with names
+/// and other constants added to the pool as needed, so the pool is only
+/// complete once `finish` is called. Members and attributes are written in
+/// the order they are added.
+pub struct ClassWriter {
+    version: ClassVersion,
+    pub constant_pool: ConstantPool,
+    access_flags: ClassAccessFlags,
+    this_class: u16,
+    super_class: u16,
+    interfaces: Vec<u16>,
+    fields_count: u16,
+    fields: Vec<u8>,
+    methods_count: u16,
+    methods: Vec<u8>,
+    attributes_count: u16,
+    attributes: Vec<u8>,
+}
+
+impl ClassWriter {
+    /// A writer that adds to `constant_pool`, which may be empty or the pool
+    /// of a parsed class whose indices are still in use.
+    pub fn new(version: ClassVersion, constant_pool: ConstantPool) -> Self {
+        Self {
+            version,
+            constant_pool,
+            access_flags: ClassAccessFlags::empty(),
+            this_class: 0,
+            super_class: 0,
+            interfaces: Vec::new(),
+            fields_count: 0,
+            fields: Vec::new(),
+            methods_count: 0,
+            methods: Vec::new(),
+            attributes_count: 0,
+            attributes: Vec::new(),
+        }
+    }
+
+    pub fn set_access_flags(&mut self, access_flags: ClassAccessFlags) {
+        self.access_flags = access_flags;
+    }
+
+    pub fn set_name(&mut self, name: &str) -> Result<()> {
+        self.this_class = self.constant_pool.add_class(name)?;
+        Ok(())
+    }
+
+    /// Sets the superclass, which only `java/lang/Object` and `module-info`
+    /// have none of.
+    pub fn set_superclass_name(&mut self, name: Option<&str>) -> Result<()> {
+        self.super_class = match name {
+            Some(name) => self.constant_pool.add_class(name)?,
+            None => 0,
+        };
+        Ok(())
+    }
+
+    pub fn add_interface(&mut self, name: &str) -> Result<()> {
+        let index = self.constant_pool.add_class(name)?;
+        self.add_interface_index(index);
+        Ok(())
+    }
+
+    /// Adds an interface by the index of its `CONSTANT_Class` entry.
+    pub fn add_interface_index(&mut self, index: u16) {
+        self.interfaces.push(index);
+    }
+
+    pub fn add_field(&mut self, field: &Field) -> Result<()> {
+        field.to_bytes(&mut self.constant_pool, &mut self.fields)?;
+        self.fields_count += 1;
+        Ok(())
+    }
+
+    pub fn add_method(&mut self, method: &Method) -> Result<()> {
+        method.to_bytes(&mut self.constant_pool, &mut self.methods)?;
+        self.methods_count += 1;
+        Ok(())
+    }
+
+    pub fn add_attribute(&mut self, attribute: &Attribute) -> Result<()> {
+        attribute.to_bytes(&mut self.constant_pool, &mut self.attributes)?;
+        self.attributes_count += 1;
+        Ok(())
+    }
+
+    /// Lays out the class file.
+    pub fn finish(self) -> Result<Vec<u8>> {
+        let mut buf = Vec::new();
+        buf.put_u32(0xCAFEBABE);
+        buf.put_u16(self.version.minor);
+        buf.put_u16(self.version.major);
+        self.constant_pool.to_bytes(&mut buf)?;
+        buf.put_u16(self.access_flags.bits());
+        buf.put_u16(self.this_class);
+        buf.put_u16(self.super_class);
+        buf.put_u16(self.interfaces.len() as u16);
+        for interface in &self.interfaces {
+            buf.put_u16(*interface);
+        }
+        buf.put_u16(self.fields_count);
+        buf.put_slice(&self.fields);
+        buf.put_u16(self.methods_count);
+        buf.put_slice(&self.methods);
+        buf.put_u16(self.attributes_count);
+        buf.put_slice(&self.attributes);
+        Ok(buf)
+    }
+}
@@ -0,0 +1,6 @@
+/** For tests of passing arguments to a method. */
+public class Arguments {
+    static void take(long a, int b, double c, Object d) {}
+
+    void take(long a, int b, double c, Object d, int e) {}
+}
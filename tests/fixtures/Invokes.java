@@ -0,0 +1,20 @@
+import java.util.ArrayList;
+import java.util.List;
+import java.util.function.IntSupplier;
+
+/** Interface and dynamic invocations, for round-trip tests. */
+public class Invokes {
+    static int size(List<String> list) {
+        list.add("a");
+        return list.size();
+    }
+
+    static String concat(String a, int b) {
+        return a + b;
+    }
+
+    static int lambda(int x) {
+        IntSupplier supplier = () -> x * 2;
+        return supplier.getAsInt() + size(new ArrayList<>());
+    }
+}
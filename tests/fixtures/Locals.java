@@ -0,0 +1,10 @@
+/** Compiled with -g, for tests of the local variable tables. */
+public class Locals {
+    static int sum(int n) {
+        int total = 0;
+        for (int i = 0; i < n; i++) {
+            total += i;
+        }
+        return total;
+    }
+}
@@ -0,0 +1,99 @@
+/** Switches at each of the four padding alignments, for round-trip tests. */
+public class Switches {
+    static int table0(int x) {
+        switch (x) {
+            case 0: return 0;
+            case 1: return 1;
+            case 2: return 2;
+            case 3: return 3;
+            case 4: return 4;
+            default: return -1;
+        }
+    }
+
+    static int table1(int x) {
+        x += 1;
+        switch (x) {
+            case 0: return 0;
+            case 1: return 1;
+            case 2: return 2;
+            case 3: return 3;
+            case 4: return 4;
+            default: return -1;
+        }
+    }
+
+    static int table2(int x) {
+        x += 1;
+        x += 1;
+        switch (x) {
+            case 0: return 0;
+            case 1: return 1;
+            case 2: return 2;
+            case 3: return 3;
+            case 4: return 4;
+            default: return -1;
+        }
+    }
+
+    static int table3(int x) {
+        x += 1;
+        x += 1;
+        x += 1;
+        switch (x) {
+            case 0: return 0;
+            case 1: return 1;
+            case 2: return 2;
+            case 3: return 3;
+            case 4: return 4;
+            default: return -1;
+        }
+    }
+
+    static int lookup0(int x) {
+        switch (x) {
+            case 1: return 0;
+            case 100: return 1;
+            case 10000: return 2;
+            case -5: return 3;
+            default: return -1;
+        }
+    }
+
+    static int lookup1(int x) {
+        x += 1;
+        switch (x) {
+            case 1: return 0;
+            case 100: return 1;
+            case 10000: return 2;
+            case -5: return 3;
+            default: return -1;
+        }
+    }
+
+    static int lookup2(int x) {
+        x += 1;
+        x += 1;
+        switch (x) {
+            case 1: return 0;
+            case 100: return 1;
+            case 10000: return 2;
+            case -5: return 3;
+            default: return -1;
+        }
+    }
+
+    static int lookup3(int x) {
+        x += 1;
+        x += 1;
+        x += 1;
+        switch (x) {
+            case 1: return 0;
+            case 100: return 1;
+            case 10000: return 2;
+            case -5: return 3;
+            default: return -1;
+        }
+    }
+
+}
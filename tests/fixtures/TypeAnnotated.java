@@ -0,0 +1,21 @@
+import java.lang.annotation.ElementType;
+import java.lang.annotation.Retention;
+import java.lang.annotation.RetentionPolicy;
+import java.lang.annotation.Target;
+import java.util.List;
+
+/** Compiled with -g, for tests of type annotations on code. */
+public class TypeAnnotated {
+    @Retention(RetentionPolicy.RUNTIME)
+    @Target(ElementType.TYPE_USE)
+    @interface Visible {}
+
+    @Target(ElementType.TYPE_USE)
+    @interface Invisible {}
+
+    static int size(Object value) {
+        @Visible List<@Invisible String> list = (@Visible List<@Invisible String>) value;
+        Object copy = new @Visible Object();
+        return list.size() + copy.hashCode();
+    }
+}
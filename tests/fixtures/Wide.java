@@ -0,0 +1,268 @@
+/** Locals past index 255, which need the wide prefix, for round-trip tests. */
+public class Wide {
+    static int many(int x) {
+        int v0 = x + 0;
+        int v1 = x + 1;
+        int v2 = x + 2;
+        int v3 = x + 3;
+        int v4 = x + 4;
+        int v5 = x + 5;
+        int v6 = x + 6;
+        int v7 = x + 7;
+        int v8 = x + 8;
+        int v9 = x + 9;
+        int v10 = x + 10;
+        int v11 = x + 11;
+        int v12 = x + 12;
+        int v13 = x + 13;
+        int v14 = x + 14;
+        int v15 = x + 15;
+        int v16 = x + 16;
+        int v17 = x + 17;
+        int v18 = x + 18;
+        int v19 = x + 19;
+        int v20 = x + 20;
+        int v21 = x + 21;
+        int v22 = x + 22;
+        int v23 = x + 23;
+        int v24 = x + 24;
+        int v25 = x + 25;
+        int v26 = x + 26;
+        int v27 = x + 27;
+        int v28 = x + 28;
+        int v29 = x + 29;
+        int v30 = x + 30;
+        int v31 = x + 31;
+        int v32 = x + 32;
+        int v33 = x + 33;
+        int v34 = x + 34;
+        int v35 = x + 35;
+        int v36 = x + 36;
+        int v37 = x + 37;
+        int v38 = x + 38;
+        int v39 = x + 39;
+        int v40 = x + 40;
+        int v41 = x + 41;
+        int v42 = x + 42;
+        int v43 = x + 43;
+        int v44 = x + 44;
+        int v45 = x + 45;
+        int v46 = x + 46;
+        int v47 = x + 47;
+        int v48 = x + 48;
+        int v49 = x + 49;
+        int v50 = x + 50;
+        int v51 = x + 51;
+        int v52 = x + 52;
+        int v53 = x + 53;
+        int v54 = x + 54;
+        int v55 = x + 55;
+        int v56 = x + 56;
+        int v57 = x + 57;
+        int v58 = x + 58;
+        int v59 = x + 59;
+        int v60 = x + 60;
+        int v61 = x + 61;
+        int v62 = x + 62;
+        int v63 = x + 63;
+        int v64 = x + 64;
+        int v65 = x + 65;
+        int v66 = x + 66;
+        int v67 = x + 67;
+        int v68 = x + 68;
+        int v69 = x + 69;
+        int v70 = x + 70;
+        int v71 = x + 71;
+        int v72 = x + 72;
+        int v73 = x + 73;
+        int v74 = x + 74;
+        int v75 = x + 75;
+        int v76 = x + 76;
+        int v77 = x + 77;
+        int v78 = x + 78;
+        int v79 = x + 79;
+        int v80 = x + 80;
+        int v81 = x + 81;
+        int v82 = x + 82;
+        int v83 = x + 83;
+        int v84 = x + 84;
+        int v85 = x + 85;
+        int v86 = x + 86;
+        int v87 = x + 87;
+        int v88 = x + 88;
+        int v89 = x + 89;
+        int v90 = x + 90;
+        int v91 = x + 91;
+        int v92 = x + 92;
+        int v93 = x + 93;
+        int v94 = x + 94;
+        int v95 = x + 95;
+        int v96 = x + 96;
+        int v97 = x + 97;
+        int v98 = x + 98;
+        int v99 = x + 99;
+        int v100 = x + 100;
+        int v101 = x + 101;
+        int v102 = x + 102;
+        int v103 = x + 103;
+        int v104 = x + 104;
+        int v105 = x + 105;
+        int v106 = x + 106;
+        int v107 = x + 107;
+        int v108 = x + 108;
+        int v109 = x + 109;
+        int v110 = x + 110;
+        int v111 = x + 111;
+        int v112 = x + 112;
+        int v113 = x + 113;
+        int v114 = x + 114;
+        int v115 = x + 115;
+        int v116 = x + 116;
+        int v117 = x + 117;
+        int v118 = x + 118;
+        int v119 = x + 119;
+        int v120 = x + 120;
+        int v121 = x + 121;
+        int v122 = x + 122;
+        int v123 = x + 123;
+        int v124 = x + 124;
+        int v125 = x + 125;
+        int v126 = x + 126;
+        int v127 = x + 127;
+        int v128 = x + 128;
+        int v129 = x + 129;
+        int v130 = x + 130;
+        int v131 = x + 131;
+        int v132 = x + 132;
+        int v133 = x + 133;
+        int v134 = x + 134;
+        int v135 = x + 135;
+        int v136 = x + 136;
+        int v137 = x + 137;
+        int v138 = x + 138;
+        int v139 = x + 139;
+        int v140 = x + 140;
+        int v141 = x + 141;
+        int v142 = x + 142;
+        int v143 = x + 143;
+        int v144 = x + 144;
+        int v145 = x + 145;
+        int v146 = x + 146;
+        int v147 = x + 147;
+        int v148 = x + 148;
+        int v149 = x + 149;
+        int v150 = x + 150;
+        int v151 = x + 151;
+        int v152 = x + 152;
+        int v153 = x + 153;
+        int v154 = x + 154;
+        int v155 = x + 155;
+        int v156 = x + 156;
+        int v157 = x + 157;
+        int v158 = x + 158;
+        int v159 = x + 159;
+        int v160 = x + 160;
+        int v161 = x + 161;
+        int v162 = x + 162;
+        int v163 = x + 163;
+        int v164 = x + 164;
+        int v165 = x + 165;
+        int v166 = x + 166;
+        int v167 = x + 167;
+        int v168 = x + 168;
+        int v169 = x + 169;
+        int v170 = x + 170;
+        int v171 = x + 171;
+        int v172 = x + 172;
+        int v173 = x + 173;
+        int v174 = x + 174;
+        int v175 = x + 175;
+        int v176 = x + 176;
+        int v177 = x + 177;
+        int v178 = x + 178;
+        int v179 = x + 179;
+        int v180 = x + 180;
+        int v181 = x + 181;
+        int v182 = x + 182;
+        int v183 = x + 183;
+        int v184 = x + 184;
+        int v185 = x + 185;
+        int v186 = x + 186;
+        int v187 = x + 187;
+        int v188 = x + 188;
+        int v189 = x + 189;
+        int v190 = x + 190;
+        int v191 = x + 191;
+        int v192 = x + 192;
+        int v193 = x + 193;
+        int v194 = x + 194;
+        int v195 = x + 195;
+        int v196 = x + 196;
+        int v197 = x + 197;
+        int v198 = x + 198;
+        int v199 = x + 199;
+        int v200 = x + 200;
+        int v201 = x + 201;
+        int v202 = x + 202;
+        int v203 = x + 203;
+        int v204 = x + 204;
+        int v205 = x + 205;
+        int v206 = x + 206;
+        int v207 = x + 207;
+        int v208 = x + 208;
+        int v209 = x + 209;
+        int v210 = x + 210;
+        int v211 = x + 211;
+        int v212 = x + 212;
+        int v213 = x + 213;
+        int v214 = x + 214;
+        int v215 = x + 215;
+        int v216 = x + 216;
+        int v217 = x + 217;
+        int v218 = x + 218;
+        int v219 = x + 219;
+        int v220 = x + 220;
+        int v221 = x + 221;
+        int v222 = x + 222;
+        int v223 = x + 223;
+        int v224 = x + 224;
+        int v225 = x + 225;
+        int v226 = x + 226;
+        int v227 = x + 227;
+        int v228 = x + 228;
+        int v229 = x + 229;
+        int v230 = x + 230;
+        int v231 = x + 231;
+        int v232 = x + 232;
+        int v233 = x + 233;
+        int v234 = x + 234;
+        int v235 = x + 235;
+        int v236 = x + 236;
+        int v237 = x + 237;
+        int v238 = x + 238;
+        int v239 = x + 239;
+        int v240 = x + 240;
+        int v241 = x + 241;
+        int v242 = x + 242;
+        int v243 = x + 243;
+        int v244 = x + 244;
+        int v245 = x + 245;
+        int v246 = x + 246;
+        int v247 = x + 247;
+        int v248 = x + 248;
+        int v249 = x + 249;
+        int v250 = x + 250;
+        int v251 = x + 251;
+        int v252 = x + 252;
+        int v253 = x + 253;
+        int v254 = x + 254;
+        int v255 = x + 255;
+        int v256 = x + 256;
+        int v257 = x + 257;
+        int v258 = x + 258;
+        int v259 = x + 259;
+        v259 += 1000;
+        v258++;
+        return v259 + v258 + v0;
+    }
+}
@@ -0,0 +1,18 @@
+/**
+ * For tests of the Module, ModulePackages and ModuleMainClass attributes.
+ * Compiled with stub classes in the packages it names, then packed with
+ * `jar --module-version 1.2.3 --main-class com.example.app.api.Main`, which
+ * adds the version and the last two attributes.
+ */
+module com.example.app {
+    requires transitive java.logging;
+    requires static java.sql;
+
+    exports com.example.app.api;
+    exports com.example.app.spi to java.logging, java.sql;
+    opens com.example.app.internal;
+    opens com.example.app.impl to java.logging;
+
+    uses com.example.app.spi.Service;
+    provides com.example.app.spi.Service with com.example.app.impl.ServiceImpl;
+}